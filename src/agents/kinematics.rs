@@ -0,0 +1,107 @@
+use crate::util::{Meters, Vec2};
+
+/// How something moves: it speeds up in the direction it's pushed, up to a top speed, and slows
+/// down by itself once it isn't pushed any more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kinematics {
+  pub vel: Vec2,
+  /// Meters per second per second gained when pushed
+  pub accel: Meters,
+  /// Meters per second
+  pub max_speed: Meters,
+  /// Meters per second per second lost when not pushed
+  pub friction: Meters,
+}
+
+impl Kinematics {
+  pub fn new(accel: Meters, max_speed: Meters, friction: Meters) -> Kinematics {
+    Kinematics { vel: Vec2::zeros(), accel, max_speed, friction }
+  }
+
+  /// Advances `dt` seconds while being pushed towards `dir`, returning how far to move. Pushes
+  /// longer than 1 are cut down to 1, so diagonals aren't any faster than straight lines.
+  pub fn step(&mut self, dir: Vec2, dt: f32) -> Vec2 {
+    let dir = if dir.norm() > 1.0 { dir.normalize() } else { dir };
+    if dir == Vec2::zeros() {
+      // Friction only ever slows things down, it never pushes them back the other way
+      let speed = self.vel.norm();
+      let slowed = (speed - self.friction * dt).max(0.0);
+      self.vel = if speed > 0.0 { self.vel * (slowed / speed) } else { Vec2::zeros() };
+    } else {
+      self.vel += dir * self.accel * dt;
+      // Pushing part way on a stick gives a lower top speed
+      let top = self.max_speed * dir.norm();
+      if self.vel.norm() > top {
+        self.vel = self.vel.normalize() * top;
+      }
+    }
+    self.vel * dt
+  }
+
+  /// Comes to a dead stop, like after running into a wall
+  pub fn stop(&mut self) {
+    self.vel = Vec2::zeros();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  static DT: f32 = 1.0 / 60.0;
+
+  fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.0001
+  }
+
+  #[test]
+  fn test_accelerates_to_top_speed() {
+    let mut kin = Kinematics::new(10.0, 5.0, 20.0);
+    let moved = kin.step(Vec2::new(1.0, 0.0), DT);
+    assert!(close(kin.vel.x, 10.0 * DT));
+    assert!(close(moved.x, kin.vel.x * DT));
+    for _ in 0..120 {
+      kin.step(Vec2::new(1.0, 0.0), DT);
+    }
+    assert!(close(kin.vel.norm(), 5.0));
+  }
+
+  #[test]
+  fn test_diagonals_arent_faster() {
+    let mut straight = Kinematics::new(10.0, 5.0, 20.0);
+    let mut diagonal = straight.clone();
+    for _ in 0..10 {
+      straight.step(Vec2::new(0.0, 1.0), DT);
+      diagonal.step(Vec2::new(1.0, 1.0), DT);
+    }
+    assert!(close(straight.vel.norm(), diagonal.vel.norm()));
+    for _ in 0..120 {
+      diagonal.step(Vec2::new(1.0, 1.0), DT);
+    }
+    assert!(close(diagonal.vel.norm(), 5.0));
+  }
+
+  #[test]
+  fn test_friction_stops_without_reversing() {
+    let mut kin = Kinematics::new(10.0, 5.0, 20.0);
+    kin.vel = Vec2::new(-3.0, 4.0);
+    kin.step(Vec2::zeros(), DT);
+    assert!(close(kin.vel.norm(), 5.0 - 20.0 * DT));
+    // Still heading the same way
+    assert!(close(kin.vel.x / kin.vel.y, -0.75));
+    for _ in 0..60 {
+      kin.step(Vec2::zeros(), DT);
+    }
+    assert_eq!(kin.vel, Vec2::zeros());
+    assert_eq!(kin.step(Vec2::zeros(), DT), Vec2::zeros());
+  }
+
+  #[test]
+  fn test_partial_push_has_lower_top_speed() {
+    let mut kin = Kinematics::new(10.0, 5.0, 20.0);
+    for _ in 0..120 {
+      kin.step(Vec2::new(0.5, 0.0), DT);
+    }
+    assert!(close(kin.vel.norm(), 2.5));
+  }
+}
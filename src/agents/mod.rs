@@ -1,13 +1,66 @@
+pub mod kinematics;
+pub mod monster;
 pub mod mouse_mover;
 pub mod player;
+pub mod stats;
 
-use crate::util::Point;
-use crate::util::Vec2;
+use crate::agents::stats::Stats;
+use crate::collision::Shape2D;
+use crate::util::Assets;
+use crate::util::{Meters, Point, Vec2};
+use crate::world::camera::Camera;
+use ggez::graphics;
+use ggez::graphics::{Color, DrawParam};
+use ggez::{Context, GameResult};
+use nc::shape::{Ball, ShapeHandle};
 
 pub trait Agent {
-  fn width(&self) -> u32;
-  fn height(&self) -> u32;
+  /// Agents take up a circle of this radius, which is also how big their symbol is drawn
+  fn radius(&self) -> Meters;
   fn symbol(&self) -> &'static str;
   fn pos(&self) -> Point;
+  /// Which way the agent is looking, in radians from the +x axis towards +y
+  fn facing(&self) -> f32;
+  /// How fast the agent can move, in meters per second
+  fn speed(&self) -> Meters;
+  fn stats(&self) -> &Stats;
+  fn stats_mut(&mut self) -> &mut Stats;
   fn trans(&mut self, by: Vec2);
+
+  /// The agent's collision shape, centered on `pos`
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(Ball::new(self.radius()))
+  }
+}
+
+/// Angle of `dir` in the same terms as `Agent::facing`, or `current` if `dir` is zero
+pub fn facing_towards(dir: Vec2, current: f32) -> f32 {
+  if dir == Vec2::zeros() {
+    current
+  } else {
+    dir.y.atan2(dir.x)
+  }
+}
+
+/// Draws an agent's symbol centered where it stands, as tall as the agent is across. Text isn't
+/// drawn through the level transform, so the camera is needed to work out how big that is on
+/// screen.
+pub fn draw_agent<T: Agent>(
+  agent: &T,
+  ctx: &mut Context,
+  assets: &mut Assets,
+  cam: &Camera,
+) -> GameResult<()> {
+  let center = cam.lspace_to_sspace(ctx, agent.pos());
+  let txt = assets.agent_txt(agent);
+  let (w, h) = txt.dimensions(ctx);
+  let scale = cam.meters_to_pixels(ctx, agent.radius() * 2.0) / h as f32;
+  let corner = center - Vec2::new(w as f32, h as f32) * scale / 2.0;
+  let repositioned = DrawParam {
+    dest: corner.into(),
+    scale: Vec2::new(scale, scale).into(),
+    color: Color::new(1.0, 1.0, 1.0, 1.0),
+    ..DrawParam::default()
+  };
+  graphics::draw(ctx, txt, repositioned)
 }
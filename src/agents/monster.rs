@@ -0,0 +1,201 @@
+use super::stats::Stats;
+use super::{draw_agent, facing_towards, Agent};
+use crate::util::Assets;
+use crate::util::{Meters, Point, Vec2};
+use crate::world::camera::Camera;
+use ggez::{Context, GameResult};
+
+/// The different sorts of monster living in the caves
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MonsterKind {
+  Rat,
+  Goblin,
+  Skeleton,
+  Ogre,
+}
+
+impl MonsterKind {
+  pub fn all() -> &'static [MonsterKind] {
+    static KINDS: [MonsterKind; 4] =
+      [MonsterKind::Rat, MonsterKind::Goblin, MonsterKind::Skeleton, MonsterKind::Ogre];
+    &KINDS
+  }
+
+  /// What the kind is called in encounter tables and spawn records
+  pub fn name(self) -> &'static str {
+    match self {
+      MonsterKind::Rat => "rat",
+      MonsterKind::Goblin => "goblin",
+      MonsterKind::Skeleton => "skeleton",
+      MonsterKind::Ogre => "ogre",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<MonsterKind> {
+    MonsterKind::all().iter().find(|k| k.name() == name).cloned()
+  }
+
+  fn symbol(self) -> &'static str {
+    match self {
+      MonsterKind::Rat => "r",
+      MonsterKind::Goblin => "g",
+      MonsterKind::Skeleton => "s",
+      MonsterKind::Ogre => "O",
+    }
+  }
+
+  pub fn radius(self) -> Meters {
+    match self {
+      MonsterKind::Rat => 0.25,
+      MonsterKind::Goblin | MonsterKind::Skeleton => 0.4,
+      MonsterKind::Ogre => 0.8,
+    }
+  }
+
+  /// Meters per second
+  fn speed(self) -> Meters {
+    match self {
+      MonsterKind::Rat => 5.0,
+      MonsterKind::Goblin => 4.0,
+      MonsterKind::Skeleton => 3.0,
+      MonsterKind::Ogre => 2.5,
+    }
+  }
+
+  /// Quietest noise the kind notices, in decibels
+  fn hearing(self) -> f32 {
+    match self {
+      MonsterKind::Rat => 0.0,
+      MonsterKind::Goblin => 5.0,
+      MonsterKind::Skeleton => 12.0,
+      MonsterKind::Ogre => 8.0,
+    }
+  }
+
+  fn stats(self) -> Stats {
+    match self {
+      MonsterKind::Rat => Stats::new(4, 1, 0),
+      MonsterKind::Goblin => Stats::new(10, 2, 0),
+      MonsterKind::Skeleton => Stats::new(12, 3, 1),
+      MonsterKind::Ogre => Stats::new(30, 6, 2),
+    }
+  }
+}
+
+/// Something living in the caves that isn't the player
+pub struct Monster {
+  pub kind: MonsterKind,
+  pos: Point,
+  radius: Meters,
+  facing: f32,
+  stats: Stats,
+  /// Where the monster last heard something, which it goes to have a look at
+  investigating: Option<Point>,
+}
+
+impl Monster {
+  pub fn new(kind: MonsterKind, pos: Point) -> Monster {
+    Monster::with_radius(kind, pos, kind.radius())
+  }
+
+  /// A monster bigger or smaller than usual for its kind
+  pub fn with_radius(kind: MonsterKind, pos: Point, radius: Meters) -> Monster {
+    Monster { kind, pos, radius, facing: 0.0, stats: kind.stats(), investigating: None }
+  }
+
+  pub fn investigating(&self) -> Option<Point> {
+    self.investigating
+  }
+
+  /// Lets the monster know a noise at `at` reached it `loudness` loud. If it's loud enough to
+  /// notice, the monster drops whatever it was looking into to go and find out what it was.
+  pub fn hear(&mut self, at: Point, loudness: f32) -> bool {
+    let heard = loudness >= self.kind.hearing();
+    if heard {
+      self.investigating = Some(at);
+    }
+    heard
+  }
+
+  /// How far the monster wants to move in the next `dt` seconds to get to what it's
+  /// investigating. It stops once it's standing on it.
+  pub fn investigate_step(&mut self, dt: f32) -> Vec2 {
+    let to = match self.investigating {
+      Some(to) => to - self.pos,
+      None => return Vec2::zeros(),
+    };
+    let dist = to.norm();
+    if dist <= self.radius {
+      self.investigating = None;
+      return Vec2::zeros();
+    }
+    self.facing = facing_towards(to, self.facing);
+    to * (self.kind.speed() * dt).min(dist) / dist
+  }
+
+  /// Stops investigating, for when the way there is blocked
+  pub fn give_up(&mut self) {
+    self.investigating = None;
+  }
+
+  pub fn draw(&self, ctx: &mut Context, assets: &mut Assets, cam: &Camera) -> GameResult<()> {
+    draw_agent(self, ctx, assets, cam)
+  }
+}
+
+impl Agent for Monster {
+  fn radius(&self) -> Meters {
+    self.radius
+  }
+  fn symbol(&self) -> &'static str {
+    self.kind.symbol()
+  }
+  fn pos(&self) -> Point {
+    self.pos
+  }
+  fn facing(&self) -> f32 {
+    self.facing
+  }
+  fn speed(&self) -> Meters {
+    self.kind.speed()
+  }
+  fn stats(&self) -> &Stats {
+    &self.stats
+  }
+  fn stats_mut(&mut self) -> &mut Stats {
+    &mut self.stats
+  }
+
+  fn trans(&mut self, by: Vec2) {
+    self.pos += by;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_investigates_what_it_hears() {
+    let mut rat = Monster::new(MonsterKind::Rat, Point::new(0.0, 0.0));
+    let mut ogre = Monster::new(MonsterKind::Ogre, Point::new(0.0, 0.0));
+    let noise = Point::new(3.0, 4.0);
+    assert!(rat.hear(noise, 4.0));
+    assert!(!ogre.hear(noise, 4.0));
+    assert_eq!(ogre.investigate_step(1.0), Vec2::zeros());
+
+    let mut steps = 0;
+    loop {
+      let step = rat.investigate_step(0.1);
+      if step == Vec2::zeros() {
+        break;
+      }
+      rat.trans(step);
+      steps += 1;
+    }
+    // Five meters at five meters per second, give or take the last step
+    assert!((9..=10).contains(&steps), "{}", steps);
+    assert!(na::distance(&rat.pos(), &noise) <= rat.radius());
+    assert_eq!(rat.investigating(), None);
+  }
+}
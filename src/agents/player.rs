@@ -1,50 +1,110 @@
 extern crate ggez;
 extern crate nalgebra;
 
-use super::Agent;
-use crate::util::Assets;
+use super::kinematics::Kinematics;
+use super::stats::Stats;
+use super::{draw_agent, facing_towards, Agent};
+use crate::items::inventory::{Inventory, InventoryError};
+use crate::items::{Item, ItemKind, Kg};
 use crate::util::Point;
 use crate::util::Vec2;
-use ggez::graphics;
-use ggez::graphics::Color;
-use ggez::graphics::DrawParam;
+use crate::util::{Assets, Meters};
+use crate::world::camera::Camera;
 use ggez::{Context, GameResult};
 
 static PLAYER_SYM: &'static str = "@";
+static PLAYER_RADIUS: Meters = 0.4;
+/// Meters per second per second the player speeds up by while walking
+static PLAYER_ACCEL: f32 = 40.0;
+/// How fast the player walks on open ground, in meters per second
+static PLAYER_SPEED: f32 = 6.0;
+/// Meters per second per second the player slows down by after letting go
+static PLAYER_FRICTION: f32 = 30.0;
+static PLAYER_HEALTH: u32 = 20;
+/// Attack and defense with nothing equipped
+static PLAYER_ATTACK: u32 = 3;
+static PLAYER_DEFENSE: u32 = 1;
+static PLAYER_MAX_ITEMS: usize = 12;
+static PLAYER_MAX_WEIGHT: Kg = 30.0;
 
 pub struct Player {
   pos: Point,
+  facing: f32,
+  pub kinematics: Kinematics,
+  /// Attack and defense here include whatever is equipped
+  stats: Stats,
+  inventory: Inventory,
 }
 
 impl Player {
   pub fn new(pos: Point) -> Player {
-    Player { pos }
-  }
-
-  /// Currently we have to pass scale in here separately b/c we don't want
-  /// the overal transform to scale our text, since we handle that with
-  /// font sizes.
-  pub fn draw(&self, ctx: &mut Context, assets: &mut Assets, scale: Vec2) -> GameResult<()> {
-    let d = Point::new(self.pos.x * scale.x, self.pos.y * scale.y);
-    let repositioned = DrawParam {
-      dest: d.into(),
-      // This offset is because the draw point is the upper-left corner of
-      // the text.
-      offset: Point::new(0.60, 0.60).into(),
-      color: Color::new(1.0, 1.0, 1.0, 1.0),
-      ..DrawParam::default()
+    Player {
+      pos,
+      facing: 0.0,
+      kinematics: Kinematics::new(PLAYER_ACCEL, PLAYER_SPEED, PLAYER_FRICTION),
+      stats: Stats::new(PLAYER_HEALTH, PLAYER_ATTACK, PLAYER_DEFENSE),
+      inventory: Inventory::new(PLAYER_MAX_ITEMS, PLAYER_MAX_WEIGHT),
+    }
+  }
+
+  pub fn inventory(&self) -> &Inventory {
+    &self.inventory
+  }
+
+  pub fn pick_up(&mut self, item: Item) -> Result<(), InventoryError> {
+    self.inventory.add(item)
+  }
+
+  /// Takes the item at `ix` out of the inventory, unequipping it first if need be
+  pub fn drop_item(&mut self, ix: usize) -> Option<Item> {
+    let item = self.inventory.remove(ix);
+    self.refresh_stats();
+    item
+  }
+
+  /// Eats or drinks the item at `ix` if it's a consumable, otherwise equips or unequips it
+  pub fn use_item(&mut self, ix: usize) -> Result<String, InventoryError> {
+    let item = *self.inventory.get(ix).ok_or(InventoryError::NoSuchItem)?;
+    let msg = match item.kind {
+      ItemKind::Consumable { heal } => {
+        self.inventory.remove(ix);
+        format!("Used the {}, healing {}", item.name, self.stats.heal(heal))
+      }
+      _ if self.inventory.is_equipped(ix) => {
+        self.inventory.unequip(ix)?;
+        format!("Took off the {}", item.name)
+      }
+      _ => {
+        self.inventory.equip(ix)?;
+        format!("Equipped the {}", item.name)
+      }
     };
-    let txt = assets.agent_txt(self);
-    graphics::draw(ctx, txt, repositioned)
+    self.refresh_stats();
+    Ok(msg)
+  }
+
+  /// Brings attack and defense up to date with what's equipped
+  fn refresh_stats(&mut self) {
+    self.stats.attack = PLAYER_ATTACK + self.inventory.attack_bonus();
+    self.stats.defense = PLAYER_DEFENSE + self.inventory.defense_bonus();
+  }
+
+  /// Runs movement for `dt` seconds while pushed towards `dir`, returning how far the player wants
+  /// to go. They turn to face wherever they're headed.
+  pub fn walk(&mut self, dir: Vec2, dt: f32) -> Vec2 {
+    let by = self.kinematics.step(dir, dt);
+    self.facing = facing_towards(by, self.facing);
+    by
+  }
+
+  pub fn draw(&self, ctx: &mut Context, assets: &mut Assets, cam: &Camera) -> GameResult<()> {
+    draw_agent(self, ctx, assets, cam)
   }
 }
 
 impl Agent for Player {
-  fn width(&self) -> u32 {
-    1
-  }
-  fn height(&self) -> u32 {
-    1
+  fn radius(&self) -> Meters {
+    PLAYER_RADIUS
   }
   fn symbol(&self) -> &'static str {
     PLAYER_SYM
@@ -52,6 +112,18 @@ impl Agent for Player {
   fn pos(&self) -> Point {
     self.pos
   }
+  fn facing(&self) -> f32 {
+    self.facing
+  }
+  fn speed(&self) -> Meters {
+    self.kinematics.max_speed
+  }
+  fn stats(&self) -> &Stats {
+    &self.stats
+  }
+  fn stats_mut(&mut self) -> &mut Stats {
+    &mut self.stats
+  }
 
   fn trans(&mut self, by: Vec2) {
     self.pos += by;
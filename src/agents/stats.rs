@@ -0,0 +1,85 @@
+/// The numbers that decide how an agent fares in a fight
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+  pub max_health: u32,
+  pub health: u32,
+  /// Damage dealt by each hit before the target's defense
+  pub attack: u32,
+  /// Knocked off the damage of every hit taken
+  pub defense: u32,
+  /// Damage from the ground underfoot that hasn't added up to a whole point yet
+  scorch: f32,
+}
+
+impl Stats {
+  /// Stats for an agent at full health
+  pub fn new(max_health: u32, attack: u32, defense: u32) -> Stats {
+    Stats { max_health, health: max_health, attack, defense, scorch: 0.0 }
+  }
+
+  pub fn is_alive(&self) -> bool {
+    self.health > 0
+  }
+
+  /// Takes a hit of `damage`, less defense. Every hit does at least 1 damage. Returns how much
+  /// health was lost.
+  pub fn take_hit(&mut self, damage: u32) -> u32 {
+    let dealt = damage.saturating_sub(self.defense).max(1).min(self.health);
+    self.health -= dealt;
+    dealt
+  }
+
+  /// Takes `damage` from hazardous terrain, which goes straight through defense. Fractions of a
+  /// point carry over to the next call. Returns how much health was lost.
+  pub fn take_hazard(&mut self, damage: f32) -> u32 {
+    self.scorch += damage;
+    let whole = self.scorch.floor();
+    self.scorch -= whole;
+    let dealt = (whole as u32).min(self.health);
+    self.health -= dealt;
+    dealt
+  }
+
+  /// Gets back up to `amount` health, without going over the maximum. Returns how much was gained.
+  pub fn heal(&mut self, amount: u32) -> u32 {
+    let gained = amount.min(self.max_health - self.health);
+    self.health += gained;
+    gained
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_defense_softens_hits() {
+    let mut stats = Stats::new(10, 3, 2);
+    assert_eq!(stats.take_hit(5), 3);
+    assert_eq!(stats.take_hit(1), 1);
+    assert_eq!(stats.health, 6);
+    assert_eq!(stats.take_hit(50), 6);
+    assert!(!stats.is_alive());
+    assert_eq!(stats.take_hit(5), 0);
+  }
+
+  #[test]
+  fn test_healing_stops_at_max() {
+    let mut stats = Stats::new(10, 3, 2);
+    stats.take_hit(6);
+    assert_eq!(stats.heal(3), 3);
+    assert_eq!(stats.heal(3), 1);
+    assert_eq!(stats.health, 10);
+  }
+
+  #[test]
+  fn test_hazards_add_up() {
+    let mut stats = Stats::new(10, 3, 5);
+    assert_eq!(stats.take_hazard(0.5), 0);
+    assert_eq!(stats.take_hazard(0.75), 1);
+    assert_eq!(stats.take_hazard(2.0), 2);
+    assert_eq!(stats.health, 7);
+    assert_eq!(stats.take_hazard(100.0), 7);
+    assert!(!stats.is_alive());
+  }
+}
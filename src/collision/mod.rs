@@ -5,13 +5,14 @@ use crate::util::Meters;
 use na::Isometry2;
 use nc::bounding_volume::AABB;
 use nc::broad_phase::BroadPhasePairFilter;
-use nc::shape::{Compound, Cuboid, ShapeHandle};
+use nc::shape::{Compound, Cuboid, Polyline, ShapeHandle};
 use nc::world::{CollisionGroups, CollisionObject, CollisionObjectHandle};
 
 pub type CollW = nc::world::CollisionWorld<Meters, CollidableDat>;
 pub type CollisionRect = Cuboid<Meters>;
 pub type Shape2D = ShapeHandle<Meters>;
 pub type Compound2D = Compound<Meters>;
+pub type Polyline2D = Polyline<Meters>;
 
 pub fn new_collw() -> CollW {
   let mut retme = CollW::new(0.02);
@@ -57,6 +58,12 @@ pub struct CollidableDat {
 pub enum CollidableType {
   RoomWall,
   CompoundRoomWall,
+  CaveHole,
+  CaveWall,
+  Obstacle,
+  Furniture,
+  Item,
+  Monster,
   Generic, // When the type doesn't really matter
 }
 
@@ -108,6 +115,19 @@ impl BroadPhasePairFilter<Meters, CollidableDat> for SameEntityFilter {
 
 pub struct CollGroups;
 
+/// Walls, which stop everything
+pub static WALL_GROUP: usize = 1;
+pub static WALKER_GROUP: usize = 2;
+/// Terrain that slows walkers down
+pub static SLOW_GROUP: usize = 3;
+/// Terrain that can't be walked over, but can be flown or shot over
+pub static WALK_BLOCK_GROUP: usize = 4;
+pub static PROJECTILE_GROUP: usize = 5;
+/// Items lying on the floor
+pub static ITEM_GROUP: usize = 6;
+/// Looking around for items to pick up
+pub static REACH_GROUP: usize = 7;
+
 /// The syntax is { fn_name [member,ship] [white,list] [black,list] } where the white and black
 /// lists are optional
 macro_rules! new_coll_grp {
@@ -138,7 +158,13 @@ macro_rules! new_coll_grp {
 }
 
 impl CollGroups {
-  new_coll_grp! { wall_cg [1] }
+  new_coll_grp! { wall_cg [WALL_GROUP] }
+  new_coll_grp! { slow_cg [SLOW_GROUP] }
+  new_coll_grp! { walk_block_cg [WALK_BLOCK_GROUP] }
+  new_coll_grp! { walker_cg [WALKER_GROUP] [WALL_GROUP, SLOW_GROUP, WALK_BLOCK_GROUP] }
+  new_coll_grp! { projectile_cg [PROJECTILE_GROUP] [WALL_GROUP] }
+  new_coll_grp! { item_cg [ITEM_GROUP] [WALL_GROUP, REACH_GROUP] }
+  new_coll_grp! { reach_cg [REACH_GROUP] [ITEM_GROUP] }
 }
 
 #[cfg(test)]
@@ -166,4 +192,23 @@ mod test {
     assert!(tcg3.is_group_blacklisted(4));
     assert!(!tcg3.is_group_blacklisted(1));
   }
+
+  #[test]
+  fn test_projectiles_fly_over_terrain() {
+    let walker = CollGroups::walker_cg();
+    let projectile = CollGroups::projectile_cg();
+    for terrain in &[CollGroups::slow_cg(), CollGroups::walk_block_cg()] {
+      assert!(walker.can_interact_with_groups(terrain));
+      assert!(!projectile.can_interact_with_groups(terrain));
+    }
+    assert!(projectile.can_interact_with_groups(&CollGroups::wall_cg()));
+  }
+
+  #[test]
+  fn test_items_are_only_reached_for() {
+    let item = CollGroups::item_cg();
+    assert!(!CollGroups::walker_cg().can_interact_with_groups(&item));
+    assert!(CollGroups::reach_cg().can_interact_with_groups(&item));
+    assert!(CollGroups::wall_cg().can_interact_with_groups(&item));
+  }
 }
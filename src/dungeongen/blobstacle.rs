@@ -1,29 +1,165 @@
 extern crate ggez;
 
-use super::ca_simulator::CASim;
-use crate::util::Point;
-use ggez::graphics::DrawParam;
+use super::ca_simulator::{BoundaryTracer, CASim};
+use super::gen_error::GenError;
+use crate::collision::{CollGroups, Collidable, CollidableType, Compound2D, Shape2D};
+use crate::util::geom::{origin, ring_contains, triangulate_ring};
+use crate::util::{Meters, Point};
+use ggez::graphics::{Color, DrawParam};
 use ggez::{Context, GameResult};
+use na::Isometry2;
+use nc::shape::{Compound, ConvexPolygon, ShapeHandle};
+use nc::world::CollisionGroups;
+use rand::Rng;
+
+/// Blobs get grown on a grid this many cells across
+static BLOB_CELLS: usize = 32;
+/// Blobs that haven't finished growing after this many ticks are given up on
+static MAX_GROWTH_TICKS: usize = 500;
+
+/// What a blobstacle is made of, which decides how it affects things moving past it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlobKind {
+  /// Slows down anything walking through it
+  Water,
+  /// Can't be walked over, but things can be thrown or shot across it
+  Chasm,
+  /// Blocks everything, like a wall
+  Rubble,
+}
+
+impl BlobKind {
+  pub fn all() -> &'static [BlobKind] {
+    static KINDS: [BlobKind; 3] = [BlobKind::Water, BlobKind::Chasm, BlobKind::Rubble];
+    &KINDS
+  }
+
+  pub fn color(self) -> Color {
+    match self {
+      BlobKind::Water => Color::new(0.2, 0.4, 0.9, 1.0),
+      BlobKind::Chasm => Color::new(0.1, 0.1, 0.1, 1.0),
+      BlobKind::Rubble => Color::new(0.6, 0.45, 0.3, 1.0),
+    }
+  }
+
+  pub fn collision_group(self) -> CollisionGroups {
+    match self {
+      BlobKind::Water => CollGroups::slow_cg(),
+      BlobKind::Chasm => CollGroups::walk_block_cg(),
+      BlobKind::Rubble => CollGroups::wall_cg(),
+    }
+  }
+}
 
 /// Blobstacles are backed by a CA sim but have additional information like
 /// a position, ability to determine intersections, etc.
+#[derive(Clone)]
 pub struct Blobstacle {
+  pub kind: BlobKind,
+  /// Top left corner of the blob's sim, in level space
   position: Point,
   sim: CASim,
+  /// The blob's outline, in level space
+  outline: Vec<Point>,
+  /// The outline cut into triangles, so bays in the outline are left open
+  shape: Compound2D,
 }
 
 impl Blobstacle {
-  pub fn _new(pos: Point) -> Blobstacle {
-    // TODO: I think this number is effectively "blob width in world units"
-    // But need to verify that.
-    let mut sim = CASim::new(128, 128, 10.0);
-    sim._generate();
-    Blobstacle { position: pos, sim }
+  /// Grows a blob of the given kind that fits in a `size` by `size` box with its top left corner
+  /// at `pos`. Fails if the CA died out or never finished growing.
+  pub fn new<R: Rng>(
+    rng: &mut R,
+    kind: BlobKind,
+    pos: Point,
+    size: Meters,
+  ) -> Result<Blobstacle, GenError> {
+    let mut sim = CASim::new(rng, BLOB_CELLS, BLOB_CELLS, size);
+    sim.tracer = BoundaryTracer::MarchingSquares;
+    sim.meters_per_cell = size / BLOB_CELLS as f32;
+    if !(0..MAX_GROWTH_TICKS).any(|_| sim.tick()) {
+      return Err(GenError::BlobDidNotGrow { ticks: MAX_GROWTH_TICKS });
+    }
+    let outline = sim.uspace_boundary(Point::new(0.0, 0.0));
+    let outline: Vec<Point> = outline.iter().map(|p| pos + p.coords).collect();
+    Blobstacle::with_outline(kind, pos, sim, outline)
+  }
+
+  /// A blob whose collision shape follows `outline`, which is in level space. Fails if the outline
+  /// doesn't enclose any area.
+  fn with_outline(
+    kind: BlobKind,
+    position: Point,
+    sim: CASim,
+    outline: Vec<Point>,
+  ) -> Result<Blobstacle, GenError> {
+    let pieces: Vec<(Isometry2<Meters>, Shape2D)> = triangulate_ring(&outline)
+      .iter()
+      .filter_map(|tri| ConvexPolygon::try_from_points(tri))
+      .map(|tri| (origin(), ShapeHandle::new(tri)))
+      .collect();
+    if pieces.is_empty() {
+      return Err(GenError::BadOutline { corners: outline.len() });
+    }
+    Ok(Blobstacle { kind, position, sim, outline, shape: Compound::new(pieces) })
+  }
+
+  /// Corners of the blob's outline, in level space
+  pub fn outline(&self) -> &[Point] {
+    &self.outline
+  }
+
+  pub fn contains(&self, p: Point) -> bool {
+    ring_contains(&self.outline, p)
   }
 
   pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
     let repositioned =
-      DrawParam { dest: self.position.into(), color: (227, 77, 40).into(), ..DrawParam::default() };
+      DrawParam { dest: self.position.into(), color: self.kind.color(), ..DrawParam::default() };
     self.sim.draw(ctx, repositioned)
   }
 }
+
+impl Collidable for Blobstacle {
+  fn location(&self) -> Isometry2<Meters> {
+    origin()
+  }
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(self.shape.clone())
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    self.kind.collision_group()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::Obstacle
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use nc::query::PointQuery;
+
+  #[test]
+  fn test_bays_are_left_open() {
+    // A C shape, open to the east
+    let outline = vec![
+      Point::new(0.0, 0.0),
+      Point::new(4.0, 0.0),
+      Point::new(4.0, 1.0),
+      Point::new(1.0, 1.0),
+      Point::new(1.0, 3.0),
+      Point::new(4.0, 3.0),
+      Point::new(4.0, 4.0),
+      Point::new(0.0, 4.0),
+    ];
+    let sim = CASim::from_grid(vec![vec![false]], 1.0);
+    let blob = Blobstacle::with_outline(BlobKind::Chasm, outline[0], sim, outline).unwrap();
+    // The bay is inside the C's hull, but nothing is drawn there so nothing should be in the way
+    let (bay, solid) = (Point::new(3.0, 2.0), Point::new(0.5, 2.0));
+    assert!(!blob.contains(bay));
+    assert!(!blob.shape.contains_point(&origin(), &bay));
+    assert!(blob.contains(solid));
+    assert!(blob.shape.contains_point(&origin(), &solid));
+  }
+}
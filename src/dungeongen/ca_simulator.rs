@@ -2,55 +2,202 @@ extern crate ggez;
 extern crate rand;
 
 use super::direction::Direction;
-use crate::util::Point;
+use super::marching_squares;
+use crate::util::geom::{ring_area, smooth_ring, walk_grid, GridRect, IntPoint, PolygonWithHoles};
 use crate::util::Vec2;
+use crate::util::{Meters, Point};
 use ggez::{
   graphics::draw,
   graphics::{Color, DrawMode, DrawParam, Drawable, FilterMode, Image, Mesh},
   Context, GameResult,
 };
+use rand::Rng;
 
 type CellGrid = Vec<Vec<bool>>;
+type Cell = (i32, i32);
 
+/// Connected groups of live cells smaller than this are dropped as noise
+static MIN_CAVE_CELLS: usize = 5;
+/// Holes smaller than this are filled in rather than traced
+static MIN_HOLE_CELLS: usize = 4;
+/// Tunnels carved between caves are this many cells either side of their center line
+static TUNNEL_RADIUS: i32 = 2;
+/// Growth stops after this many iterations even if the CA never reached the edge of the grid, so
+/// a CA that dies out or stalls can't keep the sim running forever
+static MAX_CA_ITERATIONS: usize = 1000;
+
+/// The outline of one connected cave, and the outlines of any holes inside of it. Coordinates are
+/// in CA cell space.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaveOutline {
+  pub outer: Vec<Point>,
+  pub holes: Vec<Vec<Point>>,
+}
+
+/// How traced outlines get cleaned up once tracing is done
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundarySmoothing {
+  /// Outline points closer than this to the simplified outline are dropped
+  pub tolerance: Meters,
+  /// How many rounds of corner cutting to do after simplifying
+  pub chaikin_passes: u32,
+}
+
+impl Default for BoundarySmoothing {
+  fn default() -> Self {
+    BoundarySmoothing { tolerance: 0.3, chaikin_passes: 2 }
+  }
+}
+
+/// Which algorithm turns the grid of cells into cave outlines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundaryTracer {
+  /// Walks around the edge cells one step per tick, so it can be watched as it goes. Outline
+  /// points are cell centers.
+  RadialSweep,
+  /// Extracts every outline in a single tick. Outline points sit halfway between live and dead
+  /// cells, so it doesn't cut corners and is always deterministic.
+  MarchingSquares,
+}
+
+/// A ring waiting to be traced by the boundary stage
+#[derive(Clone)]
+struct TraceJob {
+  /// Copy of the grid where only the cells of the component being traced are live. The tracer is
+  /// free to mangle it.
+  mask: CellGrid,
+  cave: usize,
+  is_hole: bool,
+}
+
+#[derive(Clone)]
 pub struct CASim {
   pub ca_grid: CellGrid,
-  pub ca_boundary: Vec<(i32, i32)>,
+  /// The ring currently being traced
+  pub ca_boundary: Vec<Cell>,
+  /// Every connected cave in the grid, biggest first
+  pub caves: Vec<CaveOutline>,
+  /// When true, separate caves are joined by tunnels into one cave before tracing
+  pub merge_caves: bool,
+  pub tracer: BoundaryTracer,
+  pub smoothing: BoundarySmoothing,
+  /// How big a cell is, used to convert the smoothing tolerance to cells
+  pub meters_per_cell: Meters,
   width: usize,
   height: usize,
   scale: f32,
   gen_stage: u8,
+  /// How many times the CA rules have been applied
+  ca_iterations: usize,
   bounds_last_dir: Direction,
+  trace_jobs: Vec<TraceJob>,
+  cur_trace: Option<TraceJob>,
 }
 
-fn gen_cave(width: usize, height: usize) -> CellGrid {
+fn gen_cave<R: Rng>(rng: &mut R, width: usize, height: usize, seeds: &[GridRect]) -> CellGrid {
   let mut ca_grid = vec![vec![false; height]; width];
-  // First populate a random box in the middle of the grid
-  let inner_box_w = width / 4;
-  let inner_box_h = height / 4;
-  let left_edge = (width / 2) - (inner_box_w / 2);
-  let top_edge = (height / 2) - (inner_box_h / 2);
-  for x in left_edge..(inner_box_w + left_edge) {
-    for y in top_edge..(inner_box_h + top_edge) {
-      ca_grid[x][y] = rand::random();
+  // Populate each seed box with random cells
+  for seed in seeds {
+    let left_edge = seed.top_left.x.max(0) as usize;
+    let top_edge = seed.top_left.y.max(0) as usize;
+    for x in left_edge..(seed.width as usize + left_edge).min(width - 1) {
+      for y in top_edge..(seed.height as usize + top_edge).min(height - 1) {
+        ca_grid[x][y] = rng.gen();
+      }
     }
   }
   ca_grid
 }
 
 impl CASim {
-  pub fn new(width: usize, height: usize, scale: f32) -> CASim {
-    let ca_grid = gen_cave(width, height);
+  pub fn new<R: Rng>(rng: &mut R, width: usize, height: usize, scale: f32) -> CASim {
+    // A single random box in the middle of the grid
+    let inner_box_w = width / 4;
+    let inner_box_h = height / 4;
+    let left_edge = (width / 2) - (inner_box_w / 2);
+    let top_edge = (height / 2) - (inner_box_h / 2);
+    let seed = GridRect::new(
+      inner_box_w as u32,
+      inner_box_h as u32,
+      IntPoint::new(left_edge as i32, top_edge as i32),
+    );
+    CASim::with_seeds(rng, width, height, scale, &[seed])
+  }
+
+  /// Creates a sim whose initial cells are randomly populated inside each of the seed boxes
+  pub fn with_seeds<R: Rng>(
+    rng: &mut R,
+    width: usize,
+    height: usize,
+    scale: f32,
+    seeds: &[GridRect],
+  ) -> CASim {
+    CASim::with_grid(gen_cave(rng, width, height, seeds), scale)
+  }
+
+  /// Creates a sim from an already grown grid, which skips straight to finding and tracing caves
+  pub fn from_grid(ca_grid: CellGrid, scale: f32) -> CASim {
+    let mut sim = CASim::with_grid(ca_grid, scale);
+    sim.gen_stage = 1;
+    sim
+  }
+
+  fn with_grid(ca_grid: CellGrid, scale: f32) -> CASim {
+    let (width, height) = (ca_grid.len(), ca_grid[0].len());
     CASim {
       ca_grid,
       ca_boundary: Vec::new(),
+      caves: Vec::new(),
+      merge_caves: false,
+      tracer: BoundaryTracer::RadialSweep,
+      smoothing: BoundarySmoothing::default(),
+      meters_per_cell: 1.0,
       width,
       height,
       scale,
       gen_stage: 0,
+      ca_iterations: 0,
       bounds_last_dir: Direction::SouthEast,
+      trace_jobs: Vec::new(),
+      cur_trace: None,
     }
   }
 
+  /// A copy with only what drawing needs, leaving out the grid masks that tracing works through
+  pub fn drawable(&self) -> CASim {
+    CASim {
+      ca_grid: self.ca_grid.clone(),
+      ca_boundary: self.ca_boundary.clone(),
+      caves: self.caves.clone(),
+      trace_jobs: Vec::new(),
+      cur_trace: None,
+      ..*self
+    }
+  }
+
+  /// Generates `count` randomly placed seed boxes that fit inside a grid of the given size
+  pub fn rand_seeds<R: Rng>(
+    rng: &mut R,
+    width: usize,
+    height: usize,
+    count: usize,
+  ) -> Vec<GridRect> {
+    let box_w = width / 8;
+    let box_h = height / 8;
+    (0..count)
+      .map(|_| {
+        let x = rng.gen_range(width / 8, width - box_w - width / 8);
+        let y = rng.gen_range(height / 8, height - box_h - height / 8);
+        GridRect::new(box_w as u32, box_h as u32, IntPoint::new(x as i32, y as i32))
+      })
+      .collect()
+  }
+
+  /// How many rounds of growth the CA went through before reaching the edge of the grid
+  pub fn ca_iterations(&self) -> usize {
+    self.ca_iterations
+  }
+
   pub fn _generate(&mut self) {
     while !self.tick() {}
   }
@@ -58,12 +205,21 @@ impl CASim {
   pub fn tick(&mut self) -> bool {
     let stage_complete = match self.gen_stage {
       0 => self.tick_ca_sim(),
-      1 => self.tick_cave_boundary(),
-      2 => self.smooth_cave_boundary(),
-      3 => {
-        // Make sure boundary is fully conected
-        let back_to_first = self.ca_boundary[0];
-        self.ca_boundary.push(back_to_first);
+      1 => {
+        self.find_caves();
+        true
+      }
+      2 => match self.tracer {
+        BoundaryTracer::RadialSweep => self.tick_cave_boundary(),
+        BoundaryTracer::MarchingSquares => self.march_cave_boundaries(),
+      },
+      3 => self.smooth_cave_boundary(),
+      4 => {
+        // Make sure boundaries are fully conected
+        for cave in self.caves.iter_mut() {
+          close_ring(&mut cave.outer);
+          cave.holes.iter_mut().for_each(close_ring);
+        }
         true
       }
       _ => false,
@@ -71,96 +227,250 @@ impl CASim {
     if stage_complete {
       self.gen_stage += 1
     }
-    self.gen_stage > 3
+    self.gen_stage > 4
   }
 
-  /// Converts cellular automata space to unit space (scaled)
+  /// The outline of the biggest cave, converted from cellular automata space to unit space
+  /// (scaled)
   pub fn uspace_boundary(&self, shift: Point) -> Vec<Point> {
-    self
-      .ca_boundary
+    self.caves.first().map(|c| self.uspace_ring(&c.outer, shift)).unwrap_or_default()
+  }
+
+  /// Converts a ring in cellular automata space to unit space (scaled)
+  pub fn uspace_ring(&self, ring: &[Point], shift: Point) -> Vec<Point> {
+    ring
       .iter()
-      .map(|&(x, y)| {
-        let xp = ((x as f32) / (self.width as f32) + shift.x) * self.scale;
-        let yp = ((y as f32) / (self.height as f32) + shift.y) * self.scale;
+      .map(|p| {
+        let xp = (p.x / (self.width as f32) + shift.x) * self.scale;
+        let yp = (p.y / (self.height as f32) + shift.y) * self.scale;
         Point::new(xp, yp)
       })
       .collect()
   }
 
-  pub fn uspace_gboundary(&self) -> Vec<Point> {
-    // We shift by a half because we want to draw the sim centered around
-    // the destination point.
-    self.uspace_boundary(Point::new(-0.5, -0.5)).into_iter().collect()
+  /// Every traced cave, along with its holes, converted to unit space (scaled)
+  pub fn uspace_caves(&self, shift: Point) -> Vec<PolygonWithHoles> {
+    self
+      .caves
+      .iter()
+      .map(|c| {
+        let holes = c.holes.iter().map(|h| self.uspace_ring(h, shift)).collect();
+        PolygonWithHoles::new(self.uspace_ring(&c.outer, shift), holes)
+      })
+      .collect()
   }
 
   fn smooth_cave_boundary(&mut self) -> bool {
-    let mut a = 0;
-    self.ca_boundary.retain(|_| {
-      a += 1;
-      a % 2 == 0
-    });
+    let tolerance = self.smoothing.tolerance / self.meters_per_cell;
+    let passes = self.smoothing.chaikin_passes;
+    for cave in self.caves.iter_mut() {
+      cave.outer = smooth_ring(&cave.outer, tolerance, passes);
+      for hole in cave.holes.iter_mut() {
+        *hole = smooth_ring(hole, tolerance, passes);
+      }
+      // Rings the tracer couldn't make anything sensible out of aren't worth keeping
+      cave.holes.retain(|h| h.len() >= 3);
+    }
+    self.caves.retain(|c| c.outer.len() >= 3);
     true
   }
 
-  fn tick_cave_boundary(&mut self) -> bool {
-    // Inspect grid, starting top left and work around clockwise building poly
-    let mut cur_cell = (0, 0);
-    // First I move in from the corner until I hit a cell, if this is the first
-    // tick.
-    if self.ca_boundary.is_empty() {
-      'out: for x in 0..(self.width - 1) {
-        for y in 0..(self.height - 1) {
-          if self.ca_grid[x][y] {
-            cur_cell = (x as i32, y as i32);
-            break 'out;
+  /// Labels all the separate caves in the grid, optionally joins them together, and queues up
+  /// the outer ring and hole rings of each to be traced.
+  fn find_caves(&mut self) {
+    let mut caves = self.components(true);
+    // Drop the tiny ones, they'd just be noise
+    for tiny in caves.iter().filter(|c| c.len() < MIN_CAVE_CELLS) {
+      for &(x, y) in tiny {
+        self.ca_grid[x as usize][y as usize] = false;
+      }
+    }
+    caves.retain(|c| c.len() >= MIN_CAVE_CELLS);
+    if self.merge_caves && caves.len() > 1 {
+      self.carve_tunnels(caves);
+    }
+    // Fill in the tiny holes before labelling, so the filled cells end up part of their cave
+    for hole in self.components(false) {
+      if hole.len() < MIN_HOLE_CELLS && !hole.iter().any(|&c| self.on_grid_edge(c)) {
+        for &(x, y) in &hole {
+          self.ca_grid[x as usize][y as usize] = true;
+        }
+      }
+    }
+    caves = self.components(true);
+    caves.sort_by_key(|c| std::cmp::Reverse(c.len()));
+
+    let mut cave_labels = vec![vec![None; self.height]; self.width];
+    for (i, cave) in caves.iter().enumerate() {
+      for &(x, y) in cave {
+        cave_labels[x as usize][y as usize] = Some(i);
+      }
+    }
+    // Holes are the dead regions that don't reach the edge of the grid. Whichever cave is
+    // next to a hole is the one surrounding it.
+    let mut holes: Vec<(usize, Vec<Cell>)> = vec![];
+    for hole in self.components(false) {
+      if hole.iter().any(|&c| self.on_grid_edge(c)) {
+        continue;
+      }
+      let owner = hole.iter().find_map(|&(x, y)| {
+        Direction::compass()
+          .iter()
+          .map(|d| d.dir_from_tup((x, y)))
+          .find_map(|(nx, ny)| cave_labels[nx as usize][ny as usize])
+      });
+      if let Some(owner) = owner {
+        holes.push((owner, hole));
+      }
+    }
+
+    // Jobs are popped off the end, so queue them up backwards to trace the biggest cave first
+    self.caves = vec![CaveOutline::default(); caves.len()];
+    self.trace_jobs.clear();
+    for (owner, hole) in holes.into_iter().rev() {
+      self.trace_jobs.push(TraceJob { mask: self.mask_of(&hole), cave: owner, is_hole: true });
+    }
+    for (i, cave) in caves.iter().enumerate().rev() {
+      self.trace_jobs.push(TraceJob { mask: self.mask_of(cave), cave: i, is_hole: false });
+    }
+  }
+
+  /// Joins every cave onto the biggest one, closest first, by carving straight tunnels
+  fn carve_tunnels(&mut self, mut caves: Vec<Vec<Cell>>) {
+    caves.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    let mut joined = caves.remove(0);
+    while !caves.is_empty() {
+      let target = centroid(&joined);
+      let (nearest_ix, from) = caves
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, closest_cell(c, target)))
+        .min_by_key(|&(_, c)| dist_sq(c, target))
+        .unwrap();
+      let to = closest_cell(&joined, from);
+      for step in walk_grid(IntPoint::new(from.0, from.1), IntPoint::new(to.0, to.1)) {
+        for x in (step.x - TUNNEL_RADIUS)..=(step.x + TUNNEL_RADIUS) {
+          for y in (step.y - TUNNEL_RADIUS)..=(step.y + TUNNEL_RADIUS) {
+            // The CA never touches the outermost cells, so neither do tunnels
+            if x > 0 && y > 0 && x < self.width as i32 - 1 && y < self.height as i32 - 1 {
+              self.ca_grid[x as usize][y as usize] = true;
+              joined.push((x, y));
+            }
           }
         }
       }
-      self.ca_boundary.push(cur_cell);
-    }
-    cur_cell = *self.ca_boundary.last().unwrap();
-    // Then we will use a radial sweep algorithm to trace the boundary of the
-    // cells. Starting from the current point, we check it's neighbors in a
-    // clockwise fashion until we find another occupied cell.
-
-    // Start the sweep one tick clockwise from the direction we just came from
-    let in_dir = self.bounds_last_dir.opposite();
-    let dirs = {
-      let first = Direction::iterator().skip_while(|x| **x != in_dir).skip(1);
-      let rest = Direction::iterator().take_while(|x| **x != in_dir);
-      first.chain(rest)
+      joined.append(&mut caves.remove(nearest_ix));
+    }
+  }
+
+  /// Flood fills the grid into connected groups of cells that are all `live` (or all dead). Live
+  /// cells connect diagonally, like the boundary tracer moves. Dead cells only connect along
+  /// the compass directions, otherwise holes would leak out between diagonal live cells.
+  fn components(&self, live: bool) -> Vec<Vec<Cell>> {
+    let dirs: Vec<&Direction> =
+      if live { Direction::iterator().collect() } else { Direction::compass().iter().collect() };
+    let mut seen = vec![vec![false; self.height]; self.width];
+    let mut comps = vec![];
+    for x in 0..self.width {
+      for y in 0..self.height {
+        if seen[x][y] || self.ca_grid[x][y] != live {
+          continue;
+        }
+        seen[x][y] = true;
+        let mut comp = vec![];
+        let mut stack = vec![(x as i32, y as i32)];
+        while let Some(cell) = stack.pop() {
+          comp.push(cell);
+          for d in &dirs {
+            let (nx, ny) = d.dir_from_tup(cell);
+            if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+              continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if !seen[nx][ny] && self.ca_grid[nx][ny] == live {
+              seen[nx][ny] = true;
+              stack.push((nx as i32, ny as i32));
+            }
+          }
+        }
+        comps.push(comp);
+      }
+    }
+    comps
+  }
+
+  fn mask_of(&self, cells: &[Cell]) -> CellGrid {
+    let mut mask = vec![vec![false; self.height]; self.width];
+    for &(x, y) in cells {
+      mask[x as usize][y as usize] = true;
+    }
+    mask
+  }
+
+  fn on_grid_edge(&self, (x, y): Cell) -> bool {
+    x == 0 || y == 0 || x == self.width as i32 - 1 || y == self.height as i32 - 1
+  }
+
+  /// Traces the queued rings one step at a time. Returns true once every ring has been traced.
+  fn tick_cave_boundary(&mut self) -> bool {
+    if self.cur_trace.is_none() {
+      if self.caves.is_empty() {
+        self.find_caves();
+      }
+      match self.trace_jobs.pop() {
+        Some(job) => {
+          self.ca_boundary.clear();
+          self.bounds_last_dir = Direction::SouthEast;
+          self.cur_trace = Some(job);
+        }
+        None => return true,
+      }
+    }
+    let ring_done = {
+      let job = self.cur_trace.as_mut().unwrap();
+      trace_step(&mut job.mask, &mut self.ca_boundary, &mut self.bounds_last_dir)
     };
-    let mut marked_encountered = 0;
-    let mut pushed_one = false;
-    for dir in dirs {
-      let cur_pt = dir.dir_from_tup(cur_cell);
-      // Bounds check, followed by cell present check
-      let in_width = cur_pt.0 >= 0 && cur_pt.0 <= self.width as i32;
-      let in_height = cur_pt.1 >= 0 && cur_pt.1 <= self.height as i32;
-      let not_marked = !self.ca_boundary.contains(&cur_pt);
-      if !not_marked {
-        marked_encountered += 1;
+    if ring_done {
+      let job = self.cur_trace.take().unwrap();
+      let ring: Vec<Point> =
+        self.ca_boundary.drain(..).map(|(x, y)| Point::new(x as f32, y as f32)).collect();
+      if job.is_hole {
+        self.caves[job.cave].holes.push(ring);
+      } else {
+        self.caves[job.cave].outer = ring;
       }
-      if in_width && in_height && self.ca_grid[cur_pt.0 as usize][cur_pt.1 as usize] && not_marked {
-        cur_cell = cur_pt;
-        self.ca_boundary.push(cur_cell);
-        self.bounds_last_dir = *dir;
-        pushed_one = true;
-        break;
+      if self.trace_jobs.is_empty() {
+        return true;
       }
     }
-    if !pushed_one {
-      // If we didn't add anything to the boundary then we're gonna get stuck. Avoid that
-      // by removing the cell we're currently on. We don't really care about mangling the
-      // underlying ca sim.
-      if let Some((x, y)) = self.ca_boundary.pop() {
-        self.ca_grid[x as usize][y as usize] = false;
+    false
+  }
+
+  /// Extracts the outline and holes of every queued cave in one go with marching squares. The
+  /// cave masks already contain their holes, so the hole jobs aren't needed.
+  fn march_cave_boundaries(&mut self) -> bool {
+    if self.caves.is_empty() {
+      self.find_caves();
+    }
+    for job in self.trace_jobs.drain(..).filter(|j| !j.is_hole) {
+      let mut rings = marching_squares::contours(&job.mask);
+      // There's only one component in the mask, so its outline is the biggest ring and anything
+      // winding the other way is a hole
+      rings.sort_by(|a, b| ring_area(b).abs().partial_cmp(&ring_area(a).abs()).unwrap());
+      if rings.is_empty() {
+        continue;
       }
+      let outer = rings.remove(0);
+      let outer_sign = ring_area(&outer).signum();
+      let cave = &mut self.caves[job.cave];
+      cave.holes = rings.into_iter().filter(|r| ring_area(r).signum() != outer_sign).collect();
+      cave.outer = outer;
     }
-    marked_encountered >= 2
+    true
   }
 
   fn tick_ca_sim(&mut self) -> bool {
+    self.ca_iterations += 1;
     let mut growth_done = false;
     let mut ca_grid_next = vec![vec![false; self.height]; self.width];
     {
@@ -187,6 +497,10 @@ impl CASim {
       }
     }
     self.ca_grid = ca_grid_next;
+    if self.ca_iterations >= MAX_CA_ITERATIONS {
+      warn!("Cave CA still growing after {} iterations, stopping it", self.ca_iterations);
+      growth_done = true;
+    }
     if growth_done {
       // Trim all the "danglers" - these prevent boundary from forming properly. The boundary
       // algorithm can recover from danglers created by this pass, but the first pass avoids
@@ -245,21 +559,25 @@ impl CASim {
     let mut img = Image::from_rgba8(ctx, self.width as u16, self.height as u16, &ca_img_a)?;
     let mut scaled_params = param;
     scaled_params.scale = scalevec.into();
-    scaled_params.dest = Point::new(0.0, 0.0).into();
     // Don't make my pixels all blurry
     img.set_filter(FilterMode::Nearest);
     img.draw(ctx, scaled_params)?;
 
-    let cave_bounds = self.uspace_gboundary();
-    if !cave_bounds.is_empty() {
-      // Line width also scales w/ draw param, so need to make it reasonable.
-      let line = Mesh::new_line(
-        ctx,
-        cave_bounds.as_slice(),
-        4.0 / param.scale.x,
-        Color::new(1.0, 1.0, 1.0, 1.0),
-      )?;
-      draw(ctx, &line, param)?;
+    let in_progress: Vec<Point> =
+      self.ca_boundary.iter().map(|&(x, y)| Point::new(x as f32, y as f32)).collect();
+    let traced = self.caves.iter().flat_map(|c| Some(&c.outer).into_iter().chain(c.holes.iter()));
+    for ring in traced.chain(Some(&in_progress)) {
+      let cave_bounds = self.uspace_ring(ring, Point::new(0.0, 0.0));
+      if cave_bounds.len() >= 2 {
+        // Line width also scales w/ draw param, so need to make it reasonable.
+        let line = Mesh::new_line(
+          ctx,
+          cave_bounds.as_slice(),
+          4.0 / param.scale.x,
+          Color::new(1.0, 1.0, 1.0, 1.0),
+        )?;
+        draw(ctx, &line, param)?;
+      }
     }
     Ok(())
   }
@@ -282,16 +600,109 @@ impl CASim {
   }
 
   pub fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
-    let bounds = self.uspace_boundary(Point::new(0.0, 0.0));
-    let mesh =
-      Mesh::new_polygon(ctx, DrawMode::fill(), bounds.as_slice(), Color::new(0.7, 0.7, 0.7, 1.0))?;
-    draw(ctx, &mesh, param)
+    let fill_ring = |ctx: &mut Context, ring: &[Point], color: Color| -> GameResult<()> {
+      if ring.len() < 3 {
+        return Ok(());
+      }
+      let mesh = Mesh::new_polygon(ctx, DrawMode::fill(), ring, color)?;
+      draw(ctx, &mesh, param)
+    };
+    for cave in self.uspace_caves(Point::new(0.0, 0.0)) {
+      fill_ring(ctx, &cave.outer, Color::new(0.7, 0.7, 0.7, 1.0))?;
+      // Holes are drawn over the top in the background color, so they look cut out
+      for hole in &cave.holes {
+        fill_ring(ctx, hole, Color::new(0.0, 0.0, 0.0, 1.0))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// One step of a radial sweep around the live cells of `mask`, extending `ring`. Returns true
+/// once the ring has come back around to where it started.
+fn trace_step(mask: &mut CellGrid, ring: &mut Vec<Cell>, last_dir: &mut Direction) -> bool {
+  let width = mask.len();
+  let height = mask[0].len();
+  // Inspect grid, starting top left and work around clockwise building poly
+  // First I move in from the corner until I hit a cell, if this is the first
+  // tick.
+  if ring.is_empty() {
+    let first = (0..(width - 1))
+      .flat_map(|x| (0..(height - 1)).map(move |y| (x, y)))
+      .find(|&(x, y)| mask[x][y]);
+    match first {
+      Some((x, y)) => ring.push((x as i32, y as i32)),
+      // Nothing left to trace
+      None => return true,
+    }
+  }
+  let mut cur_cell = *ring.last().unwrap();
+  // Then we will use a radial sweep algorithm to trace the boundary of the
+  // cells. Starting from the current point, we check it's neighbors in a
+  // clockwise fashion until we find another occupied cell.
+
+  // Start the sweep one tick clockwise from the direction we just came from
+  let in_dir = last_dir.opposite();
+  let dirs = {
+    let first = Direction::iterator().skip_while(|x| **x != in_dir).skip(1);
+    let rest = Direction::iterator().take_while(|x| **x != in_dir);
+    first.chain(rest)
+  };
+  let mut marked_encountered = 0;
+  let mut pushed_one = false;
+  for dir in dirs {
+    let cur_pt = dir.dir_from_tup(cur_cell);
+    // Bounds check, followed by cell present check
+    let in_width = cur_pt.0 >= 0 && cur_pt.0 < width as i32;
+    let in_height = cur_pt.1 >= 0 && cur_pt.1 < height as i32;
+    let not_marked = !ring.contains(&cur_pt);
+    if !not_marked {
+      marked_encountered += 1;
+    }
+    if in_width && in_height && mask[cur_pt.0 as usize][cur_pt.1 as usize] && not_marked {
+      cur_cell = cur_pt;
+      ring.push(cur_cell);
+      *last_dir = *dir;
+      pushed_one = true;
+      break;
+    }
   }
+  if !pushed_one {
+    // If we didn't add anything to the boundary then we're gonna get stuck. Avoid that
+    // by removing the cell we're currently on. The mask is a copy, so we don't care about
+    // mangling it.
+    if let Some((x, y)) = ring.pop() {
+      mask[x as usize][y as usize] = false;
+    }
+  }
+  marked_encountered >= 2
+}
+
+fn close_ring(ring: &mut Vec<Point>) {
+  if let Some(&back_to_first) = ring.first() {
+    ring.push(back_to_first);
+  }
+}
+
+fn centroid(cells: &[Cell]) -> Cell {
+  let (sx, sy) =
+    cells.iter().fold((0i64, 0i64), |(sx, sy), &(x, y)| (sx + x as i64, sy + y as i64));
+  let n = cells.len().max(1) as i64;
+  ((sx / n) as i32, (sy / n) as i32)
+}
+
+fn dist_sq(a: Cell, b: Cell) -> i32 {
+  (a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)
+}
+
+fn closest_cell(cells: &[Cell], to: Cell) -> Cell {
+  *cells.iter().min_by_key(|&&c| dist_sq(c, to)).unwrap()
 }
 
 #[cfg(test)]
 mod test {
   use super::*;
+  use crate::dungeongen::level_rng::level_rng;
 
   extern crate timebomb;
 
@@ -299,7 +710,7 @@ mod test {
 
   #[test]
   fn test_boundary_doesnt_get_stuck() {
-    let mut tsim = CASim::new(10, 10, 1.0);
+    let mut tsim = CASim::new(&mut level_rng(1), 10, 10, 1.0);
     tsim.ca_grid[3][3] = true;
     tsim.ca_grid[3][2] = true;
     tsim.ca_grid[4][3] = true;
@@ -312,4 +723,127 @@ mod test {
       1000,
     )
   }
+
+  /// A sim with nothing alive, past the CA stage
+  fn empty_sim(width: usize, height: usize) -> CASim {
+    CASim::from_grid(vec![vec![false; height]; width], 1.0)
+  }
+
+  fn fill(tsim: &mut CASim, x: usize, y: usize, w: usize, h: usize, live: bool) {
+    for col in tsim.ca_grid.iter_mut().skip(x).take(w) {
+      for cell in col.iter_mut().skip(y).take(h) {
+        *cell = live;
+      }
+    }
+  }
+
+  #[test]
+  fn test_separate_caves_are_traced() {
+    let mut tsim = empty_sim(40, 40);
+    fill(&mut tsim, 2, 2, 8, 8, true);
+    fill(&mut tsim, 25, 25, 10, 10, true);
+    tsim._generate();
+    assert_eq!(tsim.caves.len(), 2);
+    assert!(tsim.caves.iter().all(|c| !c.outer.is_empty() && c.holes.is_empty()));
+    // The biggest cave comes first
+    assert!(tsim.caves[0].outer.iter().all(|p| p.x >= 24.0 && p.y >= 24.0));
+  }
+
+  #[test]
+  fn test_merged_caves_become_one() {
+    let mut tsim = empty_sim(40, 40);
+    tsim.merge_caves = true;
+    fill(&mut tsim, 2, 2, 8, 8, true);
+    fill(&mut tsim, 25, 25, 10, 10, true);
+    fill(&mut tsim, 2, 30, 6, 6, true);
+    tsim._generate();
+    assert_eq!(tsim.caves.len(), 1);
+    assert_eq!(tsim.components(true).len(), 1);
+  }
+
+  #[test]
+  fn test_tiny_caves_are_dropped() {
+    let mut tsim = empty_sim(20, 20);
+    fill(&mut tsim, 2, 2, 8, 8, true);
+    fill(&mut tsim, 15, 15, 2, 2, true);
+    tsim._generate();
+    assert_eq!(tsim.caves.len(), 1);
+    assert!(!tsim.ca_grid[15][15]);
+  }
+
+  #[test]
+  fn test_holes_are_traced() {
+    let mut tsim = empty_sim(30, 30);
+    fill(&mut tsim, 2, 2, 20, 20, true);
+    // A hole big enough to keep, and one small enough to be filled in
+    fill(&mut tsim, 6, 6, 4, 4, false);
+    fill(&mut tsim, 15, 15, 1, 1, false);
+    tsim._generate();
+    assert_eq!(tsim.caves.len(), 1);
+    assert_eq!(tsim.caves[0].holes.len(), 1);
+    let hole = &tsim.caves[0].holes[0];
+    assert!(hole.iter().all(|p| p.x >= 6.0 && p.x <= 9.0 && p.y >= 6.0 && p.y <= 9.0));
+    assert!(tsim.ca_grid[15][15]);
+  }
+
+  #[test]
+  fn test_marching_squares_finds_holes() {
+    let mut tsim = empty_sim(30, 30);
+    tsim.tracer = BoundaryTracer::MarchingSquares;
+    fill(&mut tsim, 2, 2, 20, 20, true);
+    fill(&mut tsim, 6, 6, 4, 4, false);
+    fill(&mut tsim, 15, 15, 1, 1, false);
+    // Everything is traced in one tick
+    assert!(!tsim.tick());
+    assert!(!tsim.tick());
+    assert!(tsim.caves[0].outer.len() > 3);
+    tsim._generate();
+    assert_eq!(tsim.caves.len(), 1);
+    assert_eq!(tsim.caves[0].holes.len(), 1);
+    let hole = &tsim.caves[0].holes[0];
+    assert!(hole.iter().all(|p| p.x >= 5.5 && p.x <= 9.5 && p.y >= 5.5 && p.y <= 9.5));
+  }
+
+  #[test]
+  fn test_outlines_are_closed_simple_polygons() {
+    use crate::util::geom::is_simple_ring;
+    for seed in 0..10 {
+      let mut rng = level_rng(seed);
+      // A blob with ragged edges and some random holes punched through it
+      let mut grid = vec![vec![false; 80]; 80];
+      for (x, col) in grid.iter_mut().enumerate() {
+        for (y, cell) in col.iter_mut().enumerate() {
+          let dist = ((x as f32 - 40.0).powi(2) + (y as f32 - 40.0).powi(2)).sqrt();
+          *cell = dist < 25.0 + rng.gen::<f32>() * 8.0 && rng.gen::<f32>() > 0.1;
+        }
+      }
+      let mut tsim = CASim::from_grid(grid, 1.0);
+      tsim.meters_per_cell = 0.25;
+      if seed % 2 == 0 {
+        tsim.tracer = BoundaryTracer::MarchingSquares;
+      }
+      tsim._generate();
+      assert!(!tsim.caves.is_empty(), "seed {}", seed);
+      for cave in &tsim.caves {
+        for ring in Some(&cave.outer).into_iter().chain(cave.holes.iter()) {
+          assert_eq!(ring.first(), ring.last(), "seed {}", seed);
+          assert!(is_simple_ring(&ring[..ring.len() - 1]), "seed {}: {:?}", seed, ring);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_dead_ca_still_finishes() {
+    timeout_ms(
+      || {
+        // No seeds means nothing ever grows towards the edge
+        let mut sim = CASim::with_seeds(&mut level_rng(1), 20, 20, 1.0, &[]);
+        while !sim.tick() {}
+        assert_eq!(sim.ca_iterations(), MAX_CA_ITERATIONS);
+        assert!(sim.caves.is_empty());
+      },
+      10000,
+    )
+  }
 }
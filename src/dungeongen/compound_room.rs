@@ -1,4 +1,5 @@
 use crate::collision::{Collidable, CollisionRect};
+use crate::dungeongen::gen_error::GenError;
 use crate::dungeongen::level::Wall;
 use crate::dungeongen::level::WALL_THICKNESS;
 use crate::dungeongen::rooms::DOOR_WIDTH;
@@ -13,7 +14,6 @@ use na::{Isometry2, Vector2};
 use nc::{query, query::Contact, shape::Compound};
 use num::abs;
 use rand::distributions::{Distribution, Normal};
-use rand::thread_rng;
 use rand::Rng;
 use std::f32::consts::PI;
 
@@ -25,29 +25,29 @@ pub struct CompoundRoomMaker {
 }
 
 impl CompoundRoomMaker {
-  pub fn new(starter_rect: GridRect) -> CompoundRoomMaker {
-    let rooms = vec![CompoundRoomMaker::grid_room_to_room(&starter_rect, None).unwrap()];
-    CompoundRoomMaker { rects: vec![starter_rect], rooms }
+  pub fn new(starter_rect: GridRect) -> Result<CompoundRoomMaker, GenError> {
+    let rooms = vec![CompoundRoomMaker::grid_room_to_room(&starter_rect, None)?];
+    Ok(CompoundRoomMaker { rects: vec![starter_rect], rooms })
   }
   /// Creates a new group of `Room`s that all touch each-other. This is done in a gridded space
   /// to allow snapping rooms together precisely. Parameters are max/min sizes for an individual
   /// room within the compound room.
-  pub fn rand_compound_room(
+  pub fn rand_compound_room<R: Rng>(
+    rng: &mut R,
     (x_min, x_max): (Meters, Meters),
     (y_min, y_max): (Meters, Meters),
-  ) -> Result<CompoundRoom, ()> {
-    let mut rng = thread_rng();
+  ) -> Result<CompoundRoom, GenError> {
     // The initial room
-    let starter = CompoundRoomMaker::rand_grid_room();
+    let starter = CompoundRoomMaker::rand_grid_room(rng);
 
-    let mut maker = CompoundRoomMaker::new(starter);
+    let mut maker = CompoundRoomMaker::new(starter)?;
 
     let num_extensions = rng.gen_range(1, 5);
 
     for _ in 0..num_extensions {
       let exit_angle = rng.gen_range(0.0, PI * 2.0);
-      let new = CompoundRoomMaker::rand_grid_room();
-      let contact = maker.snap_to_existing_rooms(&new, exit_angle);
+      let new = CompoundRoomMaker::rand_grid_room(rng);
+      let contact = maker.snap_to_existing_rooms(&new, exit_angle)?;
       let moved_room = maker.rects.last().unwrap();
       debug!("ROOM: {:?}\nCONTACT: {:?}", moved_room, contact);
       let midpt = maker.find_wall_overlap_midpoint(&contact)?;
@@ -91,8 +91,7 @@ impl CompoundRoomMaker {
         if let Some((_, r)) = most_extreme_wall_and_room {
           r.add_door_to_wall(*d)
         } else {
-          warn!("Couldn't find an extreme wall to punch an outside door in");
-          return Err(());
+          return Err(GenError::NoExtremeWall { side: *d });
         }
       }
     };
@@ -113,7 +112,7 @@ impl CompoundRoomMaker {
   /// Returns the midpoint of the overlap of the walls
   ///
   /// Can fail if overlap area isn't big enough, or contact point doesn't include two walls
-  fn find_wall_overlap_midpoint(&self, contact: &Contact<Meters>) -> Result<Point, ()> {
+  fn find_wall_overlap_midpoint(&self, contact: &Contact<Meters>) -> Result<Point, GenError> {
     let all_walls: Vec<(CenteredRect, Direction)> = self
       .rects
       .iter()
@@ -142,8 +141,7 @@ impl CompoundRoomMaker {
       .collect();
     debug!("target walls: {:?}", target_walls);
     if target_walls.len() != 2 {
-      debug!("Couldn't find two walls that worked when punching door in compound room");
-      return Err(());
+      return Err(GenError::MisalignedContact { at: contact.world1, walls: target_walls.len() });
     };
     // Find the overlap of the target walls
     let (w1, d) = target_walls[0];
@@ -162,8 +160,7 @@ impl CompoundRoomMaker {
     };
     let size = abs(hi_end - low_end);
     if size < DOOR_WIDTH + WALL_THICKNESS * 2.0 {
-      debug!("Wall overlap not big enough to punch door");
-      return Err(());
+      return Err(GenError::InsufficientWallOverlap { at: contact.world1, overlap: size });
     }
     let midpoint = (hi_end + low_end) / 2.0;
     debug!("lo {:?} hi {:?}", low_end, hi_end);
@@ -176,8 +173,13 @@ impl CompoundRoomMaker {
 
   /// Given some existing rooms (clustered around the origin) and a new room (at the origin),
   /// move the new room away from the origin at the exit angle until flush with the edge of one
-  /// of the existing rooms. Rooms can't be bigger than 1000 meters in any direction.
-  fn snap_to_existing_rooms(&mut self, new_room: &GridRect, exit_angle: f32) -> Contact<Meters> {
+  /// of the existing rooms. Rooms can't be bigger than 1000 meters in any direction. Fails if the
+  /// new room never touched any of the existing ones.
+  fn snap_to_existing_rooms(
+    &mut self,
+    new_room: &GridRect,
+    exit_angle: f32,
+  ) -> Result<Contact<Meters>, GenError> {
     let walk_vec: Vector2<Meters> = PolarVec::new(1000.0, exit_angle).into();
     let walk_to_pt = IntPoint::new(walk_vec.x as i32, walk_vec.y as i32);
     let walk_list = walk_grid(IntPoint::new(0, 0), walk_to_pt);
@@ -216,18 +218,17 @@ impl CompoundRoomMaker {
     let intified: Vector2<i32> =
       Vector2::new(last_pt.translation.vector.x as i32, last_pt.translation.vector.y as i32);
     self.rects.push(GridRect::new(orig_w, orig_h, IntPoint::from(intified)));
-    last_contact.unwrap()
+    last_contact.ok_or(GenError::NoContactFound)
   }
 
   /// Creates a randomly sized grid room with top-left corner at origin
-  fn rand_grid_room() -> GridRect {
+  fn rand_grid_room<R: Rng>(rng: &mut R) -> GridRect {
     // TODO: Configurable sizing parameters
-    let mut rng = thread_rng();
     let (room_w, room_h) = {
       let sizer = Normal::new(5.0, 3.0);
       let mut get_siz = || {
         sizer
-          .sample(&mut rng)
+          .sample(rng)
           .abs()
           // Rooms need to be big enough to fit a door, and a little wiggle room
           .max((DOOR_WIDTH * 2.0 + 0.2).into())
@@ -238,9 +239,9 @@ impl CompoundRoomMaker {
     GridRect::new(room_w, room_h, IntPoint::new(0, 0))
   }
 
-  fn grid_room_to_room(gr: &GridRect, door: Option<Door>) -> Result<Room, ()> {
+  fn grid_room_to_room(gr: &GridRect, door: Option<Door>) -> Result<Room, GenError> {
     let nc: Point = na::convert(gr.center());
-    Room::new(nc, gr.width as f32, gr.height as f32, door, true)
+    Room::new(nc, gr.width as f32, gr.height as f32, door.into_iter().collect(), true)
   }
 }
 
@@ -254,38 +255,49 @@ mod test {
 
   #[test]
   fn test_simple_snap() {
-    let mut maker = CompoundRoomMaker::new(GridRect::new(1, 1, IntPoint::new(0, 0)));
+    let mut maker = CompoundRoomMaker::new(GridRect::new(1, 1, IntPoint::new(0, 0))).unwrap();
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, 0.0);
+    maker.snap_to_existing_rooms(&new, 0.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(1, 0)), *maker.rects.last().unwrap());
   }
 
   #[test]
   fn test_series_of_snaps() {
-    let mut maker = CompoundRoomMaker::new(GridRect::new(1, 1, IntPoint::new(0, 0)));
+    let mut maker = CompoundRoomMaker::new(GridRect::new(1, 1, IntPoint::new(0, 0))).unwrap();
     // Up
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, PI / 2.0);
+    maker.snap_to_existing_rooms(&new, PI / 2.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(0, 1)), *maker.rects.last().unwrap());
     // Right
     let new = GridRect::new(4, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, 0.0);
+    maker.snap_to_existing_rooms(&new, 0.0).unwrap();
     assert_eq!(GridRect::new(4, 1, IntPoint::new(1, 0)), *maker.rects.last().unwrap());
     // Up again, two more times
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, PI / 2.0);
+    maker.snap_to_existing_rooms(&new, PI / 2.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(0, 2)), *maker.rects.last().unwrap());
 
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, PI / 2.0);
+    maker.snap_to_existing_rooms(&new, PI / 2.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(0, 3)), *maker.rects.last().unwrap());
     // Diagonally up and right
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, PI / 4.0);
+    maker.snap_to_existing_rooms(&new, PI / 4.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(1, 1)), *maker.rects.last().unwrap());
     // Right again
     let new = GridRect::new(1, 1, IntPoint::new(0, 0));
-    maker.snap_to_existing_rooms(&new, 0.0);
+    maker.snap_to_existing_rooms(&new, 0.0).unwrap();
     assert_eq!(GridRect::new(1, 1, IntPoint::new(5, 0)), *maker.rects.last().unwrap());
   }
+
+  #[test]
+  fn test_small_contacts_cant_fit_doors() {
+    let mut maker = CompoundRoomMaker::new(GridRect::new(1, 1, IntPoint::new(0, 0))).unwrap();
+    let new = GridRect::new(1, 1, IntPoint::new(0, 0));
+    let contact = maker.snap_to_existing_rooms(&new, 0.0).unwrap();
+    match maker.find_wall_overlap_midpoint(&contact) {
+      Err(GenError::InsufficientWallOverlap { .. }) | Err(GenError::MisalignedContact { .. }) => {}
+      other => panic!("Expected a failure, got {:?}", other),
+    }
+  }
 }
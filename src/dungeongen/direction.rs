@@ -64,6 +64,17 @@ impl Direction {
     }
   }
 
+  /// The compass direction closest to pointing along `v`
+  pub fn nearest_compass(v: (f32, f32)) -> Direction {
+    *Direction::compass()
+      .iter()
+      .max_by(|a, b| {
+        let dot = |d: &Direction| d.to_tup().0 * v.0 + d.to_tup().1 * v.1;
+        dot(a).partial_cmp(&dot(b)).unwrap()
+      })
+      .unwrap()
+  }
+
   pub fn opposite(self) -> Direction {
     match self {
       Direction::North => Direction::South,
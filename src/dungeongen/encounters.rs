@@ -0,0 +1,201 @@
+use super::tables::{parse_range, parse_sections, weighted_pick};
+use crate::agents::monster::MonsterKind;
+use crate::collision::{CollGroups, Collidable, CollidableType, Shape2D};
+use crate::dungeongen::rooms::RoomKind;
+use crate::util::{Meters, Point};
+use na::Isometry2;
+use nc::shape::{Ball, ShapeHandle};
+use nc::world::CollisionGroups;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+static ENCOUNTER_CONFIG: &str = include_str!("encounters.cfg");
+
+lazy_static! {
+  static ref ENCOUNTER_TABLES: EncounterTables =
+    EncounterTables::parse(ENCOUNTER_CONFIG).expect("The encounter tables are broken");
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct EncounterEntry {
+  kind: MonsterKind,
+  weight: f32,
+  /// Shallowest and deepest levels this shows up on
+  depths: (u32, u32),
+  /// Fewest and most monsters that show up together
+  pack_size: (usize, usize),
+}
+
+/// Monsters that show up together, which haven't been given anywhere to be yet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pack {
+  pub kind: MonsterKind,
+  pub size: usize,
+}
+
+/// Weighted monsters that can turn up in one kind of place
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncounterTable {
+  /// Fewest and most packs for each place that uses the table
+  packs: (usize, usize),
+  entries: Vec<EncounterEntry>,
+}
+
+impl EncounterTable {
+  /// Rolls up however many packs the table says to, from the entries allowed at `depth`
+  pub fn roll<R: Rng>(&self, rng: &mut R, depth: u32) -> Vec<Pack> {
+    let weights: Vec<f32> = self
+      .entries
+      .iter()
+      .map(|e| if e.depths.0 <= depth && depth <= e.depths.1 { e.weight } else { 0.0 })
+      .collect();
+    let mut packs = vec![];
+    for _ in 0..rng.gen_range(self.packs.0, self.packs.1 + 1) {
+      if let Some(ix) = weighted_pick(rng, &weights) {
+        let (kind, (min, max)) = (self.entries[ix].kind, self.entries[ix].pack_size);
+        packs.push(Pack { kind, size: rng.gen_range(min, max + 1) });
+      }
+    }
+    packs
+  }
+}
+
+/// Every encounter table, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncounterTables {
+  tables: BTreeMap<String, EncounterTable>,
+}
+
+impl EncounterTables {
+  /// Reads tables from sections like `[barracks 1-2]` followed by entries like
+  /// `goblin = 6 1+ 2-4`. Blank lines and lines starting with `#` are skipped.
+  pub fn parse(config: &str) -> Result<EncounterTables, String> {
+    let new_table = |packs| EncounterTable { packs, entries: vec![] };
+    let tables = parse_sections(config, new_table, |table, name, rest| {
+      let kind = MonsterKind::from_name(name).ok_or_else(|| format!("unknown monster {}", name))?;
+      let (weight, depths, pack_size) = match rest.split_whitespace().collect::<Vec<&str>>()[..] {
+        [weight, depths, pack_size] => (weight, depths, pack_size),
+        _ => return Err("expected monster = weight depths pack size".to_string()),
+      };
+      table.entries.push(EncounterEntry {
+        kind,
+        weight: weight.parse().map_err(|_| format!("bad weight {}", weight))?,
+        depths: parse_depths(depths).ok_or_else(|| format!("bad depths {}", depths))?,
+        pack_size: parse_range(pack_size).ok_or_else(|| format!("bad pack size {}", pack_size))?,
+      });
+      Ok(())
+    })?;
+    Ok(EncounterTables { tables })
+  }
+
+  pub fn get(&self, name: &str) -> Option<&EncounterTable> {
+    self.tables.get(name)
+  }
+
+  /// The table for rooms of the given kind
+  pub fn for_room(kind: RoomKind) -> &'static EncounterTable {
+    let name = format!("{:?}", kind).to_lowercase();
+    ENCOUNTER_TABLES.get(&name).unwrap_or_else(|| panic!("No encounter table for {}", name))
+  }
+
+  /// The table for the open cave outside the rooms
+  pub fn cave() -> &'static EncounterTable {
+    ENCOUNTER_TABLES.get("cave").expect("No encounter table for the cave")
+  }
+}
+
+/// Reads depths like `1-3`, or `3+` for level 3 and every level below it
+fn parse_depths(depths: &str) -> Option<(u32, u32)> {
+  match depths.strip_suffix('+') {
+    Some(min) => min.parse().ok().map(|min| (min, u32::MAX)),
+    None => parse_range(depths),
+  }
+}
+
+/// A monster that level generation placed. Written out it reads `kind x y pack`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Spawn {
+  pub kind: MonsterKind,
+  pub pos: Point,
+  /// Monsters that spawned together share a pack number
+  pub pack: usize,
+}
+
+impl Collidable for Spawn {
+  fn location(&self) -> Isometry2<Meters> {
+    Isometry2::new(self.pos.coords, na::zero())
+  }
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(Ball::new(self.kind.radius()))
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    CollGroups::walker_cg()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::Monster
+  }
+}
+
+impl fmt::Display for Spawn {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} {} {}", self.kind.name(), self.pos.x, self.pos.y, self.pack)
+  }
+}
+
+impl FromStr for Spawn {
+  type Err = String;
+
+  fn from_str(line: &str) -> Result<Spawn, String> {
+    let bad = || format!("bad spawn {}", line);
+    match line.split_whitespace().collect::<Vec<&str>>()[..] {
+      [kind, x, y, pack] => Ok(Spawn {
+        kind: MonsterKind::from_name(kind).ok_or_else(bad)?,
+        pos: Point::new(x.parse().map_err(|_| bad())?, y.parse().map_err(|_| bad())?),
+        pack: pack.parse().map_err(|_| bad())?,
+      }),
+      _ => Err(bad()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use rand::prng::XorShiftRng;
+  use rand::SeedableRng;
+
+  #[test]
+  fn test_every_room_has_a_table() {
+    for &kind in RoomKind::all() {
+      EncounterTables::for_room(kind);
+    }
+    EncounterTables::cave();
+  }
+
+  #[test]
+  fn test_bad_tables() {
+    assert!(EncounterTables::parse("[a 0-1]\nrat = 1 1+").unwrap_err().contains("line 2"));
+    assert!(EncounterTables::parse("[a 0-1]\ndragon = 1 1+ 1-1").is_err());
+    assert!(EncounterTables::parse("[a 0-1]\nrat = 1 deep 1-1").is_err());
+    assert!(EncounterTables::parse("[a 0-1]\nrat = 1 1+ 3-1").is_err());
+  }
+
+  #[test]
+  fn test_depth_ranges() {
+    let tables = EncounterTables::parse("[t 4-4]\nrat = 1 1-2 2-2\nogre = 1 3+ 1-1").unwrap();
+    let table = tables.get("t").unwrap();
+    let mut rng = XorShiftRng::seed_from_u64(3);
+    assert_eq!(table.roll(&mut rng, 2), vec![Pack { kind: MonsterKind::Rat, size: 2 }; 4]);
+    assert_eq!(table.roll(&mut rng, 30), vec![Pack { kind: MonsterKind::Ogre, size: 1 }; 4]);
+  }
+
+  #[test]
+  fn test_spawns_round_trip() {
+    let spawn = Spawn { kind: MonsterKind::Skeleton, pos: Point::new(12.5, -3.25), pack: 4 };
+    assert_eq!(spawn.to_string().parse(), Ok(spawn));
+    assert!("skeleton 1 2".parse::<Spawn>().is_err());
+    assert!("dragon 1 2 3".parse::<Spawn>().is_err());
+  }
+}
@@ -0,0 +1,152 @@
+use crate::collision::{CollGroups, Collidable, CollidableType, CollisionRect, Shape2D};
+use crate::dungeongen::level::WALL_THICKNESS;
+use crate::dungeongen::rooms::RoomKind;
+use crate::util::geom::{CenterOriginRect, CenteredRect};
+use crate::util::{Meters, Point};
+use ggez::graphics::{draw, Color, DrawMode, DrawParam, Mesh, Rect};
+use ggez::{Context, GameResult};
+use na;
+use na::{Isometry2, Vector2};
+use nc::shape::ShapeHandle;
+use nc::world::CollisionGroups;
+use rand::Rng;
+
+/// Rooms get roughly one piece of furniture tried per this much floor space
+static FLOOR_PER_PIECE: Meters = 6.0;
+static MAX_PIECES_PER_ROOM: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FurnitureKind {
+  Crate,
+  Barrel,
+  Altar,
+  Bed,
+  Chest,
+  Bookshelf,
+  Table,
+}
+
+impl FurnitureKind {
+  /// (width, height) of this kind of furniture
+  pub fn dims(self) -> (Meters, Meters) {
+    match self {
+      FurnitureKind::Crate => (0.8, 0.8),
+      FurnitureKind::Barrel => (0.6, 0.6),
+      FurnitureKind::Altar => (1.6, 0.9),
+      FurnitureKind::Bed => (0.9, 1.9),
+      FurnitureKind::Chest => (0.9, 0.6),
+      FurnitureKind::Bookshelf => (1.8, 0.4),
+      FurnitureKind::Table => (1.4, 0.9),
+    }
+  }
+
+  pub fn color(self) -> Color {
+    match self {
+      FurnitureKind::Crate | FurnitureKind::Barrel => Color::new(0.55, 0.4, 0.2, 1.0),
+      FurnitureKind::Altar => Color::new(0.85, 0.85, 0.9, 1.0),
+      FurnitureKind::Bed => Color::new(0.6, 0.2, 0.2, 1.0),
+      FurnitureKind::Chest => Color::new(0.85, 0.7, 0.1, 1.0),
+      FurnitureKind::Bookshelf | FurnitureKind::Table => Color::new(0.4, 0.25, 0.1, 1.0),
+    }
+  }
+
+  /// The kinds of furniture that belong in a room of the given kind
+  pub fn for_room(kind: RoomKind) -> &'static [FurnitureKind] {
+    match kind {
+      RoomKind::Storage => &[FurnitureKind::Crate, FurnitureKind::Barrel],
+      RoomKind::Shrine => &[FurnitureKind::Altar],
+      RoomKind::Barracks => &[FurnitureKind::Bed, FurnitureKind::Chest],
+      RoomKind::Vault => &[FurnitureKind::Chest],
+      RoomKind::Library => &[FurnitureKind::Bookshelf, FurnitureKind::Table],
+    }
+  }
+}
+
+/// A prop sitting on the floor of a room. Nothing can walk through furniture.
+#[derive(Debug, Clone, CenterOriginRect, PartialEq)]
+pub struct Furniture {
+  pub kind: FurnitureKind,
+  cr: CenteredRect,
+}
+
+impl Furniture {
+  pub fn new(kind: FurnitureKind, center: Point) -> Furniture {
+    let (width, height) = kind.dims();
+    Furniture { kind, cr: CenteredRect::new(center, width, height) }
+  }
+
+  /// Proposes furniture for a room of the given kind, randomly scattered inside `room`. The pieces
+  /// may overlap each other or the room's floormats, so they still need to be checked.
+  pub fn rand_layout<R: Rng>(
+    rng: &mut R,
+    kind: RoomKind,
+    room: &CenterOriginRect,
+  ) -> Vec<Furniture> {
+    let area = room.width() * room.height();
+    let count = ((area / FLOOR_PER_PIECE).ceil() as usize).min(MAX_PIECES_PER_ROOM);
+    (0..count)
+      .filter_map(|_| {
+        let fkind = *rng.choose(FurnitureKind::for_room(kind)).unwrap();
+        let (w, h) = fkind.dims();
+        // Keep a gap between the furniture and the walls
+        let x_room = (room.width() - w) / 2.0 - WALL_THICKNESS;
+        let y_room = (room.height() - h) / 2.0 - WALL_THICKNESS;
+        if x_room <= 0.0 || y_room <= 0.0 {
+          return None;
+        }
+        let center = room.center()
+          + Vector2::new(rng.gen_range(-x_room, x_room), rng.gen_range(-y_room, y_room));
+        Some(Furniture::new(fkind, center))
+      })
+      .collect()
+  }
+
+  pub fn corners(&self) -> [Point; 4] {
+    (self as &CenterOriginRect).corners()
+  }
+
+  pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+    let r: Rect = (self as &CenterOriginRect).into();
+    let r = Mesh::new_rectangle(ctx, DrawMode::fill(), r, self.kind.color())?;
+    draw(ctx, &r, DrawParam::new())
+  }
+}
+
+impl Collidable for Furniture {
+  fn location(&self) -> Isometry2<Meters> {
+    Isometry2::new(self.center().coords, na::zero())
+  }
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(CollisionRect::new(Vector2::new(self.width() / 2.0, self.height() / 2.0)))
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    CollGroups::wall_cg()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::Furniture
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::level_rng::level_rng;
+
+  #[test]
+  fn test_layout_stays_inside_room() {
+    let room = CenteredRect::new(Point::new(3.0, -2.0), 7.0, 5.0);
+    let mut rng = level_rng(1);
+    for _ in 0..20 {
+      for piece in Furniture::rand_layout(&mut rng, RoomKind::Library, &room) {
+        assert!(piece.corners().iter().all(|&c| (&room as &CenterOriginRect).contains(c)));
+        assert!(FurnitureKind::for_room(RoomKind::Library).contains(&piece.kind));
+      }
+    }
+  }
+
+  #[test]
+  fn test_tiny_rooms_stay_empty() {
+    let room = CenteredRect::new(Point::new(0.0, 0.0), 1.0, 1.0);
+    assert!(Furniture::rand_layout(&mut level_rng(1), RoomKind::Shrine, &room).is_empty());
+  }
+}
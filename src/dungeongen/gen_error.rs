@@ -0,0 +1,132 @@
+use crate::dungeongen::direction::Direction;
+use crate::util::{Meters, Point};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Why some piece of the level couldn't be generated
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GenError {
+  /// A door is too wide for its wall, or overlaps another door on the same side
+  DoorObliteratesWall { side: Direction },
+  /// An edge of a shaped room is too short to fit a door in
+  EdgeTooShort { edge: usize, length: Meters },
+  /// A door was asked for in an edge the outline doesn't have
+  NoSuchEdge { edge: usize, edges: usize },
+  /// An outline has too few corners to be a room or obstacle
+  BadOutline { corners: usize },
+  /// Two rooms of a compound room touch, but don't share enough wall to fit a door
+  InsufficientWallOverlap { at: Point, overlap: Meters },
+  /// Rooms of a compound room touch, but not along exactly two walls
+  MisalignedContact { at: Point, walls: usize },
+  /// A new room of a compound room never touched any of the existing ones
+  NoContactFound,
+  /// No room of a compound room had a wall on the given side to punch an outside door in
+  NoExtremeWall { side: Direction },
+  /// Room placement ran out of attempts and settled for fewer rooms than it wanted
+  OutOfRoomAttempts { placed: usize },
+  /// The cave CA didn't leave any cave big enough to keep
+  CaveDiedOut { attempt: usize },
+  /// A blobstacle's sim died out or didn't finish growing in time
+  BlobDidNotGrow { ticks: usize },
+}
+
+impl GenError {
+  /// Short name for this kind of failure, without any of the context
+  pub fn kind(&self) -> &'static str {
+    match self {
+      GenError::DoorObliteratesWall { .. } => "door obliterates wall",
+      GenError::EdgeTooShort { .. } => "edge too short",
+      GenError::NoSuchEdge { .. } => "no such edge",
+      GenError::BadOutline { .. } => "bad outline",
+      GenError::InsufficientWallOverlap { .. } => "insufficient wall overlap",
+      GenError::MisalignedContact { .. } => "misaligned contact",
+      GenError::NoContactFound => "no contact found",
+      GenError::NoExtremeWall { .. } => "no extreme wall",
+      GenError::OutOfRoomAttempts { .. } => "out of room attempts",
+      GenError::CaveDiedOut { .. } => "cave died out",
+      GenError::BlobDidNotGrow { .. } => "blob did not grow",
+    }
+  }
+}
+
+impl fmt::Display for GenError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GenError::DoorObliteratesWall { side } => {
+        write!(f, "door would obliterate the {:?} wall", side)
+      }
+      GenError::EdgeTooShort { edge, length } => {
+        write!(f, "edge {} is {}m long, too short for a door", edge, length)
+      }
+      GenError::NoSuchEdge { edge, edges } => {
+        write!(f, "no edge {} on an outline with {} edges", edge, edges)
+      }
+      GenError::BadOutline { corners } => write!(f, "outline only has {} corners", corners),
+      GenError::InsufficientWallOverlap { at, overlap } => {
+        write!(f, "walls at {} only overlap by {}m", at, overlap)
+      }
+      GenError::MisalignedContact { at, walls } => {
+        write!(f, "contact at {} touches {} walls instead of 2", at, walls)
+      }
+      GenError::NoContactFound => write!(f, "new room never touched the others"),
+      GenError::NoExtremeWall { side } => write!(f, "no {:?} wall for an outside door", side),
+      GenError::OutOfRoomAttempts { placed } => {
+        write!(f, "ran out of attempts after placing {} rooms", placed)
+      }
+      GenError::CaveDiedOut { attempt } => write!(f, "cave {} died out", attempt),
+      GenError::BlobDidNotGrow { ticks } => write!(f, "blob didn't grow after {} ticks", ticks),
+    }
+  }
+}
+
+impl std::error::Error for GenError {}
+
+/// Tally of the ways level generation has failed, so it's clear what is causing retries
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GenStats {
+  failures: BTreeMap<&'static str, usize>,
+}
+
+impl GenStats {
+  pub fn record(&mut self, err: &GenError) {
+    debug!("Generation failed: {}", err);
+    *self.failures.entry(err.kind()).or_insert(0) += 1;
+  }
+
+  /// How many times a failure of the given kind has happened
+  pub fn count(&self, kind: &str) -> usize {
+    self.failures.get(kind).cloned().unwrap_or(0)
+  }
+
+  pub fn total(&self) -> usize {
+    self.failures.values().sum()
+  }
+}
+
+impl fmt::Display for GenStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} generation failures", self.total())?;
+    for (kind, count) in &self.failures {
+      write!(f, "\n  {}: {}", kind, count)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_stats_count_each_kind() {
+    let mut stats = GenStats::default();
+    stats.record(&GenError::NoContactFound);
+    stats.record(&GenError::NoExtremeWall { side: Direction::North });
+    stats.record(&GenError::NoExtremeWall { side: Direction::East });
+    assert_eq!(stats.count("no extreme wall"), 2);
+    assert_eq!(stats.count("no contact found"), 1);
+    assert_eq!(stats.count("bad outline"), 0);
+    assert_eq!(stats.total(), 3);
+    assert!(stats.to_string().contains("no extreme wall: 2"));
+  }
+}
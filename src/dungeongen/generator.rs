@@ -0,0 +1,160 @@
+use crate::dungeongen::level::{Level, LevelSnapshot};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Progress snapshots are sent at most this often, which is about once a frame
+static SNAPSHOT_INTERVAL: Duration = Duration::from_millis(16);
+/// When generation is limited to a few ticks per frame so it can be watched, it sleeps this long
+/// between each frame's worth of ticks
+static SLOW_TICK: Duration = Duration::from_millis(16);
+
+/// What the generator thread sends back
+pub enum GenMsg {
+  /// How the level is looking so far
+  Progress(LevelSnapshot),
+  /// The level is done. Always the last message.
+  Finished(Box<Level>),
+}
+
+/// Generates a level on a worker thread, streaming snapshots of its progress back
+pub struct LevelGenerator {
+  rx: Receiver<GenMsg>,
+  cancelled: Arc<AtomicBool>,
+  finish_now: Arc<AtomicBool>,
+  /// Ticks to run per frame, or 0 to run flat out
+  ticks_per_frame: Arc<AtomicUsize>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl LevelGenerator {
+  /// Starts generating `level` in the background, running `ticks_per_frame` generation ticks each
+  /// frame or as many as it can if that's `None`
+  pub fn spawn(level: Level, ticks_per_frame: Option<usize>) -> LevelGenerator {
+    let (tx, rx) = channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let finish_now = Arc::new(AtomicBool::new(false));
+    let ticks_per_frame = Arc::new(AtomicUsize::new(ticks_per_frame.unwrap_or(0)));
+    let flags = (cancelled.clone(), finish_now.clone(), ticks_per_frame.clone());
+    let handle = thread::Builder::new()
+      .name("level-gen".to_string())
+      .spawn(move || generate(level, &tx, flags))
+      .expect("Couldn't start the level generation thread");
+    LevelGenerator { rx, cancelled, finish_now, ticks_per_frame, handle: Some(handle) }
+  }
+
+  /// The newest message from the generator, if there have been any since the last poll. Older
+  /// snapshots are skipped over.
+  pub fn poll(&self) -> Option<GenMsg> {
+    self.rx.try_iter().last()
+  }
+
+  /// Stops generating and keeps the level as it is right now
+  pub fn finish_now(&self) {
+    self.finish_now.store(true, Ordering::Relaxed);
+  }
+
+  /// Changes how many ticks are run each frame. `None` runs generation flat out.
+  pub fn set_speed(&self, ticks_per_frame: Option<usize>) {
+    self.ticks_per_frame.store(ticks_per_frame.unwrap_or(0), Ordering::Relaxed);
+  }
+
+  /// Stops generating and throws the level away. Waits for the worker to stop.
+  pub fn cancel(&mut self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+    if let Some(handle) = self.handle.take() {
+      if handle.join().is_err() {
+        warn!("Level generation thread panicked");
+      }
+    }
+  }
+}
+
+impl Drop for LevelGenerator {
+  fn drop(&mut self) {
+    self.cancel();
+  }
+}
+
+fn generate(
+  mut level: Level,
+  tx: &Sender<GenMsg>,
+  (cancelled, finish_now, ticks_per_frame): (Arc<AtomicBool>, Arc<AtomicBool>, Arc<AtomicUsize>),
+) {
+  let mut last_sent = Instant::now();
+  let mut ticks_this_frame = 0;
+  while !level.level_gen_finished {
+    if cancelled.load(Ordering::Relaxed) {
+      return;
+    }
+    if finish_now.load(Ordering::Relaxed) {
+      level.level_gen_finished = true;
+      break;
+    }
+    level.tick_level_gen();
+    ticks_this_frame += 1;
+    let limit = ticks_per_frame.load(Ordering::Relaxed);
+    if limit > 0 && ticks_this_frame >= limit {
+      thread::sleep(SLOW_TICK);
+      ticks_this_frame = 0;
+    }
+    if last_sent.elapsed() >= SNAPSHOT_INTERVAL {
+      // Nobody is listening any more, so there's no point carrying on
+      if tx.send(GenMsg::Progress(level.snapshot())).is_err() {
+        return;
+      }
+      last_sent = Instant::now();
+    }
+  }
+  if !cancelled.load(Ordering::Relaxed) {
+    let _ = tx.send(GenMsg::Finished(Box::new(level)));
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate timebomb;
+
+  use self::timebomb::timeout_ms;
+  use super::*;
+
+  #[test]
+  fn test_generates_in_background() {
+    timeout_ms(
+      || {
+        let gen = LevelGenerator::spawn(Level::new(), None);
+        loop {
+          match gen.rx.recv().unwrap() {
+            GenMsg::Progress(_) => continue,
+            GenMsg::Finished(level) => {
+              assert!(level.level_gen_finished);
+              break;
+            }
+          }
+        }
+      },
+      20000,
+    )
+  }
+
+  #[test]
+  fn test_cancel_stops_worker() {
+    timeout_ms(
+      || {
+        // Slow mode would take a long time to finish on its own
+        let mut gen = LevelGenerator::spawn(Level::new(), Some(1));
+        gen.cancel();
+        assert!(gen.handle.is_none());
+        // Nothing but progress could have been sent before the worker noticed
+        assert!(gen.rx.try_iter().all(|m| match m {
+          GenMsg::Progress(_) => true,
+          GenMsg::Finished(_) => false,
+        }));
+      },
+      5000,
+    )
+  }
+}
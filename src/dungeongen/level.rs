@@ -1,27 +1,121 @@
-use super::blobstacle::Blobstacle;
-use super::ca_simulator::CASim;
+use super::blobstacle::{BlobKind, Blobstacle};
+use super::ca_simulator::{BoundaryTracer, CASim};
 use super::direction::Direction;
-use super::rooms::Room;
+use super::encounters::{EncounterTables, Pack, Spawn};
+use super::furniture::Furniture;
+use super::gen_error::{GenError, GenStats};
+use super::level_rng::{level_rng, rand_seed, LevelRng};
+use super::level_stats::LevelStats;
+use super::loot::LootTables;
+use super::recording::{write_gif, GenFrame, GenRecording, Rejection};
+use super::rooms::{Door, Room, RoomKind};
+use super::terrain::{Terrain, TerrainMap};
 use crate::collision::{
-  new_collw, CollW, Collidable, CollidableDat, CollidableType, GameObjRegistrar,
+  new_collw, CollGroups, CollW, Collidable, CollidableDat, CollidableType, GameObjRegistrar,
+  Shape2D,
 };
 use crate::dungeongen::compound_room::CompoundRoomMaker;
-use crate::util::context_help::ContextHelp;
+use crate::items::floor_item::FloorItem;
 use crate::util::geom::CenterOriginRect;
-use crate::util::geom::CenteredRect;
-use crate::util::{Meters, Point};
+use crate::util::geom::{origin, triangulate_ring, CenteredRect, PolygonWithHoles};
+use crate::util::{Meters, Point, Vec2};
 use ggez::graphics;
-use ggez::graphics::{Color, DrawParam};
+use ggez::graphics::{Color, DrawMode, DrawParam, Mesh};
 use ggez::{Context, GameResult};
+use na::Isometry2;
 use nc::bounding_volume::AABB;
-use nc::shape::Polyline;
-use nc::world::CollisionObjectHandle;
+use nc::shape::{Compound, ConvexPolygon, Polyline, ShapeHandle};
+use nc::world::{CollisionGroups, CollisionObjectHandle};
 use num::{FromPrimitive, ToPrimitive};
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub type Wall = CenteredRect;
 
 pub static WALL_THICKNESS: Meters = 0.2;
+/// Width and height of every level
+// TODO: Right now the dimensions of this sim need to have the same ratio as the screen or it gets
+// squished. It's also bad at taking up most of the available screen space.
+static LEVEL_DIMS: (Meters, Meters) = (50.0, 50.0);
+/// Standalone rooms get at most this many doors
+static MAX_ROOM_DOORS: usize = 3;
+/// How many rooms each level tries to have
+static TARGET_ROOMS: usize = 10;
+/// Room placement settles for fewer rooms after this many failures in a row
+static MAX_ROOM_ATTEMPTS: usize = 200;
+/// A cave that dies out gets regrown from new seeds at most this many times
+static MAX_CAVE_ATTEMPTS: usize = 5;
+/// How many obstacles each level tries to have
+static NUM_OBSTACLES: usize = 6;
+/// Obstacle placement gives up after this many rejected blobs
+static MAX_OBSTACLE_ATTEMPTS: usize = 60;
+/// The debug overlay shows this many of the latest rejected placements
+static RECENT_REJECTIONS: usize = 8;
+/// Loot luck gained for each level deeper than the first
+static DEPTH_LUCK: f32 = 0.5;
+/// Loot luck of the compound room farthest from the middle of the level. Nearer ones get less.
+static COMPOUND_LUCK: f32 = 2.0;
+/// Nothing spawns this close to the middle of the level, where the player starts
+static SAFE_RADIUS: Meters = 8.0;
+/// The rest of a pack spawns at most this far from its leader along each axis
+static PACK_SPREAD: Meters = 2.0;
+/// Tries at finding a clear spot for each monster before leaving it out
+static MAX_SPAWN_ATTEMPTS: usize = 10;
+
+/// A hole in the cave floor, like a pillar or a chasm, outlined in level space. Nothing gets
+/// through these, or fits inside them.
+pub struct CaveHole {
+  pub ring: Vec<Point>,
+}
+
+impl Collidable for CaveHole {
+  fn location(&self) -> Isometry2<Meters> {
+    origin()
+  }
+  fn shape(&self) -> Shape2D {
+    // Solid all the way through, so things wholly inside still touch it
+    let pieces: Vec<(Isometry2<Meters>, Shape2D)> = triangulate_ring(&self.ring)
+      .iter()
+      .filter_map(|tri| ConvexPolygon::try_from_points(tri))
+      .map(|tri| (origin(), ShapeHandle::new(tri)))
+      .collect();
+    if pieces.is_empty() {
+      return ShapeHandle::new(Polyline::new(self.ring.clone(), None));
+    }
+    ShapeHandle::new(Compound::new(pieces))
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    CollGroups::wall_cg()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::CaveHole
+  }
+}
+
+/// The outer edge of a cave, in level space. Keeps everything inside the cave.
+pub struct CaveWall {
+  pub ring: Vec<Point>,
+}
+
+impl Collidable for CaveWall {
+  fn location(&self) -> Isometry2<Meters> {
+    origin()
+  }
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(Polyline::new(self.ring.clone(), None))
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    CollGroups::wall_cg()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::CaveWall
+  }
+}
 
 /// A level consists of one huge arbitrarily-shaped but enclosed curve, on top
 /// of which we will layer features. This bottom layer represents the shape of
@@ -31,6 +125,16 @@ pub struct Level {
   pub level_gen_finished: bool,
   pub rooms: Vec<Room>,
   pub obstacles: Vec<Blobstacle>,
+  pub furniture: Vec<Furniture>,
+  /// Items left lying around the rooms
+  pub floor_items: Vec<FloorItem>,
+  /// Monsters to put in the level once it's finished
+  pub spawns: Vec<Spawn>,
+  pub cave_holes: Vec<CaveHole>,
+  pub cave_walls: Vec<CaveWall>,
+  pub terrain: TerrainMap,
+  /// Every way generation has failed so far
+  pub gen_stats: GenStats,
   gen_stage: LevelGenStage,
   width: Meters,
   height: Meters,
@@ -38,62 +142,244 @@ pub struct Level {
   /// make sure the stuff being generated isn't colliding with other stuff.
   tmp_collw: CollW,
   tmp_ent_ct: usize,
+  furnished_rooms: usize,
+  looted_rooms: usize,
+  populated_rooms: usize,
+  /// How many packs of monsters have been placed
+  packs: usize,
+  obstacle_attempts: usize,
+  /// Placements thrown away for colliding with something, in the order they were tried
+  rejected: Vec<Rejection>,
+  /// Walls of the cave's bounding box, which keep rooms from being placed too far outside it
+  cave_fence: Vec<Point>,
+  /// Every tick so far, for replaying generation
+  recording: GenRecording,
+  stage_times: BTreeMap<LevelGenStage, Duration>,
+  /// Failed attempts at placing the next room
+  room_attempts: usize,
+  /// How many caves have been grown, counting the current one
+  cave_attempts: usize,
+  /// Generating another level from this seed gives the same level
+  seed: u64,
+  /// Where every random choice made while generating comes from, seeded from `seed`
+  rng: LevelRng,
+  /// How far down the level is, starting at 1. Deeper levels have better loot.
+  depth: u32,
 }
 
-#[derive(PartialEq, Ord, PartialOrd, Eq, FromPrimitive, ToPrimitive)]
-enum LevelGenStage {
+#[derive(Debug, Copy, Clone, PartialEq, Ord, PartialOrd, Eq, FromPrimitive, ToPrimitive)]
+pub enum LevelGenStage {
   CaveSim,
+  Terrain,
   RoomSim,
+  Furnish,
+  Loot,
   PlaceObstacles,
+  Populate,
   Done,
 }
 
 impl Level {
   pub fn new() -> Level {
+    Level::with_seed(rand_seed())
+  }
+
+  /// Creates a level that always generates the same way for the same seed
+  pub fn with_seed(seed: u64) -> Level {
+    Level::with_seed_at_depth(seed, 1)
+  }
+
+  /// Creates a level `depth` levels down, starting at 1, that always generates the same way for
+  /// the same seed and depth
+  pub fn with_seed_at_depth(seed: u64, depth: u32) -> Level {
+    let (width, height) = LEVEL_DIMS;
+    let mut rng = level_rng(seed);
+    let mut level = Level::with_cave_sim(Level::rand_cave_sim(&mut rng, width), width, height);
+    level.seed = seed;
+    level.rng = rng;
+    level.depth = depth.max(1);
+    level
+  }
+
+  /// A finished level with nothing in it, which stands in until a real one has been generated.
+  /// Unlike `new` it doesn't grow a cave, so it's cheap to make.
+  pub fn empty() -> Level {
+    let (width, height) = LEVEL_DIMS;
+    let mut level = Level::with_cave_sim(CASim::from_grid(vec![vec![false]], 1.0), width, height);
+    level.gen_stage = LevelGenStage::Done;
+    level.level_gen_finished = true;
+    level
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  pub fn depth(&self) -> u32 {
+    self.depth
+  }
+
+  /// Creates a level that will be generated around the cave grown by `cave_sim`
+  fn with_cave_sim(cave_sim: CASim, width: Meters, height: Meters) -> Level {
+    let recording = GenRecording::new(&cave_sim.ca_grid);
     Level {
-      // TODO: Right now the dimensions of this sim need to have the same ratio
-      // as the screen or it gets squished. It's also bad at taking up most of the available screen
-      // space.
-      cave_sim: CASim::new(200, 200, 1.0),
+      cave_sim,
       level_gen_finished: false,
       rooms: Vec::new(),
       obstacles: Vec::new(),
+      furniture: Vec::new(),
+      floor_items: Vec::new(),
+      spawns: Vec::new(),
+      cave_holes: Vec::new(),
+      cave_walls: Vec::new(),
+      terrain: TerrainMap::default(),
+      gen_stats: GenStats::default(),
       gen_stage: LevelGenStage::CaveSim,
-      width: 50.0,
-      height: 50.0,
+      width,
+      height,
       tmp_collw: new_collw(),
       tmp_ent_ct: 0,
+      furnished_rooms: 0,
+      looted_rooms: 0,
+      populated_rooms: 0,
+      packs: 0,
+      obstacle_attempts: 0,
+      rejected: Vec::new(),
+      cave_fence: Vec::new(),
+      recording,
+      stage_times: BTreeMap::new(),
+      room_attempts: 0,
+      cave_attempts: 1,
+      seed: 0,
+      rng: level_rng(0),
+      depth: 1,
     }
   }
 
+  fn rand_cave_sim<R: Rng>(rng: &mut R, width: Meters) -> CASim {
+    let seeds = CASim::rand_seeds(rng, 200, 200, 3);
+    let mut cave_sim = CASim::with_seeds(rng, 200, 200, 1.0, &seeds);
+    // Rooms only get placed around the biggest cave, so join any stragglers up with it
+    cave_sim.merge_caves = true;
+    cave_sim.tracer = BoundaryTracer::MarchingSquares;
+    cave_sim.meters_per_cell = width / 200.0;
+    cave_sim
+  }
+
   pub fn tick_level_gen(&mut self) {
+    let stage = self.gen_stage;
+    let start = Instant::now();
     let stage_complete = match self.gen_stage {
       LevelGenStage::CaveSim => self.tick_cavesim(),
+      LevelGenStage::Terrain => {
+        let dims = self.dims();
+        self.terrain = TerrainMap::generate(&mut self.rng, &self.cave_sim.ca_grid, dims);
+        true
+      }
       LevelGenStage::RoomSim => self.tick_roomsim(),
+      LevelGenStage::Furnish => self.tick_furnish(),
+      LevelGenStage::Loot => self.tick_loot(),
       LevelGenStage::PlaceObstacles => self.place_obstacles(),
+      LevelGenStage::Populate => self.tick_populate(),
       _ => false,
     };
+    if stage != LevelGenStage::Done {
+      *self.stage_times.entry(stage).or_insert_with(Duration::default) += start.elapsed();
+    }
     if stage_complete {
       self.gen_stage = ToPrimitive::to_u8(&self.gen_stage)
         .and_then(|v| FromPrimitive::from_u8(v + 1))
         .unwrap_or(LevelGenStage::Done);
     }
+    if stage != LevelGenStage::Done {
+      let frame = GenFrame::new(
+        self.gen_stage,
+        !self.cave_sim.caves.is_empty(),
+        self.rooms.len(),
+        self.furniture.len(),
+        self.floor_items.len(),
+        self.obstacles.len(),
+        self.rejected.len(),
+      );
+      self.recording.record(&self.cave_sim.ca_grid, frame);
+    }
     if self.gen_stage == LevelGenStage::Done {
       self.level_gen_finished = true;
     }
   }
 
+  /// Summarizes what has been generated so far
+  pub fn stats(&self) -> LevelStats {
+    let cave_area = self.cave_polygons().iter().map(|c| c.area()).sum::<f32>();
+    let room_area = self.rooms.iter().map(|r| r.area()).sum::<f32>();
+    LevelStats {
+      seed: self.seed,
+      depth: self.depth,
+      cave_area,
+      rooms: self.rooms.len(),
+      compound_rooms: self.rooms.iter().filter(|r| r.is_compound()).count(),
+      room_coverage: if cave_area > 0.0 { room_area / cave_area } else { 0.0 },
+      doors: self.rooms.iter().map(|r| r.doors().len()).sum(),
+      loot: self.floor_items.len(),
+      monsters: self.spawns.len(),
+      packs: self.packs,
+      ca_iterations: self.cave_sim.ca_iterations(),
+      rejections: self.rejected.iter().fold(BTreeMap::new(), |mut counts, r| {
+        *counts.entry(r.stage).or_insert(0) += 1;
+        counts
+      }),
+      stage_times: self.stage_times.clone(),
+      failures: self.gen_stats.clone(),
+    }
+  }
+
+  /// Remembers a placement thrown away during the current stage, along with where it touched
+  /// whatever it collided with
+  fn reject(&mut self, outlines: Vec<Vec<Point>>, contacts: Vec<(Point, Point)>) {
+    self.rejected.push(Rejection { stage: self.gen_stage, outlines, contacts });
+  }
+
   fn tick_cavesim(&mut self) -> bool {
-    self.cave_sim.tick()
+    let done = self.cave_sim.tick();
+    if done {
+      if self.cave_sim.caves.is_empty() {
+        self.gen_stats.record(&GenError::CaveDiedOut { attempt: self.cave_attempts });
+        if self.cave_attempts < MAX_CAVE_ATTEMPTS {
+          self.cave_attempts += 1;
+          self.cave_sim = Level::rand_cave_sim(&mut self.rng, self.width);
+          return false;
+        }
+        // The rest of generation copes with there being no cave by not adding anything
+        warn!("Couldn't grow a cave after {} tries", self.cave_attempts);
+        return true;
+      }
+      let caves = self.cave_polygons();
+      self.cave_walls = caves
+        .iter()
+        .filter(|c| c.outer.len() >= 3)
+        .map(|c| CaveWall { ring: c.outer.clone() })
+        .collect();
+      self.cave_holes = caves
+        .into_iter()
+        .flat_map(|c| c.holes)
+        .filter(|ring| ring.len() >= 3)
+        .map(|ring| CaveHole { ring })
+        .collect();
+    }
+    done
   }
 
+  /// Tries to place one room per tick, giving up on the rest once too many attempts in a row fail
   fn tick_roomsim(&mut self) -> bool {
-    let mut rng = thread_rng();
+    // Nothing to put rooms in if the cave never grew
+    if self.cave_walls.is_empty() {
+      return true;
+    }
     // Room centers should be within the bounding box of the cave
     let cave_bb = self.cave_bound_box();
     let xrange = (cave_bb.mins().x, cave_bb.maxs().x);
     let yrange = (cave_bb.mins().y, cave_bb.maxs().y);
-    if self.rooms.is_empty() {
+    if self.rooms.is_empty() && self.room_attempts == 0 {
       // First run through add the cave BB to the collision world so we don't get rooms too far
       // outside of the cave. To get the four walls, it's easy to convert the BB into a "room".
       let cave_bb_room = Room::new_with_centered_door(
@@ -105,39 +391,292 @@ impl Level {
       .unwrap();
       let nxt_id = self.get_and_inc_eid();
       self.tmp_collw.register(&cave_bb_room, CollidableDat::new(cave_bb.coltype(), nxt_id));
+      self.cave_fence = cave_bb_room.outline().to_vec();
+      // Rooms shouldn't be built on top of any holes in the cave either
+      for hole_ix in 0..self.cave_holes.len() {
+        let dat = CollidableDat::new(CollidableType::CaveHole, self.get_and_inc_eid());
+        self.tmp_collw.register(&self.cave_holes[hole_ix], dat);
+      }
       self.tmp_collw.update();
     }
-    if self.rooms.len() < 10 {
-      loop {
-        let is_compound = rng.gen_bool(2.0 / 5.0);
-        let mut nu_rooms = Vec::new();
-        if is_compound {
-          if let Ok(mut room) = CompoundRoomMaker::rand_compound_room(xrange, yrange) {
-            nu_rooms.append(&mut room);
-          } else {
-            // If we failed to generate a compound room, restart and generate a new room
-            continue;
+    if self.rooms.len() >= TARGET_ROOMS {
+      info!("Done placing rooms");
+      return true;
+    }
+    if self.room_attempts < MAX_ROOM_ATTEMPTS {
+      // Compound rooms are the hardest to fit, so they're dropped first as the budget runs out,
+      // and then rooms start getting smaller
+      let spent = self.room_attempts as f32 / MAX_ROOM_ATTEMPTS as f32;
+      self.room_attempts += 1;
+      let is_compound = spent < 0.5 && self.rng.gen_bool(2.0 / 5.0);
+      let scale = if spent < 0.75 { 1.0 } else { 0.5 };
+      let mut nu_rooms = Vec::new();
+      if is_compound {
+        match CompoundRoomMaker::rand_compound_room(&mut self.rng, xrange, yrange) {
+          Ok(mut room) => nu_rooms.append(&mut room),
+          Err(e) => {
+            // If we failed to generate a compound room, try again next tick
+            self.gen_stats.record(&e);
+            return false;
           }
-        } else {
-          nu_rooms.push(Room::new_rand(xrange, yrange));
         }
-        let cw_typ =
-          if is_compound { CollidableType::CompoundRoomWall } else { CollidableType::RoomWall };
-        let cw_dat = CollidableDat::new(cw_typ, self.get_and_inc_eid());
-        let (coll_handles, no_collisions) =
-          Level::check_room_collisions(&mut self.tmp_collw, &nu_rooms, cw_dat);
-        if no_collisions {
-          self.rooms.append(&mut nu_rooms);
+      } else {
+        let (rng, stats) = (&mut self.rng, &mut self.gen_stats);
+        nu_rooms.push(Room::new_rand(rng, xrange, yrange, MAX_ROOM_DOORS, scale, stats));
+      }
+      let cw_typ =
+        if is_compound { CollidableType::CompoundRoomWall } else { CollidableType::RoomWall };
+      let cw_dat = CollidableDat::new(cw_typ, self.get_and_inc_eid());
+      let (coll_handles, no_collisions) =
+        Level::check_room_collisions(&mut self.tmp_collw, &nu_rooms, cw_dat);
+      if no_collisions {
+        self.rooms.append(&mut nu_rooms);
+        self.room_attempts = 0;
+      } else {
+        let contacts = contact_points(&self.tmp_collw);
+        self.tmp_collw.remove(coll_handles.as_slice());
+        let mut outlines = vec![];
+        for room in &nu_rooms {
+          outlines.push(room.outline().to_vec());
+          let floormats = room.floormat();
+          outlines.extend(floormats.iter().map(|f| (f as &CenterOriginRect).corners().to_vec()));
+        }
+        self.reject(outlines, contacts);
+      }
+      return false;
+    }
+    // Settle for however many rooms fit
+    self.gen_stats.record(&GenError::OutOfRoomAttempts { placed: self.rooms.len() });
+    info!("Done placing rooms, only {} of {} fit", self.rooms.len(), TARGET_ROOMS);
+    true
+  }
+
+  /// Picks a kind for the next unfurnished room and fills it with matching furniture. Pieces that
+  /// would touch walls, door floormats or each other are dropped.
+  fn tick_furnish(&mut self) -> bool {
+    if self.furnished_rooms >= self.rooms.len() {
+      info!("Done furnishing rooms");
+      return true;
+    }
+    let room_ix = self.furnished_rooms;
+    self.furnished_rooms += 1;
+    let kind = *self.rng.choose(RoomKind::all()).unwrap();
+    self.rooms[room_ix].kind = Some(kind);
+    let layout = Furniture::rand_layout(&mut self.rng, kind, &self.rooms[room_ix]);
+    // The layout only knows about the room's bounding box, which shaped rooms don't fill
+    let room = &self.rooms[room_ix];
+    let (layout, outside): (Vec<Furniture>, Vec<Furniture>) =
+      layout.into_iter().partition(|f| f.corners().iter().all(|&c| room.contains(c)));
+    for piece in outside {
+      self.reject(vec![piece.corners().to_vec()], vec![]);
+    }
+    for piece in layout {
+      if self.place_checked(&piece, &piece.corners()) {
+        self.furniture.push(piece);
+      }
+    }
+    false
+  }
+
+  /// Rolls on the next room's loot table, and the dead end table too if it's one of the
+  /// `dead_ends`, and scatters what comes up around the room. Items that would land on walls,
+  /// floormats or furniture are dropped.
+  fn tick_loot(&mut self) -> bool {
+    if self.looted_rooms >= self.rooms.len() {
+      info!("Done placing loot");
+      return true;
+    }
+    let dead_end = self.dead_ends().contains(&self.looted_rooms);
+    let room = &self.rooms[self.looted_rooms];
+    self.looted_rooms += 1;
+    let kind = match room.kind {
+      Some(kind) => kind,
+      None => return false,
+    };
+    let luck = self.loot_luck(room);
+    let mut loot = LootTables::for_room(kind).roll(&mut self.rng, self.depth, luck);
+    if dead_end {
+      loot.extend(LootTables::dead_end().roll(&mut self.rng, self.depth, luck));
+    }
+    // Like furniture, the scatter only knows about the room's bounding box
+    let (items, outside): (Vec<FloorItem>, Vec<FloorItem>) =
+      FloorItem::rand_scatter(&mut self.rng, loot, room)
+        .into_iter()
+        .partition(|i| i.corners().iter().all(|&c| room.contains(c)));
+    for item in outside {
+      self.reject(vec![item.corners().to_vec()], vec![]);
+    }
+    for item in items {
+      if self.place_checked(&item, &item.corners()) {
+        self.floor_items.push(item);
+      }
+    }
+    false
+  }
+
+  /// Indices of the rooms inside compound rooms that the rest of their compound room only reaches
+  /// through a single doorway, with no door out to the cave. Standalone rooms are never dead ends,
+  /// since their doors all lead out into the open cave.
+  fn dead_ends(&self) -> Vec<usize> {
+    // A door in one compound room's wall that another compound room shares leads between the two
+    let opens_into = |door: &Door, room: &Room| {
+      let off = door.center() - room.center();
+      off.x.abs() <= room.width() / 2.0 + WALL_THICKNESS
+        && off.y.abs() <= room.height() / 2.0 + WALL_THICKNESS
+    };
+    let compound: Vec<usize> =
+      (0..self.rooms.len()).filter(|&ix| self.rooms[ix].is_compound()).collect();
+    compound
+      .iter()
+      .cloned()
+      .filter(|&ix| {
+        let own_doors = self.rooms[ix].doors().len();
+        let doors_in = compound
+          .iter()
+          .filter(|&&other| other != ix)
+          .flat_map(|&other| self.rooms[other].doors())
+          .filter(|d| opens_into(d, &self.rooms[ix]))
+          .count();
+        own_doors + doors_in == 1
+      })
+      .collect()
+  }
+
+  /// How lucky loot rolls in `room` are. Deeper levels are luckier everywhere, and compound rooms
+  /// get luckier the farther they are from the middle of the level, where the player starts.
+  fn loot_luck(&self, room: &Room) -> f32 {
+    let depth_luck = (self.depth - 1) as f32 * DEPTH_LUCK;
+    if !room.is_compound() {
+      return depth_luck;
+    }
+    let from_middle = |r: &Room| na::distance(&r.center(), &self.middle());
+    let farthest =
+      self.rooms.iter().filter(|r| r.is_compound()).map(from_middle).fold(0.0, f32::max);
+    if farthest <= 0.0 {
+      return depth_luck;
+    }
+    depth_luck + COMPOUND_LUCK * from_middle(room) / farthest
+  }
+
+  /// Rolls packs of monsters for the next room and spreads them around it. Once every room has had
+  /// its turn, packs for the open cave go in the pockets between rooms.
+  fn tick_populate(&mut self) -> bool {
+    if self.populated_rooms < self.rooms.len() {
+      let room_ix = self.populated_rooms;
+      self.populated_rooms += 1;
+      if let Some(kind) = self.rooms[room_ix].kind {
+        for pack in EncounterTables::for_room(kind).roll(&mut self.rng, self.depth) {
+          self.place_pack(pack, Some(room_ix));
+        }
+      }
+      return false;
+    }
+    if !self.cave_walls.is_empty() {
+      for pack in EncounterTables::cave().roll(&mut self.rng, self.depth) {
+        self.place_pack(pack, None);
+      }
+    }
+    info!("Done placing monsters");
+    true
+  }
+
+  /// Finds a spot for a pack's leader, inside room `room_ix` or out in the cave if there's no room,
+  /// then gathers the rest of the pack around them. Monsters that can't find a clear spot are left
+  /// out.
+  fn place_pack(&mut self, pack: Pack, room_ix: Option<usize>) {
+    let caves = self.cave_polygons();
+    let (center, spread) = match room_ix {
+      Some(ix) => {
+        let room = &self.rooms[ix];
+        (room.center(), Vec2::new(room.width(), room.height()) / 2.0)
+      }
+      None => {
+        let bb = self.cave_bound_box();
+        (bb.center(), bb.half_extents())
+      }
+    };
+    let mut leader = None;
+    for _ in 0..MAX_SPAWN_ATTEMPTS {
+      let jitter = Vec2::new(self.rng.gen_range(-1.0, 1.0), self.rng.gen_range(-1.0, 1.0));
+      let pos = center + jitter.component_mul(&spread);
+      let spawn = Spawn { kind: pack.kind, pos, pack: self.packs };
+      if self.spawn_fits(&spawn, room_ix, &caves) {
+        leader = Some(pos);
+        break;
+      }
+    }
+    let leader = match leader {
+      Some(pos) => pos,
+      None => return,
+    };
+    self.spawns.push(Spawn { kind: pack.kind, pos: leader, pack: self.packs });
+    for _ in 1..pack.size {
+      for _ in 0..MAX_SPAWN_ATTEMPTS {
+        let offset = Vec2::new(
+          self.rng.gen_range(-PACK_SPREAD, PACK_SPREAD),
+          self.rng.gen_range(-PACK_SPREAD, PACK_SPREAD),
+        );
+        let spawn = Spawn { kind: pack.kind, pos: leader + offset, pack: self.packs };
+        if self.spawn_fits(&spawn, room_ix, &caves) {
+          self.spawns.push(spawn);
           break;
-        } else {
-          self.tmp_collw.remove(coll_handles.as_slice());
         }
       }
-      false
-    } else {
-      info!("Done placing rooms");
-      true
     }
+    self.packs += 1;
+  }
+
+  /// Whether `spawn` is clear of the player's start, other monsters and everything in the level,
+  /// and inside room `room_ix`, or in the cave but outside every room if there's no room. Spawns
+  /// that run into something are rejected.
+  fn spawn_fits(
+    &mut self,
+    spawn: &Spawn,
+    room_ix: Option<usize>,
+    caves: &[PolygonWithHoles],
+  ) -> bool {
+    let radius = spawn.kind.radius();
+    let in_place = match room_ix {
+      Some(ix) => self.rooms[ix].contains(spawn.pos),
+      None => {
+        caves.iter().any(|c| c.contains(spawn.pos))
+          && !self.rooms.iter().any(|r| r.contains(spawn.pos))
+      }
+    };
+    let crowded =
+      self.spawns.iter().any(|s| na::distance(&s.pos, &spawn.pos) < s.kind.radius() + radius);
+    if !in_place || crowded || na::distance(&spawn.pos, &self.middle()) < SAFE_RADIUS {
+      return false;
+    }
+    // Monsters move around, so they don't stay in the collision world once they're checked
+    let dat = CollidableDat::new(spawn.coltype(), self.get_and_inc_eid());
+    let handle = self.tmp_collw.register(spawn, dat);
+    self.tmp_collw.update();
+    let clear = has_no_collisions(&self.tmp_collw);
+    if !clear {
+      let contacts = contact_points(&self.tmp_collw);
+      let r = Vec2::new(radius, radius);
+      let (mins, maxs) = (spawn.pos - r, spawn.pos + r);
+      let outline = vec![mins, Point::new(maxs.x, mins.y), maxs, Point::new(mins.x, maxs.y)];
+      self.reject(vec![outline], contacts);
+    }
+    self.tmp_collw.remove(&[handle]);
+    clear
+  }
+
+  /// Adds `placed` to the generation collision world if it doesn't touch anything there, or
+  /// rejects it if it does. Returns whether it was kept.
+  fn place_checked<T: Collidable>(&mut self, placed: &T, outline: &[Point]) -> bool {
+    let dat = CollidableDat::new(placed.coltype(), self.get_and_inc_eid());
+    let handle = self.tmp_collw.register(placed, dat);
+    self.tmp_collw.update();
+    if has_no_collisions(&self.tmp_collw) {
+      return true;
+    }
+    let contacts = contact_points(&self.tmp_collw);
+    self.tmp_collw.remove(&[handle]);
+    self.reject(vec![outline.to_vec()], contacts);
+    false
   }
 
   /// Returns a tuple of (collision handles, were any collisions)
@@ -165,6 +704,16 @@ impl Level {
     cave_polyline.aabb().clone()
   }
 
+  /// Every cave and its holes, in level space
+  pub fn cave_polygons(&self) -> Vec<PolygonWithHoles> {
+    self
+      .cave_sim
+      .uspace_caves(Point::new(0.0, 0.0))
+      .iter()
+      .map(|c| c.map_points(|p| self.uspace_to_lspace(p)))
+      .collect()
+  }
+
   fn cave_bounds(&self) -> Vec<Point> {
     self
       .cave_sim
@@ -174,16 +723,54 @@ impl Level {
       .collect()
   }
 
+  /// Grows one blobstacle per tick and keeps it if it fits in the cave without touching any rooms,
+  /// doors, holes or other obstacles
   fn place_obstacles(&mut self) -> bool {
-    // Grow some ponds using our CA generation method
-    // TODO: Re-enable / do something actually useful when I get to this point
-    //    let test_pond = Blobstacle::new(Point::new(30.0, 30.0));
-    //    let test_pond2 = Blobstacle::new(Point::new(5.5, 5.1));
-    //    let test_pond3 = Blobstacle::new(Point::new(20.8, 20.8));
-    //    self.obstacles.push(test_pond);
-    //    self.obstacles.push(test_pond2);
-    //    self.obstacles.push(test_pond3);
-    true
+    if self.obstacles.len() >= NUM_OBSTACLES || self.obstacle_attempts >= MAX_OBSTACLE_ATTEMPTS {
+      info!("Done placing obstacles");
+      return true;
+    }
+    if self.cave_walls.is_empty() {
+      return true;
+    }
+    self.obstacle_attempts += 1;
+    let cave_bb = self.cave_bound_box();
+    let size = self.rng.gen_range(3.0, 7.0);
+    if cave_bb.maxs().x - size <= cave_bb.mins().x || cave_bb.maxs().y - size <= cave_bb.mins().y {
+      return true;
+    }
+    let pos = Point::new(
+      self.rng.gen_range(cave_bb.mins().x, cave_bb.maxs().x - size),
+      self.rng.gen_range(cave_bb.mins().y, cave_bb.maxs().y - size),
+    );
+    let kind = *self.rng.choose(BlobKind::all()).unwrap();
+    let blob = match Blobstacle::new(&mut self.rng, kind, pos, size) {
+      Ok(b) => b,
+      Err(e) => {
+        self.gen_stats.record(&e);
+        return false;
+      }
+    };
+    // Rooms entirely surrounding the blob won't show up as contacts, so check those separately.
+    // The player also starts in the middle, so keep that clear.
+    let caves = self.cave_polygons();
+    let in_cave = blob.outline().iter().all(|&p| caves.iter().any(|c| c.contains(p)));
+    let in_room = self.rooms.iter().any(|r| blob.outline().iter().any(|&p| r.contains(p)));
+    if !in_cave || in_room || blob.contains(self.middle()) {
+      self.reject(vec![blob.outline().to_vec()], vec![]);
+      return false;
+    }
+    let dat = CollidableDat::new(blob.coltype(), self.get_and_inc_eid());
+    let handle = self.tmp_collw.register(&blob, dat);
+    self.tmp_collw.update();
+    if has_no_collisions(&self.tmp_collw) {
+      self.obstacles.push(blob);
+    } else {
+      let contacts = contact_points(&self.tmp_collw);
+      self.tmp_collw.remove(&[handle]);
+      self.reject(vec![blob.outline().to_vec()], contacts);
+    }
+    false
   }
 
   /// Converts level space to unit space
@@ -196,12 +783,27 @@ impl Level {
     Point::new(p.x * self.width, p.y * self.height)
   }
 
+  /// What the floor is made of at `p`, if it's inside the cave
+  pub fn terrain_at(&self, p: Point) -> Option<Terrain> {
+    self.terrain.at(p)
+  }
+
   pub fn middle(&self) -> Point {
     Point::new(self.width / 2.0, self.height / 2.0)
   }
 
+  /// The level's (width, height)
+  pub fn dims(&self) -> Vec2 {
+    Vec2::new(self.width, self.height)
+  }
+
   pub fn produce_collidables(&self) -> Vec<&Collidable> {
-    self.rooms.iter().map(|r| r as &Collidable).collect()
+    let rooms = self.rooms.iter().map(|r| r as &Collidable);
+    let walls = self.cave_walls.iter().map(|w| w as &Collidable);
+    let holes = self.cave_holes.iter().map(|h| h as &Collidable);
+    let obstacles = self.obstacles.iter().map(|o| o as &Collidable);
+    let furniture = self.furniture.iter().map(|f| f as &Collidable);
+    rooms.chain(walls).chain(holes).chain(obstacles).chain(furniture).collect()
   }
 
   fn get_and_inc_eid(&mut self) -> usize {
@@ -210,35 +812,147 @@ impl Level {
     c_id
   }
 
+  /// Copies everything needed to draw the level as it currently is, so it can be shown while
+  /// generation carries on elsewhere
+  pub fn snapshot(&self) -> LevelSnapshot {
+    LevelSnapshot {
+      stage: self.gen_stage,
+      cave_sim: self.cave_sim.drawable(),
+      terrain: self.terrain.clone(),
+      rooms: self.rooms.clone(),
+      furniture: self.furniture.clone(),
+      floor_items: self.floor_items.clone(),
+      spawns: self.spawns.clone(),
+      obstacles: self.obstacles.clone(),
+      rejected: self.recent_rejections(self.recording.frames.len().saturating_sub(1)),
+      cave_fence: self.cave_fence.clone(),
+      dims: self.dims(),
+    }
+  }
+
+  pub fn recording(&self) -> &GenRecording {
+    &self.recording
+  }
+
+  /// What the level looked like after recorded frame `ix`
+  pub fn replay(&self, ix: usize) -> LevelSnapshot {
+    let frame = match self.recording.frames.get(ix) {
+      Some(f) => f,
+      None => return self.snapshot(),
+    };
+    let mut cave_sim = self.cave_sim.drawable();
+    cave_sim.ca_grid = self.recording.grid_at(ix);
+    cave_sim.ca_boundary.clear();
+    if !frame.traced {
+      cave_sim.caves.clear();
+    }
+    LevelSnapshot {
+      stage: frame.stage,
+      cave_sim,
+      terrain: if frame.stage > LevelGenStage::Terrain {
+        self.terrain.clone()
+      } else {
+        TerrainMap::default()
+      },
+      rooms: self.rooms[..frame.rooms].to_vec(),
+      furniture: self.furniture[..frame.furniture].to_vec(),
+      // Picking items up in the world takes them off the level's list
+      floor_items: self.floor_items[..frame.floor_items.min(self.floor_items.len())].to_vec(),
+      // Spawns aren't counted in frames, and only show up once populating is done
+      spawns: if frame.stage > LevelGenStage::Populate { self.spawns.clone() } else { Vec::new() },
+      obstacles: self.obstacles[..frame.obstacles].to_vec(),
+      rejected: self.recent_rejections(ix),
+      cave_fence: if frame.stage >= LevelGenStage::RoomSim {
+        self.cave_fence.clone()
+      } else {
+        Vec::new()
+      },
+      dims: self.dims(),
+    }
+  }
+
+  /// Writes the whole recorded generation out as an animated GIF
+  pub fn export_gif(&self, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_gif(file, (0..self.recording.frames.len()).map(|ix| self.replay(ix)))
+  }
+
+  /// The last few placements rejected up to and including recorded frame `ix`, oldest first
+  fn recent_rejections(&self, ix: usize) -> Vec<Rejection> {
+    let end = self.recording.frames.get(ix).map_or(0, |f| f.rejections);
+    self.rejected[end.saturating_sub(RECENT_REJECTIONS)..end].to_vec()
+  }
+}
+
+/// The drawable parts of a level at some point during generation
+#[derive(Clone)]
+pub struct LevelSnapshot {
+  pub stage: LevelGenStage,
+  pub cave_sim: CASim,
+  pub terrain: TerrainMap,
+  pub rooms: Vec<Room>,
+  pub furniture: Vec<Furniture>,
+  pub floor_items: Vec<FloorItem>,
+  pub spawns: Vec<Spawn>,
+  pub obstacles: Vec<Blobstacle>,
+  /// The latest placements that were tried and thrown away, oldest first
+  pub rejected: Vec<Rejection>,
+  /// Walls of the cave's bounding box that room placement treats as a room
+  pub cave_fence: Vec<Point>,
+  pub dims: Vec2,
+}
+
+impl LevelSnapshot {
   // Rendering code below =============================================================
-  pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+  /// Draws the level. `l_to_s` is the transform from level space to screen space.
+  pub fn draw(&self, ctx: &mut Context, l_to_s: DrawParam) -> GameResult<()> {
     graphics::set_transform(ctx, DrawParam::default().to_matrix());
     graphics::apply_transformations(ctx)?;
-    let sscale = ctx.sscale();
-    let center_scale = self.lscale(ctx);
 
-    if self.gen_stage == LevelGenStage::CaveSim {
-      self.cave_sim.draw_evolution(ctx, sscale)?;
+    if self.stage == LevelGenStage::CaveSim {
+      // The CA sim draws itself in unit space, so stretch that out to the level's size
+      let u_to_s =
+        DrawParam { scale: Vec2::from(l_to_s.scale).component_mul(&self.dims).into(), ..l_to_s };
+      self.cave_sim.draw_evolution(ctx, u_to_s)?;
     } else {
-      graphics::set_transform(ctx, center_scale.to_matrix());
+      graphics::set_transform(ctx, l_to_s.to_matrix());
       graphics::apply_transformations(ctx)?;
       // Next stage, we render the cave as a polygon and place rooms
       let color = Color::new(0.5, 0.5, 0.5, 1.0);
       // TODO: We also do this u->l conversion in the generator. Combine
       // somehow?
       self.cave_sim.draw(ctx, self.u_to_l_scale().color(color))?;
+      self.terrain.draw(ctx)?;
 
       if !self.rooms.is_empty() {
         for room in &self.rooms {
           let grayval = 0.3;
           room.draw(ctx, &DrawParam::new().color(Color::new(grayval, grayval, grayval, 1.0)))?;
         }
+        for piece in &self.furniture {
+          piece.draw(ctx)?;
+        }
+        // Once the level is finished the world owns the items, and draws the ones still lying around
+        if self.stage != LevelGenStage::Done {
+          for item in &self.floor_items {
+            item.draw(ctx)?;
+          }
+        }
       }
 
       if !self.rooms.is_empty() {
         for obstacle in &self.obstacles {
           obstacle.draw(ctx)?;
         }
+        // Like items, monsters are the world's to draw once the level is finished
+        if self.stage != LevelGenStage::Done {
+          let color = Color::new(0.8, 0.15, 0.15, 1.0);
+          for spawn in &self.spawns {
+            let r = spawn.kind.radius();
+            let dot = Mesh::new_circle(ctx, DrawMode::fill(), spawn.pos, r, 0.01, color)?;
+            graphics::draw(ctx, &dot, DrawParam::default())?;
+          }
+        }
       }
       //       Test center room of one sq unit
       //      graphics::set_color(ctx, Color::new(0.0, 0.5, 0.0, 1.0))?;
@@ -247,24 +961,45 @@ impl Level {
     Ok(())
   }
 
-  fn lspace_to_sspace(&self, ctx: &Context, p: Point) -> Point {
-    let p = self.lspace_to_uspace(p);
-    ctx.uspace_to_sspace(p)
-  }
-
-  pub fn sspace_to_lspace(&self, ctx: &Context, p: Point) -> Point {
-    let p = ctx.sspace_to_uspace(p);
-    self.uspace_to_lspace(p)
+  /// Draws what generation has been throwing away: the latest rejected placements in red, fading
+  /// with age, the contacts that got them rejected in yellow, and the cave fence in cyan.
+  pub fn draw_debug(&self, ctx: &mut Context, l_to_s: DrawParam) -> GameResult<()> {
+    graphics::set_transform(ctx, l_to_s.to_matrix());
+    graphics::apply_transformations(ctx)?;
+    let closed_line = |ctx: &mut Context, outline: &[Point], color: Color| -> GameResult<()> {
+      if outline.len() < 2 {
+        return Ok(());
+      }
+      let mut closed = outline.to_vec();
+      closed.push(outline[0]);
+      let line = Mesh::new_line(ctx, &closed, WALL_THICKNESS / 2.0, color)?;
+      graphics::draw(ctx, &line, DrawParam::default())
+    };
+    closed_line(ctx, &self.cave_fence, Color::new(0.0, 0.8, 0.8, 0.8))?;
+    let contact_color = Color::new(1.0, 0.9, 0.0, 1.0);
+    for (age, rejection) in self.rejected.iter().rev().enumerate() {
+      let alpha = 0.8 * (1.0 - age as f32 / (RECENT_REJECTIONS + 1) as f32);
+      for outline in &rejection.outlines {
+        closed_line(ctx, outline, Color::new(0.9, 0.1, 0.1, alpha))?;
+      }
+      for &(p1, p2) in &rejection.contacts {
+        for &p in &[p1, p2] {
+          let dot =
+            Mesh::new_circle(ctx, DrawMode::fill(), p, WALL_THICKNESS, 0.01, contact_color)?;
+          graphics::draw(ctx, &dot, DrawParam::default())?;
+        }
+        // Penetrating contacts have their points apart, so show how deep they went
+        if na::distance(&p1, &p2) > 0.0 {
+          let line = Mesh::new_line(ctx, &[p1, p2], WALL_THICKNESS / 2.0, contact_color)?;
+          graphics::draw(ctx, &line, DrawParam::default())?;
+        }
+      }
+    }
+    Ok(())
   }
 
   fn u_to_l_scale(&self) -> DrawParam {
-    let as_vec = self.uspace_to_lspace(Point::new(1.0, 1.0)).coords;
-    DrawParam { scale: as_vec.into(), ..Default::default() }
-  }
-
-  pub fn lscale(&self, ctx: &Context) -> DrawParam {
-    let as_vec = self.lspace_to_sspace(ctx, Point::new(1.0, 1.0)).coords;
-    DrawParam { scale: as_vec.into(), ..Default::default() }
+    DrawParam { scale: self.dims.into(), ..Default::default() }
   }
 }
 
@@ -272,12 +1007,27 @@ fn has_no_collisions(collw: &CollW) -> bool {
   collw.contact_pairs(true).peekable().peek().is_none()
 }
 
+/// Where everything in the collision world is touching, as a point on each of the two objects
+fn contact_points(collw: &CollW) -> Vec<(Point, Point)> {
+  let mut points = vec![];
+  for (_, _, _, manifold) in collw.contact_pairs(true) {
+    for tracked in manifold.contacts() {
+      points.push((tracked.contact.world1, tracked.contact.world2));
+    }
+  }
+  points
+}
+
 #[cfg(test)]
 mod test {
   extern crate timebomb;
 
   use self::timebomb::timeout_ms;
   use super::*;
+  use crate::dungeongen::room_shapes::RoomShape;
+  use crate::dungeongen::rooms::DOOR_WIDTH;
+  use nc::query::Ray;
+  use std::thread;
 
   #[test]
   fn test_no_room_collisions() {
@@ -294,6 +1044,226 @@ mod test {
     )
   }
 
+  #[test]
+  fn test_same_seed_same_level() {
+    timeout_ms(
+      || {
+        let generate = || {
+          let mut l = Level::with_seed(1234);
+          while !l.level_gen_finished {
+            l.tick_level_gen();
+          }
+          l
+        };
+        let (a, b) = (generate(), generate());
+        // Interleaving generation across threads mustn't disturb either level's random stream
+        let other = thread::spawn(generate);
+        let c = generate();
+        let d = other.join().unwrap();
+        assert_eq!(a.seed(), 1234);
+        for l in &[&b, &c, &d] {
+          assert_eq!(a.cave_sim.ca_grid, l.cave_sim.ca_grid);
+          assert_eq!(a.rooms, l.rooms);
+          assert_eq!(a.furniture, l.furniture);
+          assert_eq!(a.floor_items, l.floor_items);
+          assert_eq!(a.spawns, l.spawns);
+          assert_eq!(a.obstacles.len(), l.obstacles.len());
+        }
+        assert!(a.spawns.iter().all(|s| na::distance(&s.pos, &a.middle()) >= SAFE_RADIUS));
+      },
+      40000,
+    )
+  }
+
+  #[test]
+  fn test_replay_matches_generation() {
+    timeout_ms(
+      || {
+        let mut l = Level::new();
+        let mut ticks = 0;
+        while !l.level_gen_finished {
+          l.tick_level_gen();
+          ticks += 1;
+        }
+        let frames = &l.recording().frames;
+        assert_eq!(frames.len(), ticks);
+        let last = l.replay(ticks - 1);
+        assert_eq!(last.cave_sim.ca_grid, l.cave_sim.ca_grid);
+        assert_eq!(last.rooms, l.rooms);
+        assert_eq!(last.obstacles.len(), l.obstacles.len());
+        // Nothing had been placed before the rooms stage
+        let first = l.replay(0);
+        assert!(first.rooms.is_empty() && first.obstacles.is_empty());
+        // The overlay shows the latest rejections as of each frame
+        let recent = l.recent_rejections(ticks - 1);
+        assert_eq!(recent.len(), l.rejected.len().min(RECENT_REJECTIONS));
+        assert_eq!(recent.last(), l.rejected.last());
+        assert!(first.rejected.is_empty() && first.cave_fence.is_empty());
+        assert!(last.cave_fence.len() == 4 || l.cave_walls.is_empty());
+        // Rooms only get rejected for running into things
+        let mut room_rejections = l.rejected.iter().filter(|r| r.stage == LevelGenStage::RoomSim);
+        assert!(room_rejections.all(|r| !r.contacts.is_empty()));
+      },
+      20000,
+    )
+  }
+
+  #[test]
+  fn test_cramped_cave_still_finishes() {
+    timeout_ms(
+      || {
+        // A cave only a few meters across, which most rooms won't fit in
+        let mut grid = vec![vec![false; 40]; 40];
+        for col in grid.iter_mut().skip(18).take(4) {
+          for cell in col.iter_mut().skip(18).take(4) {
+            *cell = true;
+          }
+        }
+        let mut cave_sim = CASim::from_grid(grid, 1.0);
+        cave_sim.tracer = BoundaryTracer::MarchingSquares;
+        let mut l = Level::with_cave_sim(cave_sim, 50.0, 50.0);
+        while !l.level_gen_finished {
+          l.tick_level_gen();
+        }
+        assert!(l.rooms.len() <= TARGET_ROOMS);
+        l.tmp_collw.update();
+        assert!(has_no_collisions(&l.tmp_collw));
+      },
+      20000,
+    )
+  }
+
+  #[test]
+  fn test_furniture_keeps_doors_clear() {
+    timeout_ms(
+      || {
+        let mut l = Level::new();
+        while l.gen_stage < LevelGenStage::PlaceObstacles {
+          l.tick_level_gen();
+        }
+        assert!(l.rooms.iter().all(|r| r.kind.is_some()));
+        let floormats: Vec<CenteredRect> = l.rooms.iter().flat_map(|r| r.floormat()).collect();
+        for piece in &l.furniture {
+          let piece = piece as &CenterOriginRect;
+          assert!(!floormats.iter().any(|f| piece.overlaps(f)));
+        }
+      },
+      10000,
+    )
+  }
+
+  #[test]
+  fn test_obstacles_stay_clear() {
+    timeout_ms(
+      || {
+        let mut l = Level::new();
+        while !l.level_gen_finished {
+          l.tick_level_gen();
+        }
+        l.tmp_collw.update();
+        assert!(has_no_collisions(&l.tmp_collw));
+        for o in &l.obstacles {
+          assert!(!o.contains(l.middle()));
+          for r in &l.rooms {
+            assert!(!o.outline().iter().any(|&p| r.contains(p)));
+          }
+        }
+      },
+      20000,
+    )
+  }
+
+  #[test]
+  fn test_stats_match_level() {
+    timeout_ms(
+      || {
+        let mut l = Level::new();
+        while !l.level_gen_finished {
+          l.tick_level_gen();
+        }
+        let stats = l.stats();
+        assert_eq!(stats.rooms, l.rooms.len());
+        assert!(stats.doors >= stats.simple_rooms());
+        assert!(stats.cave_area > 0.0);
+        assert!(stats.room_coverage > 0.0);
+        assert!(stats.ca_iterations > 0);
+        // Every stage got ticked at least once
+        assert_eq!(stats.stage_times.len(), LevelGenStage::Done as usize);
+      },
+      20000,
+    )
+  }
+
+  #[test]
+  fn test_cave_holes_are_collidable() {
+    let mut grid = vec![vec![false; 40]; 40];
+    for (x, col) in grid.iter_mut().enumerate().take(38).skip(2) {
+      for (y, cell) in col.iter_mut().enumerate().take(38).skip(2) {
+        *cell = !(15..20).contains(&x) || !(15..20).contains(&y);
+      }
+    }
+    let mut l = Level::new();
+    l.cave_sim = CASim::from_grid(grid, 1.0);
+    while l.gen_stage == LevelGenStage::CaveSim {
+      l.tick_level_gen();
+    }
+    assert_eq!(l.cave_holes.len(), 1);
+    let hole_center = l.uspace_to_lspace(Point::new(17.0 / 40.0, 17.0 / 40.0));
+    assert!(!l.cave_polygons()[0].contains(hole_center));
+    let collidables = l.produce_collidables();
+    let holes = collidables.iter().filter(|c| c.coltype() == CollidableType::CaveHole);
+    assert_eq!(holes.count(), 1);
+  }
+
+  #[test]
+  fn test_rooms_dont_fit_inside_holes() {
+    let mut grid = vec![vec![false; 40]; 40];
+    for (x, col) in grid.iter_mut().enumerate().take(38).skip(2) {
+      for (y, cell) in col.iter_mut().enumerate().take(38).skip(2) {
+        *cell = !(10..30).contains(&x) || !(10..30).contains(&y);
+      }
+    }
+    let mut l = Level::new();
+    l.cave_sim = CASim::from_grid(grid, 1.0);
+    while l.gen_stage == LevelGenStage::CaveSim {
+      l.tick_level_gen();
+    }
+    assert_eq!(l.cave_holes.len(), 1);
+    let mut collw = new_collw();
+    collw.register(&l.cave_holes[0], CollidableDat::new(CollidableType::CaveHole, 0));
+    // Well clear of the hole's edge on every side
+    let hole_center = l.uspace_to_lspace(Point::new(0.5, 0.5));
+    let room = Room::new_with_centered_door(hole_center, 3.0, 3.0, Direction::North).unwrap();
+    let dat = CollidableDat::new(CollidableType::RoomWall, 1);
+    let (_, no_collisions) = Level::check_room_collisions(&mut collw, &[room], dat);
+    assert!(!no_collisions);
+  }
+
+  #[test]
+  fn test_cave_wall_keeps_things_inside() {
+    let mut grid = vec![vec![false; 40]; 40];
+    for col in grid.iter_mut().take(30).skip(10) {
+      for cell in col.iter_mut().take(30).skip(10) {
+        *cell = true;
+      }
+    }
+    let mut l = Level::new();
+    l.cave_sim = CASim::from_grid(grid, 1.0);
+    while l.gen_stage == LevelGenStage::CaveSim {
+      l.tick_level_gen();
+    }
+    assert_eq!(l.cave_walls.len(), 1);
+    let mut collw = new_collw();
+    for (id, c) in l.produce_collidables().into_iter().enumerate() {
+      collw.register(c, CollidableDat::new(c.coltype(), id));
+    }
+    collw.update();
+    // A ray from the middle of the cave out past its edge has to hit the wall
+    let ray = Ray::new(l.middle(), Vec2::new(1.0, 0.0));
+    let hits: Vec<_> = collw.interferences_with_ray(&ray, &CollisionGroups::new()).collect();
+    assert!(hits.iter().any(|(obj, _)| obj.data().otype == CollidableType::CaveWall));
+  }
+
   #[test]
   fn test_rooms_can_nest() {
     let mut collw = new_collw();
@@ -325,4 +1295,69 @@ mod test {
     // There should be collisions!
     assert!(!no_collisions);
   }
+
+  #[test]
+  fn test_shaped_rooms_collide() {
+    let mut collw = new_collw();
+    // Each side of the circle is about 2.3m, enough to fit a door
+    let outline = RoomShape::Circle.outline(&mut level_rng(1), 12.0, 12.0);
+    let room1 =
+      Room::new_outlined(Point::new(0.0, 0.0), RoomShape::Circle, &outline, vec![(0, 0.0)]);
+    let room2 =
+      Room::new_outlined(Point::new(8.0, 0.0), RoomShape::Circle, &outline, vec![(0, 0.0)]);
+    let room3 =
+      Room::new_outlined(Point::new(30.0, 0.0), RoomShape::Circle, &outline, vec![(0, 0.0)]);
+    let dat = |id| CollidableDat::new(CollidableType::RoomWall, id);
+    let (_, no_collisions) = Level::check_room_collisions(&mut collw, &[room1.unwrap()], dat(1));
+    assert!(no_collisions);
+    let (handles, no_collisions) =
+      Level::check_room_collisions(&mut collw, &[room2.unwrap()], dat(2));
+    assert!(!no_collisions);
+    collw.remove(&handles);
+    let (_, no_collisions) = Level::check_room_collisions(&mut collw, &[room3.unwrap()], dat(3));
+    assert!(no_collisions);
+  }
+
+  #[test]
+  fn test_shaped_rooms_leave_a_door_gap() {
+    use crate::util::geom::dist_to_segment;
+    let outline = RoomShape::L.outline(&mut level_rng(1), 8.0, 6.0);
+    for edge in 0..outline.len() {
+      let room =
+        Room::new_outlined(Point::new(0.0, 0.0), RoomShape::L, &outline, vec![(edge, 0.5)]);
+      let room = match room {
+        Ok(r) => r,
+        // Some of the L's edges are too short for a door
+        Err(_) => continue,
+      };
+      assert_eq!(room.segments.len(), outline.len() + 1);
+      let door = room.floormat()[0].center;
+      for seg in &room.segments {
+        assert!(dist_to_segment(door, seg.a, seg.b) >= DOOR_WIDTH / 2.0 - 0.001);
+      }
+    }
+  }
+
+  #[test]
+  fn test_only_compound_rooms_set_the_luck_scale() {
+    let mut l = Level::empty();
+    let mid = l.middle();
+    let room =
+      |dx, compound| Room::new(mid + Vec2::new(dx, 0.0), 4.0, 4.0, Vec::new(), compound).unwrap();
+    // The plain room further out doesn't stop the farthest compound room getting all the luck
+    l.rooms = vec![room(10.0, true), room(20.0, false)];
+    assert!((l.loot_luck(&l.rooms[0]) - COMPOUND_LUCK).abs() < 1e-4);
+  }
+
+  #[test]
+  fn test_dead_ends_are_compound_leaves() {
+    let mut l = Level::empty();
+    let door = |x, facing| Door::of_width(Point::new(x, 0.0), DOOR_WIDTH, facing);
+    // The front room opens onto the cave, and the back room only opens onto the front room
+    let front = Room::new(Point::new(0.0, 0.0), 4.0, 4.0, vec![door(-2.0, Direction::West)], true);
+    let back = Room::new(Point::new(4.0, 0.0), 4.0, 4.0, vec![door(2.0, Direction::West)], true);
+    let alone = Room::new_with_centered_door(Point::new(20.0, 0.0), 4.0, 4.0, Direction::North);
+    l.rooms = vec![front.unwrap(), back.unwrap(), alone.unwrap()];
+    assert_eq!(l.dead_ends(), vec![1]);
+  }
 }
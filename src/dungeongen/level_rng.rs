@@ -0,0 +1,30 @@
+use rand::prng::XorShiftRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+/// Random numbers for level generation. Every level has a stream of its own, so generating one
+/// never disturbs another, whichever thread it happens on.
+pub type LevelRng = XorShiftRng;
+
+/// A level generation stream that always comes out the same for the same seed
+pub fn level_rng(seed: u64) -> LevelRng {
+  XorShiftRng::seed_from_u64(seed)
+}
+
+/// A seed for a brand new level
+pub fn rand_seed() -> u64 {
+  thread_rng().gen()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_seed_repeats_stream() {
+    let mut rng = level_rng(7);
+    let first: Vec<u32> = (0..5).map(|_| rng.gen()).collect();
+    let mut rng = level_rng(7);
+    let again: Vec<u32> = (0..5).map(|_| rng.gen()).collect();
+    assert_eq!(first, again);
+  }
+}
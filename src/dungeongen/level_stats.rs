@@ -0,0 +1,66 @@
+use super::gen_error::GenStats;
+use super::level::LevelGenStage;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Summary of what level generation produced and what it took to get there, for tuning
+#[derive(Debug, Default, Clone)]
+pub struct LevelStats {
+  /// What the level was generated from, to get it back with `regen`
+  pub seed: u64,
+  pub depth: u32,
+  /// Floor space of all the caves, not counting holes
+  pub cave_area: f32,
+  pub rooms: usize,
+  /// How many of `rooms` are part of a compound room
+  pub compound_rooms: usize,
+  /// Fraction of the cave area covered by rooms
+  pub room_coverage: f32,
+  pub doors: usize,
+  /// Items scattered around the rooms
+  pub loot: usize,
+  pub monsters: usize,
+  pub packs: usize,
+  /// Rounds of growth the cave CA went through
+  pub ca_iterations: usize,
+  /// Placements thrown away for colliding with something, per stage
+  pub rejections: BTreeMap<LevelGenStage, usize>,
+  /// Time spent ticking each stage
+  pub stage_times: BTreeMap<LevelGenStage, Duration>,
+  pub failures: GenStats,
+}
+
+impl LevelStats {
+  pub fn simple_rooms(&self) -> usize {
+    self.rooms - self.compound_rooms
+  }
+
+  pub fn total_time(&self) -> Duration {
+    self.stage_times.values().sum()
+  }
+}
+
+impl fmt::Display for LevelStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Level stats for seed {} at depth {}:", self.seed, self.depth)?;
+    writeln!(f, "  cave area: {:.1}m2 after {} CA iterations", self.cave_area, self.ca_iterations)?;
+    writeln!(
+      f,
+      "  rooms: {} ({} simple, {} in compounds) covering {:.1}% with {} doors",
+      self.rooms,
+      self.simple_rooms(),
+      self.compound_rooms,
+      self.room_coverage * 100.0,
+      self.doors
+    )?;
+    writeln!(f, "  loot: {} items", self.loot)?;
+    writeln!(f, "  monsters: {} in {} packs", self.monsters, self.packs)?;
+    for (stage, time) in &self.stage_times {
+      let rejected = self.rejections.get(stage).cloned().unwrap_or(0);
+      writeln!(f, "  {:?}: {:?}, {} rejected", stage, time, rejected)?;
+    }
+    writeln!(f, "  total: {:?}", self.total_time())?;
+    write!(f, "  {}", self.failures)
+  }
+}
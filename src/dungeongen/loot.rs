@@ -0,0 +1,175 @@
+use super::tables::{parse_sections, weighted_pick};
+use crate::dungeongen::rooms::RoomKind;
+use crate::items::Item;
+use rand::Rng;
+use std::collections::BTreeMap;
+
+static LOOT_CONFIG: &str = include_str!("loot.cfg");
+/// How much each step of luck multiplies the chances of an entry one rarity tier up
+static LUCK_PER_TIER: f32 = 0.5;
+
+lazy_static! {
+  static ref LOOT_TABLES: LootTables =
+    LootTables::parse(LOOT_CONFIG).expect("The loot tables are broken");
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rarity {
+  Common,
+  Uncommon,
+  Rare,
+  Legendary,
+}
+
+impl Rarity {
+  fn from_name(name: &str) -> Option<Rarity> {
+    match name {
+      "common" => Some(Rarity::Common),
+      "uncommon" => Some(Rarity::Uncommon),
+      "rare" => Some(Rarity::Rare),
+      "legendary" => Some(Rarity::Legendary),
+      _ => None,
+    }
+  }
+
+  /// How many tiers up from common this is
+  fn tier(self) -> i32 {
+    self as i32
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LootEntry {
+  item: Item,
+  weight: f32,
+  rarity: Rarity,
+  /// Shallower levels never roll this entry
+  min_depth: u32,
+}
+
+/// Weighted items that can turn up in one kind of place
+#[derive(Debug, Clone, PartialEq)]
+pub struct LootTable {
+  /// Fewest and most times the table is rolled on for each place that uses it
+  rolls: (usize, usize),
+  entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+  /// Rolls on the table however many times it says to. `luck` is 0 for an ordinary place, and
+  /// every point of it makes rarer entries more likely.
+  pub fn roll<R: Rng>(&self, rng: &mut R, depth: u32, luck: f32) -> Vec<Item> {
+    let weights: Vec<f32> = self
+      .entries
+      .iter()
+      .map(|e| {
+        if e.min_depth > depth {
+          0.0
+        } else {
+          e.weight * (1.0 + luck * LUCK_PER_TIER).powi(e.rarity.tier())
+        }
+      })
+      .collect();
+    let rolls = rng.gen_range(self.rolls.0, self.rolls.1 + 1);
+    (0..rolls)
+      .filter_map(|_| weighted_pick(rng, &weights))
+      .map(|ix| self.entries[ix].item)
+      .collect()
+  }
+}
+
+/// Every loot table, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct LootTables {
+  tables: BTreeMap<String, LootTable>,
+}
+
+impl LootTables {
+  /// Reads tables from sections like `[vault 1-3]` followed by entries like
+  /// `chainmail = 1 legendary 2`. Blank lines and lines starting with `#` are skipped.
+  pub fn parse(config: &str) -> Result<LootTables, String> {
+    let new_table = |rolls| LootTable { rolls, entries: vec![] };
+    let tables = parse_sections(config, new_table, |table, name, rest| {
+      let item = Item::named(name).ok_or_else(|| format!("unknown item {}", name))?;
+      let (weight, rarity, min_depth) = match rest.split_whitespace().collect::<Vec<&str>>()[..] {
+        [weight, rarity] => (weight, rarity, "1"),
+        [weight, rarity, min_depth] => (weight, rarity, min_depth),
+        _ => return Err("expected item = weight rarity [min depth]".to_string()),
+      };
+      table.entries.push(LootEntry {
+        item,
+        weight: weight.parse().map_err(|_| format!("bad weight {}", weight))?,
+        rarity: Rarity::from_name(rarity).ok_or_else(|| format!("unknown rarity {}", rarity))?,
+        min_depth: min_depth.parse().map_err(|_| format!("bad depth {}", min_depth))?,
+      });
+      Ok(())
+    })?;
+    Ok(LootTables { tables })
+  }
+
+  pub fn get(&self, name: &str) -> Option<&LootTable> {
+    self.tables.get(name)
+  }
+
+  /// The table for rooms of the given kind
+  pub fn for_room(kind: RoomKind) -> &'static LootTable {
+    let name = format!("{:?}", kind).to_lowercase();
+    LOOT_TABLES.get(&name).unwrap_or_else(|| panic!("No loot table for {}", name))
+  }
+
+  /// The extra table for rooms with only one door
+  pub fn dead_end() -> &'static LootTable {
+    LOOT_TABLES.get("dead_end").expect("No loot table for dead ends")
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use rand::prng::XorShiftRng;
+  use rand::SeedableRng;
+
+  #[test]
+  fn test_every_room_has_a_table() {
+    for &kind in RoomKind::all() {
+      LootTables::for_room(kind);
+    }
+    LootTables::dead_end();
+  }
+
+  #[test]
+  fn test_bad_tables() {
+    assert!(LootTables::parse("bread = 1 common").unwrap_err().contains("line 1"));
+    assert!(LootTables::parse("[a 0-1]\n\nbread = 1 mythic").unwrap_err().contains("line 3"));
+    assert!(LootTables::parse("[a 2-1]").is_err());
+    assert!(LootTables::parse("[a 0-1]\ncake = 1 common").is_err());
+    assert!(LootTables::parse("[a 0-1]\nbread = lots common").is_err());
+  }
+
+  #[test]
+  fn test_depth_limits() {
+    let tables = LootTables::parse("[t 5-5]\nbread = 1 common\naxe = 100 rare 3").unwrap();
+    let table = tables.get("t").unwrap();
+    let mut rng = XorShiftRng::seed_from_u64(7);
+    let shallow = table.roll(&mut rng, 1, 0.0);
+    assert_eq!(shallow.len(), 5);
+    assert!(shallow.iter().all(|i| i.name == "bread"));
+    assert!(table.roll(&mut rng, 3, 0.0).iter().any(|i| i.name == "axe"));
+  }
+
+  #[test]
+  fn test_luck_finds_rarer_loot() {
+    let tables = LootTables::parse("[t 1-1]\nbread = 10 common\nchainmail = 1 legendary").unwrap();
+    let table = tables.get("t").unwrap();
+    let mut rng = XorShiftRng::seed_from_u64(7);
+    let mut chainmail = |luck: f32| {
+      (0..1000)
+        .flat_map(|_| table.roll(&mut rng, 1, luck))
+        .filter(|i| i.name == "chainmail")
+        .count()
+    };
+    let (unlucky, lucky) = (chainmail(0.0), chainmail(4.0));
+    assert!(unlucky < 200, "{}", unlucky);
+    assert!(lucky > 500, "{}", lucky);
+  }
+}
@@ -0,0 +1,164 @@
+use crate::util::Point;
+use std::collections::HashMap;
+
+/// A point on the half-cell lattice, doubled so it can be hashed
+type LatticeKey = (i32, i32);
+
+/// Extracts the contours around the live cells of `grid` (indexed `[x][y]`) with marching squares.
+/// Cell `(x, y)` is sampled at point `(x, y)`, and contour points sit halfway between a live and a
+/// dead cell. Everything outside the grid counts as dead.
+///
+/// Rings don't repeat their first point. Every ring winds so the live cells are on the same side,
+/// which means outer rings and holes have signed areas of opposite signs (see `geom::ring_area`).
+/// Diagonally touching live cells are treated as connected, same as the radial sweep tracer.
+pub fn contours(grid: &[Vec<bool>]) -> Vec<Vec<Point>> {
+  let width = grid.len() as i32;
+  let height = grid.first().map_or(0, |c| c.len()) as i32;
+  let live =
+    |x: i32, y: i32| x >= 0 && y >= 0 && x < width && y < height && grid[x as usize][y as usize];
+
+  // Each segment is keyed by its start point, and directed so live cells are on its right (y points down)
+  let mut segments: HashMap<LatticeKey, LatticeKey> = HashMap::new();
+  for x in -1..width {
+    for y in -1..height {
+      // Corners clockwise from top left, along with the edge that follows each corner
+      let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+      let states: Vec<bool> = corners.iter().map(|&(cx, cy)| live(cx, cy)).collect();
+      let live_ct = states.iter().filter(|s| **s).count();
+      if live_ct == 0 || live_ct == 4 {
+        continue;
+      }
+      // Doubled midpoint of the edge from corner i to corner i + 1
+      let edge_mid = |i: usize| {
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        (a.0 + b.0, a.1 + b.1)
+      };
+      let crossings: Vec<usize> = (0..4).filter(|&i| states[i] != states[(i + 1) % 4]).collect();
+      let mut pairs = vec![];
+      if crossings.len() == 2 {
+        pairs.push((crossings[0], crossings[1]));
+      } else {
+        // Saddle. The live diagonal is connected, so cut off each dead corner on its own. The
+        // edges either side of corner i are i - 1 and i.
+        for i in (0..4).filter(|&i| !states[i]) {
+          pairs.push(((i + 3) % 4, i));
+        }
+      }
+      for (e1, e2) in pairs {
+        let (a, b) = (edge_mid(e1), edge_mid(e2));
+        // Pick a point that's definitely on the live side of this segment to orient it by. For
+        // a saddle that's the middle of the square, otherwise any live corner works.
+        let live_side = if crossings.len() == 4 {
+          (2 * x + 1, 2 * y + 1)
+        } else {
+          let (lx, ly) = corners[(0..4).find(|&i| states[i]).unwrap()];
+          (2 * lx, 2 * ly)
+        };
+        let cross = (b.0 - a.0) * (live_side.1 - a.1) - (b.1 - a.1) * (live_side.0 - a.0);
+        if cross > 0 {
+          segments.insert(a, b);
+        } else {
+          segments.insert(b, a);
+        }
+      }
+    }
+  }
+
+  // Chain the segments up into rings. Start from the smallest remaining key so the output
+  // doesn't depend on hash ordering.
+  let mut starts: Vec<LatticeKey> = segments.keys().cloned().collect();
+  starts.sort();
+  let mut rings = vec![];
+  for start in starts {
+    if !segments.contains_key(&start) {
+      continue;
+    }
+    let mut ring = vec![];
+    let mut cur = start;
+    while let Some(next) = segments.remove(&cur) {
+      ring.push(Point::new(cur.0 as f32 / 2.0, cur.1 as f32 / 2.0));
+      cur = next;
+    }
+    rings.push(ring);
+  }
+  rings
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::level_rng::level_rng;
+  use crate::util::geom::{is_simple_ring, ring_area};
+  use rand::Rng;
+
+  #[test]
+  fn test_single_cell_is_a_diamond() {
+    let mut grid = vec![vec![false; 3]; 3];
+    grid[1][1] = true;
+    let rings = contours(&grid);
+    assert_eq!(rings.len(), 1);
+    assert_eq!(rings[0].len(), 4);
+    assert!((ring_area(&rings[0]).abs() - 0.5).abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_holes_wind_the_other_way() {
+    let mut grid = vec![vec![true; 7]; 7];
+    grid[3][3] = false;
+    let rings = contours(&grid);
+    assert_eq!(rings.len(), 2);
+    let (a1, a2) = (ring_area(&rings[0]), ring_area(&rings[1]));
+    assert!(a1 * a2 < 0.0);
+    // The outer ring is a 7x7 square with its corners cut, the hole a diamond
+    let (outer, hole) = if a1.abs() > a2.abs() { (a1, a2) } else { (a2, a1) };
+    assert!((outer.abs() - 48.5).abs() < 0.0001);
+    assert!((hole.abs() - 0.5).abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_diagonal_cells_are_connected() {
+    let mut grid = vec![vec![false; 4]; 4];
+    grid[1][1] = true;
+    grid[2][2] = true;
+    assert_eq!(contours(&grid).len(), 1);
+  }
+
+  #[test]
+  fn test_is_deterministic() {
+    for seed in 0..10 {
+      let mut rng = level_rng(seed);
+      let grid: Vec<Vec<bool>> =
+        (0..20).map(|_| (0..20).map(|_| rng.gen::<bool>()).collect()).collect();
+      assert_eq!(contours(&grid), contours(&grid), "seed {}", seed);
+    }
+  }
+
+  #[test]
+  fn test_enclosed_area_matches_live_cells() {
+    for seed in 0..50 {
+      let mut rng = level_rng(seed);
+      let (w, h) = (25, 18);
+      let grid: Vec<Vec<bool>> =
+        (0..w).map(|_| (0..h).map(|_| rng.gen::<f32>() < 0.6).collect()).collect();
+      let live =
+        |x: i32, y: i32| x >= 0 && y >= 0 && x < w && y < h && grid[x as usize][y as usize];
+      let live_ct = grid.iter().flatten().filter(|c| **c).count() as f32;
+      // Every square the contour passes through can be off by at most a quarter of a cell
+      let mut mixed_squares = 0;
+      for x in -1..w {
+        for y in -1..h {
+          let states = [live(x, y), live(x + 1, y), live(x, y + 1), live(x + 1, y + 1)];
+          if states.iter().any(|s| *s) && !states.iter().all(|s| *s) {
+            mixed_squares += 1;
+          }
+        }
+      }
+
+      let rings = contours(&grid);
+      // Holes have the opposite sign, so they get subtracted
+      let area = rings.iter().map(|r| ring_area(r)).sum::<f32>().abs();
+      assert!((area - live_ct).abs() <= 0.25 * mixed_squares as f32 + 0.001, "seed {}", seed);
+      assert!(rings.iter().all(|r| is_simple_ring(r)), "seed {}", seed);
+    }
+  }
+}
@@ -4,4 +4,16 @@ pub mod level;
 mod blobstacle;
 mod ca_simulator;
 mod compound_room;
-mod rooms;
+mod encounters;
+mod furniture;
+mod gen_error;
+pub mod generator;
+pub mod level_rng;
+mod level_stats;
+mod loot;
+mod marching_squares;
+mod recording;
+mod room_shapes;
+pub mod rooms;
+mod tables;
+pub mod terrain;
@@ -0,0 +1,295 @@
+use crate::dungeongen::level::{LevelGenStage, LevelSnapshot};
+use crate::dungeongen::terrain::Terrain;
+use crate::util::geom::ring_contains;
+use crate::util::{Meters, Point};
+use gif::{Encoder, Frame, Repeat, SetParameter};
+use std::borrow::Cow;
+use std::io;
+use std::io::Write;
+
+type CellGrid = Vec<Vec<bool>>;
+
+/// GIF frames are this many pixels across for every cell of the cave grid
+static GIF_PIXELS_PER_CELL: usize = 2;
+/// Hundredths of a second each GIF frame is shown for
+static GIF_FRAME_DELAY: u16 = 3;
+
+/// A placement that generation tried and threw away
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+  pub stage: LevelGenStage,
+  /// Outline of each thing that was rejected together, like all the rooms of a compound room and
+  /// their floormats
+  pub outlines: Vec<Vec<Point>>,
+  /// Pairs of points where the rejected placement touched something already in the level. Empty
+  /// when it was rejected for some other reason, like poking out of the cave.
+  pub contacts: Vec<(Point, Point)>,
+}
+
+/// What one tick of level generation changed. Rooms, furniture, items, obstacles and rejections
+/// only ever get added to, so a frame just remembers how many there were.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenFrame {
+  /// The stage the level was in after the tick
+  pub stage: LevelGenStage,
+  /// Cave grid cells that flipped during the tick
+  flipped: Vec<(u16, u16)>,
+  /// Whether the cave outlines had been traced yet
+  pub traced: bool,
+  pub rooms: usize,
+  pub furniture: usize,
+  pub floor_items: usize,
+  pub obstacles: usize,
+  pub rejections: usize,
+}
+
+/// Every tick of a level's generation, so it can be stepped through after the fact
+#[derive(Debug, Clone, Default)]
+pub struct GenRecording {
+  /// The cave grid before the first tick
+  start_grid: CellGrid,
+  /// The cave grid as of the latest frame, to diff the next one against
+  cur_grid: CellGrid,
+  pub frames: Vec<GenFrame>,
+}
+
+impl GenRecording {
+  pub fn new(start_grid: &CellGrid) -> GenRecording {
+    GenRecording { start_grid: start_grid.clone(), cur_grid: start_grid.clone(), frames: vec![] }
+  }
+
+  /// Records a frame, working out which cells of `grid` changed since the last one
+  pub fn record(&mut self, grid: &CellGrid, mut frame: GenFrame) {
+    frame.flipped = vec![];
+    for (x, (col, cur_col)) in grid.iter().zip(self.cur_grid.iter_mut()).enumerate() {
+      for (y, (&cell, cur_cell)) in col.iter().zip(cur_col.iter_mut()).enumerate() {
+        if cell != *cur_cell {
+          frame.flipped.push((x as u16, y as u16));
+          *cur_cell = cell;
+        }
+      }
+    }
+    self.frames.push(frame);
+  }
+
+  /// The cave grid as it was after frame `ix`
+  pub fn grid_at(&self, ix: usize) -> CellGrid {
+    let mut grid = self.start_grid.clone();
+    for frame in self.frames.iter().take(ix + 1) {
+      for &(x, y) in &frame.flipped {
+        let cell = &mut grid[x as usize][y as usize];
+        *cell = !*cell;
+      }
+    }
+    grid
+  }
+
+  /// Index of the next frame after `from` (or before it, if `forward` is false) where a placement
+  /// got rejected
+  pub fn next_rejection(&self, from: usize, forward: bool) -> Option<usize> {
+    let rejected_at =
+      |&ix: &usize| ix > 0 && self.frames[ix].rejections > self.frames[ix - 1].rejections;
+    if forward {
+      (from + 1..self.frames.len()).find(rejected_at)
+    } else {
+      (1..from.min(self.frames.len())).rev().find(rejected_at)
+    }
+  }
+}
+
+impl GenFrame {
+  pub fn new(
+    stage: LevelGenStage,
+    traced: bool,
+    rooms: usize,
+    furniture: usize,
+    floor_items: usize,
+    obstacles: usize,
+    rejections: usize,
+  ) -> GenFrame {
+    GenFrame {
+      stage,
+      flipped: vec![],
+      traced,
+      rooms,
+      furniture,
+      floor_items,
+      obstacles,
+      rejections,
+    }
+  }
+}
+
+/// Colors in the GIF palette, by index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Px {
+  Background,
+  Cave,
+  Terrain(Terrain),
+  Room,
+  Furniture,
+  Obstacle,
+  Rejected,
+}
+
+impl Px {
+  fn index(self) -> u8 {
+    match self {
+      Px::Background => 0,
+      Px::Cave => 1,
+      Px::Terrain(t) => match t {
+        Terrain::Stone => 2,
+        Terrain::Moss => 3,
+        Terrain::Mud => 4,
+        Terrain::Water => 5,
+        Terrain::Lava => 6,
+      },
+      Px::Room => 7,
+      Px::Furniture => 8,
+      Px::Obstacle => 9,
+      Px::Rejected => 10,
+    }
+  }
+
+  fn palette() -> Vec<u8> {
+    let terrain = [Terrain::Stone, Terrain::Moss, Terrain::Mud, Terrain::Water, Terrain::Lava];
+    let mut palette = vec![0, 0, 0, 0xAF, 0xAF, 0xAF];
+    for t in terrain.iter() {
+      let (r, g, b, _) = t.color().to_rgba();
+      palette.extend_from_slice(&[r, g, b]);
+    }
+    palette
+      .extend_from_slice(&[0x4D, 0x4D, 0x4D, 0x8C, 0x66, 0x33, 0x33, 0x66, 0xE6, 0xE6, 0x1A, 0x1A]);
+    palette
+  }
+}
+
+/// Writes each snapshot as a frame of a looping GIF
+pub fn write_gif<W: Write>(
+  out: W,
+  snapshots: impl Iterator<Item = LevelSnapshot>,
+) -> io::Result<()> {
+  let mut snapshots = snapshots.peekable();
+  let (cells_w, cells_h) = match snapshots.peek() {
+    Some(s) => (s.cave_sim.ca_grid.len(), s.cave_sim.ca_grid.first().map_or(0, |c| c.len())),
+    None => return Ok(()),
+  };
+  let (width, height) = (cells_w * GIF_PIXELS_PER_CELL, cells_h * GIF_PIXELS_PER_CELL);
+  let mut encoder = Encoder::new(out, width as u16, height as u16, &Px::palette())?;
+  encoder.set(Repeat::Infinite)?;
+  for snapshot in snapshots {
+    let pixels = rasterize(&snapshot, width, height);
+    let frame = Frame {
+      width: width as u16,
+      height: height as u16,
+      delay: GIF_FRAME_DELAY,
+      buffer: Cow::Owned(pixels),
+      ..Frame::default()
+    };
+    encoder.write_frame(&frame)?;
+  }
+  Ok(())
+}
+
+/// Draws a snapshot into a `width` by `height` grid of palette indices
+fn rasterize(snapshot: &LevelSnapshot, width: usize, height: usize) -> Vec<u8> {
+  let mut pixels = vec![Px::Background.index(); width * height];
+  let m_per_px = (snapshot.dims.x / width as Meters, snapshot.dims.y / height as Meters);
+  // Pixel centers in level space. Cave cells are centered on their coordinates, same as terrain.
+  let to_level = |px: usize, py: usize| {
+    Point::new(
+      (px as Meters + 0.5) * m_per_px.0 - m_per_px.0 * GIF_PIXELS_PER_CELL as Meters / 2.0,
+      (py as Meters + 0.5) * m_per_px.1 - m_per_px.1 * GIF_PIXELS_PER_CELL as Meters / 2.0,
+    )
+  };
+  for py in 0..height {
+    for px in 0..width {
+      let live = snapshot.cave_sim.ca_grid[px / GIF_PIXELS_PER_CELL][py / GIF_PIXELS_PER_CELL];
+      let color = match snapshot.terrain.at(to_level(px, py)) {
+        Some(t) => Px::Terrain(t),
+        None if live => Px::Cave,
+        None => Px::Background,
+      };
+      pixels[py * width + px] = color.index();
+    }
+  }
+  // Everything else only covers a little of the level, so just fill in its bounding box
+  let mut fill = |outline: &[Point], color: Px| {
+    if outline.len() < 3 {
+      return;
+    }
+    let (mut mins, mut maxs) = (outline[0], outline[0]);
+    for p in outline {
+      mins = Point::new(mins.x.min(p.x), mins.y.min(p.y));
+      maxs = Point::new(maxs.x.max(p.x), maxs.y.max(p.y));
+    }
+    let px_range = |lo: Meters, hi: Meters, m: Meters, max: usize| {
+      let lo = ((lo / m).floor().max(0.0) as usize).min(max);
+      let hi = ((hi / m).ceil().max(0.0) as usize + GIF_PIXELS_PER_CELL).min(max);
+      lo..hi
+    };
+    for py in px_range(mins.y, maxs.y, m_per_px.1, height) {
+      for px in px_range(mins.x, maxs.x, m_per_px.0, width) {
+        if ring_contains(outline, to_level(px, py)) {
+          pixels[py * width + px] = color.index();
+        }
+      }
+    }
+  };
+  for room in &snapshot.rooms {
+    fill(room.outline(), Px::Room);
+  }
+  for piece in &snapshot.furniture {
+    fill(&piece.corners(), Px::Furniture);
+  }
+  for obstacle in &snapshot.obstacles {
+    fill(obstacle.outline(), Px::Obstacle);
+  }
+  for outline in snapshot.rejected.iter().flat_map(|r| &r.outlines) {
+    fill(outline, Px::Rejected);
+  }
+  pixels
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_grid_replays_from_diffs() {
+    let start = vec![vec![false; 4]; 4];
+    let mut rec = GenRecording::new(&start);
+    let mut grids = vec![];
+    let mut grid = start.clone();
+    for i in 0..6 {
+      grid[i % 4][(i * 3) % 4] = !grid[i % 4][(i * 3) % 4];
+      grid[(i + 1) % 4][i % 4] = true;
+      rec.record(&grid, GenFrame::new(LevelGenStage::CaveSim, false, 0, 0, 0, 0, 0));
+      grids.push(grid.clone());
+    }
+    for (ix, g) in grids.iter().enumerate() {
+      assert_eq!(&rec.grid_at(ix), g);
+    }
+    // Unchanged cells aren't stored
+    assert!(rec.frames.iter().all(|f| f.flipped.len() <= 2));
+  }
+
+  #[test]
+  fn test_finds_rejections() {
+    let grid = vec![vec![false; 2]; 2];
+    let mut rec = GenRecording::new(&grid);
+    for &rejections in &[0, 0, 1, 1, 3, 3] {
+      rec.record(&grid, GenFrame::new(LevelGenStage::RoomSim, true, 0, 0, 0, 0, rejections));
+    }
+    assert_eq!(rec.next_rejection(0, true), Some(2));
+    assert_eq!(rec.next_rejection(2, true), Some(4));
+    assert_eq!(rec.next_rejection(4, true), None);
+    assert_eq!(rec.next_rejection(4, false), Some(2));
+    assert_eq!(rec.next_rejection(2, false), None);
+  }
+
+  #[test]
+  fn test_palette_covers_every_color() {
+    assert_eq!(Px::palette().len(), 3 * (Px::Rejected.index() as usize + 1));
+  }
+}
@@ -0,0 +1,150 @@
+use crate::collision::{CollisionRect, Shape2D};
+use crate::dungeongen::level::WALL_THICKNESS;
+use crate::util::{Meters, Point, Vec2};
+use na::{Isometry2, Vector2};
+use nc::shape::ShapeHandle;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Round rooms are approximated with this many sides
+static CIRCLE_SIDES: usize = 16;
+
+/// The outline a room is built around
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoomShape {
+  /// The plain axis-aligned box, which has four axis-aligned walls
+  Rect,
+  Circle,
+  L,
+  T,
+  /// A random convex polygon
+  Convex,
+}
+
+impl RoomShape {
+  /// Shapes other than `Rect`, which are all built from an outline
+  pub fn outlined() -> &'static [RoomShape] {
+    static SHAPES: [RoomShape; 4] =
+      [RoomShape::Circle, RoomShape::L, RoomShape::T, RoomShape::Convex];
+    &SHAPES
+  }
+
+  /// Corners of this shape when it fits in a `w` by `h` box centered on the origin. They go
+  /// clockwise (with y pointing down) and the first isn't repeated at the end. Convex shapes get
+  /// their corners from `rng`, the rest always come out the same.
+  pub fn outline<R: Rng>(self, rng: &mut R, w: Meters, h: Meters) -> Vec<Point> {
+    let (hw, hh) = (w / 2.0, h / 2.0);
+    match self {
+      RoomShape::Rect => {
+        vec![Point::new(-hw, -hh), Point::new(hw, -hh), Point::new(hw, hh), Point::new(-hw, hh)]
+      }
+      RoomShape::Circle => (0..CIRCLE_SIDES)
+        .map(|i| {
+          let theta = i as f32 / CIRCLE_SIDES as f32 * PI * 2.0;
+          Point::new(hw * theta.cos(), hh * theta.sin())
+        })
+        .collect(),
+      // The bottom right quarter is cut away
+      RoomShape::L => vec![
+        Point::new(-hw, -hh),
+        Point::new(hw, -hh),
+        Point::new(hw, 0.0),
+        Point::new(0.0, 0.0),
+        Point::new(0.0, hh),
+        Point::new(-hw, hh),
+      ],
+      // A bar along the top, with a stem half as wide coming down from the middle of it
+      RoomShape::T => vec![
+        Point::new(-hw, -hh),
+        Point::new(hw, -hh),
+        Point::new(hw, 0.0),
+        Point::new(hw / 2.0, 0.0),
+        Point::new(hw / 2.0, hh),
+        Point::new(-hw / 2.0, hh),
+        Point::new(-hw / 2.0, 0.0),
+        Point::new(-hw, 0.0),
+      ],
+      // Points on an ellipse are always in convex position, so just pick a few at random
+      RoomShape::Convex => {
+        let mut angles = vec![];
+        // Corners that are too close together get merged, so make sure there are enough left
+        while angles.len() < 3 {
+          let sides = rng.gen_range(5, 9);
+          angles = (0..sides).map(|_| rng.gen_range(0.0, PI * 2.0)).collect();
+          angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+          angles.dedup_by(|a, b| (*a - *b).abs() < 0.3);
+        }
+        angles.iter().map(|theta| Point::new(hw * theta.cos(), hh * theta.sin())).collect()
+      }
+    }
+  }
+}
+
+/// A straight piece of wall from `a` to `b` that can be at any angle
+#[derive(new, Debug, Copy, Clone, PartialEq)]
+pub struct WallSegment {
+  pub a: Point,
+  pub b: Point,
+}
+
+impl WallSegment {
+  pub fn length(&self) -> Meters {
+    (self.b - self.a).norm()
+  }
+
+  /// This wall as a piece of a compound collision shape centered on `relative_to`
+  pub fn collision_piece(&self, relative_to: Point) -> (Isometry2<Meters>, Shape2D) {
+    let along: Vec2 = self.b - self.a;
+    let mid = self.a + along / 2.0;
+    let angle = along.y.atan2(along.x);
+    let loc = Isometry2::new(mid - relative_to, angle);
+    let half_extents = Vector2::new(self.length() / 2.0, WALL_THICKNESS / 2.0);
+    (loc, ShapeHandle::new(CollisionRect::new(half_extents)))
+  }
+
+  pub fn translate(&mut self, by: Vec2) {
+    self.a += by;
+    self.b += by;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::level_rng::level_rng;
+  use crate::util::geom::{is_simple_ring, ring_area};
+
+  #[test]
+  fn test_outlines_fit_their_box() {
+    for seed in 0..20 {
+      let mut rng = level_rng(seed);
+      for &shape in RoomShape::outlined().iter().chain(Some(&RoomShape::Rect)) {
+        let outline = shape.outline(&mut rng, 6.0, 4.0);
+        let what = format!("{:?} with seed {}", shape, seed);
+        assert!(outline.len() >= 3, "{}", what);
+        assert!(is_simple_ring(&outline), "{}", what);
+        assert!(outline.iter().all(|p| p.x.abs() <= 3.001 && p.y.abs() <= 2.001), "{}", what);
+      }
+    }
+  }
+
+  #[test]
+  fn test_outlines_go_clockwise() {
+    for seed in 0..20 {
+      let mut rng = level_rng(seed);
+      for &shape in RoomShape::outlined() {
+        // Clockwise with y down is a positive shoelace area
+        let area = ring_area(&shape.outline(&mut rng, 6.0, 4.0));
+        assert!(area > 0.0, "{:?} with seed {}", shape, seed);
+      }
+    }
+  }
+
+  #[test]
+  fn test_segment_collision_piece_is_rotated() {
+    let seg = WallSegment::new(Point::new(1.0, 1.0), Point::new(3.0, 3.0));
+    let (loc, _) = seg.collision_piece(Point::new(1.0, 0.0));
+    assert!((loc.translation.vector - Vector2::new(1.0, 2.0)).norm() < 0.0001);
+    assert!((loc.rotation.angle() - PI / 4.0).abs() < 0.0001);
+  }
+}
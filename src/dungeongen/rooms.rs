@@ -1,10 +1,12 @@
 use super::direction::Direction;
+use super::gen_error::{GenError, GenStats};
+use super::room_shapes::{RoomShape, WallSegment};
 use crate::{
   collision::{CollGroups, Collidable, CollidableType, CollisionRect, Shape2D},
   dungeongen::level::Wall,
   dungeongen::level::WALL_THICKNESS,
-  util::geom::{CenterOriginRect, CenteredRect},
-  util::{Meters, Point},
+  util::geom::{ring_area, ring_contains, CenterOriginRect, CenteredRect},
+  util::{Meters, Point, Vec2},
 };
 use ggez::graphics::DrawMode;
 use ggez::graphics::Mesh;
@@ -18,17 +20,42 @@ use na::{Isometry2, Vector2};
 use nc::shape::{Compound, ShapeHandle};
 use nc::world::CollisionGroups;
 use rand::distributions::{Distribution, Normal};
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 pub static DOOR_WIDTH: Meters = 1.1;
 
-#[derive(Debug, CenterOriginRect, PartialEq)]
+/// What a room is used for, which decides how it gets furnished
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoomKind {
+  Storage,
+  Shrine,
+  Barracks,
+  Vault,
+  Library,
+}
+
+impl RoomKind {
+  pub fn all() -> &'static [RoomKind] {
+    static KINDS: [RoomKind; 5] =
+      [RoomKind::Storage, RoomKind::Shrine, RoomKind::Barracks, RoomKind::Vault, RoomKind::Library];
+    &KINDS
+  }
+}
+
+#[derive(Debug, Clone, CenterOriginRect, PartialEq)]
 pub struct Room {
   cr: CenteredRect,
   doors: Vec<Door>,
   /// Tuple of wall, and side of the room that wall belongs to
   pub walls: Vec<(Wall, Direction)>,
   is_compound: bool,
+  /// Assigned once the room has been placed
+  pub kind: Option<RoomKind>,
+  pub shape: RoomShape,
+  /// The room's corners in level space, going clockwise
+  outline: Vec<Point>,
+  /// Walls of non-rectangular rooms, which can be at any angle. Rectangular rooms use `walls`.
+  pub segments: Vec<WallSegment>,
 }
 
 impl Room {
@@ -36,29 +63,166 @@ impl Room {
     center: Point,
     width: Meters,
     height: Meters,
-    door: Option<Door>,
+    doors: Vec<Door>,
     is_compound: bool,
-  ) -> Result<Room, ()> {
+  ) -> Result<Room, GenError> {
     let cr: &CenterOriginRect = &CenteredRect::new(center, width, height);
-    let walls = match door {
-      None => cr.gen_walls(),
-      Some(door) => Room::gen_walls_with_door(cr, door, door.facing)?,
-    };
-    let doorvec = if let Some(d) = door { vec![d] } else { vec![] };
-    Ok(Room { cr: CenteredRect::new(center, width, height), doors: doorvec, walls, is_compound })
+    let door_sides: Vec<(Door, Direction)> = doors.iter().map(|&d| (d, d.facing)).collect();
+    let walls = Room::gen_walls_with_doors(cr, &door_sides)?;
+    let cr = CenteredRect::new(center, width, height);
+    let outline = cr.corners().to_vec();
+    Ok(Room {
+      cr,
+      doors,
+      walls,
+      is_compound,
+      kind: None,
+      shape: RoomShape::Rect,
+      outline,
+      segments: vec![],
+    })
+  }
+
+  /// Creates a room walled along `outline`, which is relative to `center`. Each of `doors` is the
+  /// edge starting at the corner it goes in, and an offset (between -1.0 and 1.0) that slides it
+  /// along that edge. Fails if an edge is too short to fit a door or already has one.
+  pub fn new_outlined(
+    center: Point,
+    shape: RoomShape,
+    outline: &[Point],
+    doors: Vec<(usize, f32)>,
+  ) -> Result<Room, GenError> {
+    let outline: Vec<Point> = outline.iter().map(|p| center + p.coords).collect();
+    let n = outline.len();
+    if n < 3 {
+      return Err(GenError::BadOutline { corners: n });
+    }
+    let mut door_edges: Vec<Option<Door>> = vec![None; n];
+    for (edge, offset) in doors {
+      if edge >= n {
+        return Err(GenError::NoSuchEdge { edge, edges: n });
+      }
+      let (a, b) = (outline[edge], outline[(edge + 1) % n]);
+      let edge_len = (b - a).norm();
+      let slack = (edge_len - DOOR_WIDTH) / 2.0 - WALL_THICKNESS;
+      if slack < 0.0 {
+        return Err(GenError::EdgeTooShort { edge, length: edge_len });
+      }
+      let along: Vec2 = (b - a) / edge_len;
+      let door_center = a + along * (edge_len / 2.0 + slack * offset);
+      // Outlines go clockwise, so the outside of the room is to the left of each edge
+      let facing = Direction::nearest_compass((along.y, -along.x));
+      if door_edges[edge].is_some() {
+        return Err(GenError::DoorObliteratesWall { side: facing });
+      }
+      door_edges[edge] = Some(Door::of_width(door_center, DOOR_WIDTH, facing));
+    }
+
+    // Edges with a door in them get split in two either side of it
+    let mut segments = vec![];
+    for (i, door) in door_edges.iter().enumerate() {
+      let (a, b) = (outline[i], outline[(i + 1) % n]);
+      match door {
+        Some(door) => {
+          let half_gap = (b - a).normalize() * DOOR_WIDTH / 2.0;
+          segments.push(WallSegment::new(a, door.center() - half_gap));
+          segments.push(WallSegment::new(door.center() + half_gap, b));
+        }
+        None => segments.push(WallSegment::new(a, b)),
+      }
+    }
+    let doors: Vec<Door> = door_edges.into_iter().flatten().collect();
+
+    let (mins, maxs) = outline.iter().fold((outline[0], outline[0]), |(mins, maxs), p| {
+      (Point::new(mins.x.min(p.x), mins.y.min(p.y)), Point::new(maxs.x.max(p.x), maxs.y.max(p.y)))
+    });
+    let cr = CenteredRect::new(na::center(&mins, &maxs), maxs.x - mins.x, maxs.y - mins.y);
+    Ok(Room { cr, doors, walls: vec![], is_compound: false, kind: None, shape, outline, segments })
+  }
+
+  /// The room's corners in level space, going clockwise
+  pub fn outline(&self) -> &[Point] {
+    &self.outline
+  }
+
+  pub fn doors(&self) -> &[Door] {
+    &self.doors
+  }
+
+  pub fn doors_mut(&mut self) -> &mut [Door] {
+    &mut self.doors
+  }
+
+  pub fn is_compound(&self) -> bool {
+    self.is_compound
+  }
+
+  /// Floor space inside the room's outline
+  pub fn area(&self) -> Meters {
+    ring_area(&self.outline).abs()
+  }
+
+  /// True if `p` is inside the room's outline
+  pub fn contains(&self, p: Point) -> bool {
+    ring_contains(&self.outline, p)
   }
 
-  /// Creates a new `Room` randomly placed somewhere in the provided range
-  pub fn new_rand((x_min, x_max): (Meters, Meters), (y_min, y_max): (Meters, Meters)) -> Room {
-    let mut rng = thread_rng();
+  /// Creates a new `Room` randomly placed somewhere in the provided range. Rectangular rooms get
+  /// between 1 and `max_doors` doors, which may share a side. `scale` shrinks (or grows) the
+  /// usual room size, though rooms never get too small to fit a door. Attempts that had to be
+  /// retried are tallied in `stats`.
+  pub fn new_rand<R: Rng>(
+    rng: &mut R,
+    (x_min, x_max): (Meters, Meters),
+    (y_min, y_max): (Meters, Meters),
+    max_doors: usize,
+    scale: f32,
+    stats: &mut GenStats,
+  ) -> Room {
     let c_x: f32 = rng.gen_range(x_min, x_max);
     let c_y: f32 = rng.gen_range(y_min, y_max);
-    let (room_w, room_h) = Room::rand_room_box();
-    let mut rng = thread_rng();
-    // Add a door somewhere along the room edge
-    let side = rng.choose(Direction::compass()).unwrap();
-    let door = Room::gen_rand_door(c_x, c_y, room_w, room_h, *side);
-    Room::new(Point::new(c_x, c_y), room_w, room_h, Some(door), false).unwrap()
+    let (room_w, room_h) = Room::rand_room_box(rng, scale);
+    // Half of the rooms get a fancier shape, as long as there's an edge that fits a door. Each
+    // door gets an edge to itself.
+    if rng.gen_bool(0.5) {
+      let shape = *rng.choose(RoomShape::outlined()).unwrap();
+      let outline = shape.outline(rng, room_w, room_h);
+      let mut door_edges: Vec<usize> = (0..outline.len())
+        .filter(|&i| {
+          let edge_len = (outline[(i + 1) % outline.len()] - outline[i]).norm();
+          edge_len >= DOOR_WIDTH + WALL_THICKNESS * 2.0
+        })
+        .collect();
+      if !door_edges.is_empty() {
+        rng.shuffle(&mut door_edges);
+        door_edges.truncate(rng.gen_range(1, max_doors.max(1) + 1));
+        let doors = door_edges.into_iter().map(|edge| (edge, rng.gen_range(-1.0, 1.0))).collect();
+        let center = Point::new(c_x, c_y);
+        match Room::new_outlined(center, shape, &outline, doors) {
+          Ok(room) => return room,
+          Err(e) => stats.record(&e),
+        }
+      }
+    }
+    // Scatter doors along the room edges. Doors that land on top of each other can't both fit, so
+    // try again with one fewer until they do. A single door always fits.
+    let center = Point::new(c_x, c_y);
+    let mut door_count = rng.gen_range(1, max_doors.max(1) + 1);
+    loop {
+      let doors = (0..door_count)
+        .map(|_| {
+          let side = *rng.choose(Direction::compass()).unwrap();
+          Room::gen_rand_door(rng, c_x, c_y, room_w, room_h, side)
+        })
+        .collect();
+      match Room::new(center, room_w, room_h, doors, false) {
+        Ok(room) => return room,
+        Err(e) => {
+          stats.record(&e);
+          door_count = (door_count - 1).max(1);
+        }
+      }
+    }
   }
 
   /// Creates a new `Room` with a door centered along the wall of the provided direction
@@ -67,20 +231,26 @@ impl Room {
     width: Meters,
     height: Meters,
     door_side: Direction,
-  ) -> Result<Room, ()> {
+  ) -> Result<Room, GenError> {
     let door = Room::gen_door(center.x, center.y, width, height, door_side, 0.0);
-    Room::new(center, width, height, Some(door), false)
+    Room::new(center, width, height, vec![door], false)
   }
 
   pub fn draw(&self, ctx: &mut Context, draw_param: &DrawParam) -> GameResult<()> {
     // TODO: Configurable door colors
-    let door_color = Color::new(0.9, 0.9, 0.9, 1.0);
+    let open_color = Color::new(0.9, 0.9, 0.9, 1.0);
+    let closed_color = Color::new(0.55, 0.35, 0.15, 1.0);
     for &(wall, _) in &self.walls {
       let r: Rect = (&wall as &CenterOriginRect).into();
       let r = Mesh::new_rectangle(ctx, DrawMode::fill(), r, draw_param.color)?;
       draw(ctx, &r, *draw_param)?;
     }
+    for seg in &self.segments {
+      let line = Mesh::new_line(ctx, &[seg.a, seg.b], WALL_THICKNESS, draw_param.color)?;
+      draw(ctx, &line, *draw_param)?;
+    }
     for door in &self.doors {
+      let door_color = if door.open { open_color } else { closed_color };
       let r: Rect = (door as &CenterOriginRect).into();
       let r = Mesh::new_rectangle(ctx, DrawMode::fill(), r, door_color)?;
       draw(ctx, &r, DrawParam::new().color(door_color))?;
@@ -114,6 +284,12 @@ impl Room {
       w.center.x += x;
       w.center.y += y;
     }
+    for p in self.outline.iter_mut() {
+      *p += Vec2::new(x, y);
+    }
+    for seg in self.segments.iter_mut() {
+      seg.translate(Vec2::new(x, y));
+    }
   }
 
   /// Adds a door to the room centered on the wall on the side of the given direction
@@ -129,15 +305,12 @@ impl Room {
     self.doors.push(new_door);
   }
 
-  fn rand_room_box() -> (Meters, Meters) {
+  fn rand_room_box<R: Rng>(rng: &mut R, scale: f32) -> (Meters, Meters) {
     // TODO: Configurable sizing parameters
-    let mut rng = thread_rng();
     let (room_w, room_h) = {
       let sizer = Normal::new(5.0, 3.0);
       let mut get_siz = || {
-        sizer
-          .sample(&mut rng)
-          .abs()
+        (sizer.sample(rng).abs() * f64::from(scale))
           // Rooms need to be big enough to fit a door, and a little wiggle room
           .max((DOOR_WIDTH * 2.0).into())
           .min(30.0) as Meters
@@ -147,8 +320,14 @@ impl Room {
     (room_w, room_h)
   }
 
-  fn gen_rand_door(c_x: f32, c_y: f32, room_w: f32, room_h: f32, side: Direction) -> Door {
-    let mut rng = thread_rng();
+  fn gen_rand_door<R: Rng>(
+    rng: &mut R,
+    c_x: f32,
+    c_y: f32,
+    room_w: f32,
+    room_h: f32,
+    side: Direction,
+  ) -> Door {
     let offset_mul: f32 = rng.gen_range(-1.0, 1.0);
     Room::gen_door(c_x, c_y, room_w, room_h, side, offset_mul)
   }
@@ -193,65 +372,69 @@ impl Room {
     rect: &CenterOriginRect,
     door: Door,
     door_side: Direction,
-  ) -> Result<Vec<(Wall, Direction)>, ()> {
-    let retme = rect.gen_walls();
-    retme
-      .into_iter()
-      .flat_map(|(wall, d)| {
-        let has_door = door_side == d;
-        if has_door {
-          let center = rect.center();
-          let width = rect.width();
-          let height = rect.height();
-          match d {
-            Direction::North | Direction::South => {
-              let yoffset = center.y + height / 2.0 * d.to_tup().1;
-              let s1_rt_edge = door.left_edge();
-              let s1_lf_edge = center.x - width / 2.0 - WALL_THICKNESS / 2.0;
-              let s1c = Point::new(s1_lf_edge + (s1_rt_edge - s1_lf_edge) / 2.0, yoffset);
-              let s2_rt_edge = center.x + width / 2.0 + WALL_THICKNESS / 2.0;
-              let s2_lf_edge = door.right_edge();
-              let s2c = Point::new(s2_lf_edge + (s2_rt_edge - s2_lf_edge) / 2.0, yoffset);
-              let side1 = Wall::new(s1c, s1_rt_edge - s1_lf_edge, WALL_THICKNESS);
-              let side2 = Wall::new(s2c, s2_rt_edge - s2_lf_edge, WALL_THICKNESS);
-              if side1.width() < 0.0
-                || side1.height() < 0.0
-                || side2.width() < 0.0
-                || side2.height() < 0.0
-              {
-                warn!("Error generating walls for room. Door would obliterate wall");
-                vec![Err(())]
-              } else {
-                vec![Ok((side1, d)), Ok((side2, d))]
-              }
-            }
-            _ => {
-              let xoffset = center.x + width / 2.0 * d.to_tup().0;
-              let s1_tp_edge = center.y - height / 2.0 - WALL_THICKNESS / 2.0;
-              let s1_bt_edge = door.top_edge();
-              let s1c = Point::new(xoffset, s1_tp_edge + (s1_bt_edge - s1_tp_edge) / 2.0);
-              let s2_tp_edge = door.bottom_edge();
-              let s2_bt_edge = center.y + height / 2.0 + WALL_THICKNESS / 2.0;
-              let s2c = Point::new(xoffset, s2_tp_edge + (s2_bt_edge - s2_tp_edge) / 2.0);
-              let side1 = Wall::new(s1c, WALL_THICKNESS, s1_bt_edge - s1_tp_edge);
-              let side2 = Wall::new(s2c, WALL_THICKNESS, s2_bt_edge - s2_tp_edge);
-              if side1.width() < 0.0
-                || side1.height() < 0.0
-                || side2.width() < 0.0
-                || side2.height() < 0.0
-              {
-                warn!("Error generating walls for room. Door would obliterate wall");
-                vec![Err(())]
-              } else {
-                vec![Ok((side1, d)), Ok((side2, d))]
-              }
-            }
+  ) -> Result<Vec<(Wall, Direction)>, GenError> {
+    Room::gen_walls_with_doors(rect, &[(door, door_side)])
+  }
+
+  /// Generates walls for the room with a gap for each door, paired with the side it goes in. A
+  /// side with several doors is split into a piece between each of them. Fails if doors overlap
+  /// each other or hang off the end of their wall.
+  pub fn gen_walls_with_doors(
+    rect: &CenterOriginRect,
+    doors: &[(Door, Direction)],
+  ) -> Result<Vec<(Wall, Direction)>, GenError> {
+    let center = rect.center();
+    let width = rect.width();
+    let height = rect.height();
+    let mut walls = vec![];
+    for (wall, d) in rect.gen_walls() {
+      let horizontal = match d {
+        Direction::North | Direction::South => true,
+        _ => false,
+      };
+      // The spans along the wall taken up by doors, in the same axis as the wall
+      let mut gaps: Vec<(Meters, Meters)> = doors
+        .iter()
+        .filter(|&&(_, side)| side == d)
+        .map(|(door, _)| {
+          if horizontal {
+            (door.left_edge(), door.right_edge())
+          } else {
+            (door.top_edge(), door.bottom_edge())
           }
-        } else {
-          vec![Ok((wall, d))]
+        })
+        .collect();
+      if gaps.is_empty() {
+        walls.push((wall, d));
+        continue;
+      }
+      gaps.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+      let (start, end) = if horizontal {
+        (center.x - width / 2.0, center.x + width / 2.0)
+      } else {
+        (center.y - height / 2.0, center.y + height / 2.0)
+      };
+      let (start, end) = (start - WALL_THICKNESS / 2.0, end + WALL_THICKNESS / 2.0);
+      let ends = gaps.iter().map(|g| g.0).chain(Some(end));
+      let starts = Some(start).into_iter().chain(gaps.iter().map(|g| g.1));
+      for (lo, hi) in starts.zip(ends) {
+        if hi < lo {
+          return Err(GenError::DoorObliteratesWall { side: d });
         }
-      })
-      .collect()
+        // Doors that butt up against each other or the corner leave nothing in between
+        if hi == lo {
+          continue;
+        }
+        let mid = lo + (hi - lo) / 2.0;
+        let piece = if horizontal {
+          Wall::new(Point::new(mid, wall.center().y), hi - lo, WALL_THICKNESS)
+        } else {
+          Wall::new(Point::new(wall.center().x, mid), WALL_THICKNESS, hi - lo)
+        };
+        walls.push((piece, d));
+      }
+    }
+    Ok(walls)
   }
 }
 
@@ -275,7 +458,8 @@ impl Collidable for Room {
       let loc = Isometry2::new(w.center().coords - self.center().coords, na::zero());
       (loc, ShapeHandle::new(cr))
     });
-    let whole_shape = Compound::new(shapes.collect());
+    let segments = self.segments.iter().map(|s| s.collision_piece(self.center()));
+    let whole_shape = Compound::new(shapes.chain(segments).collect());
     ShapeHandle::new(whole_shape)
   }
   fn collision_group(&self) -> CollisionGroups {
@@ -294,6 +478,9 @@ impl Collidable for Room {
 pub struct Door {
   cr: CenteredRect,
   facing: Direction,
+  /// Closed doors muffle sound going through them
+  #[new(value = "true")]
+  open: bool,
 }
 
 impl Door {
@@ -305,6 +492,14 @@ impl Door {
     };
     Door::new(CenteredRect::new(center, xsiz, ysiz), dir)
   }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  pub fn set_open(&mut self, open: bool) {
+    self.open = open;
+  }
 }
 
 // TESTS ================================================================================
@@ -312,6 +507,7 @@ impl Door {
 #[cfg(test)]
 mod test {
   use super::*;
+  use crate::dungeongen::level_rng::level_rng;
 
   #[test]
   fn test_wall_gen() {
@@ -351,4 +547,79 @@ mod test {
     );
     assert!(walls.contains(&(nw2, Direction::North)));
   }
+
+  #[test]
+  fn test_two_doors_same_side() {
+    let w = 10.0;
+    let rect = CenteredRect::new(Point::new(0.0, 0.0), w, 4.0);
+    let door1 = Room::gen_door(0.0, 0.0, w, 4.0, Direction::North, -0.5);
+    let door2 = Room::gen_door(0.0, 0.0, w, 4.0, Direction::North, 0.5);
+    // Order of the doors shouldn't matter
+    let sides = [(door2, Direction::North), (door1, Direction::North)];
+    let walls = Room::gen_walls_with_doors(&rect, &sides).unwrap();
+    assert_eq!(walls.len(), 6);
+    let north: Vec<Wall> =
+      walls.iter().filter(|(_, d)| *d == Direction::North).map(|&(w, _)| w).collect();
+    assert_eq!(north.len(), 3);
+    // The piece between the doors spans exactly the gap between them
+    let between = door2.left_edge() - door1.right_edge();
+    let mid = Wall::new(Point::new(0.0, -2.0), between, WALL_THICKNESS);
+    assert!(north.contains(&mid), "{:?}", north);
+    // And nothing covers either doorway
+    for door in &[door1, door2] {
+      assert!(north.iter().all(|w| w.right_edge() <= door.left_edge() + 0.0001
+        || w.left_edge() >= door.right_edge() - 0.0001));
+    }
+  }
+
+  #[test]
+  fn test_overlapping_doors_fail() {
+    let rect = CenteredRect::new(Point::new(0.0, 0.0), 4.0, 4.0);
+    let door1 = Room::gen_door(0.0, 0.0, 4.0, 4.0, Direction::East, 0.0);
+    let door2 = Room::gen_door(0.0, 0.0, 4.0, 4.0, Direction::East, 0.2);
+    let sides = [(door1, Direction::East), (door2, Direction::East)];
+    assert!(Room::gen_walls_with_doors(&rect, &sides).is_err());
+  }
+
+  #[test]
+  fn test_doors_need_a_real_edge() {
+    let outline = RoomShape::Circle.outline(&mut level_rng(1), 12.0, 12.0);
+    let n = outline.len();
+    let err = Room::new_outlined(Point::new(0.0, 0.0), RoomShape::Circle, &outline, vec![(n, 0.0)]);
+    assert_eq!(err.unwrap_err(), GenError::NoSuchEdge { edge: n, edges: n });
+  }
+
+  #[test]
+  fn test_rand_rooms_door_counts() {
+    let mut rng = level_rng(1);
+    let mut stats = GenStats::default();
+    for _ in 0..50 {
+      let room = Room::new_rand(&mut rng, (0.0, 10.0), (0.0, 10.0), 4, 1.0, &mut stats);
+      assert!(room.doors.len() >= 1 && room.doors.len() <= 4);
+      if room.shape == RoomShape::Rect {
+        assert!(room.walls.len() >= 4);
+      }
+    }
+  }
+
+  #[test]
+  fn test_shaped_rooms_get_several_doors() {
+    let outline = RoomShape::L.outline(&mut level_rng(1), 8.0, 6.0);
+    let center = Point::new(0.0, 0.0);
+    let room =
+      Room::new_outlined(center, RoomShape::L, &outline, vec![(0, 0.0), (1, 0.5)]).unwrap();
+    assert_eq!(room.doors.len(), 2);
+    // Both door edges are split around their doorway
+    assert_eq!(room.segments.len(), outline.len() + 2);
+    assert!(Room::new_outlined(center, RoomShape::L, &outline, vec![(0, -0.5), (0, 0.5)]).is_err());
+
+    let mut rng = level_rng(1);
+    let mut stats = GenStats::default();
+    let shaped: Vec<Room> = (0..200)
+      .map(|_| Room::new_rand(&mut rng, (0.0, 10.0), (0.0, 10.0), 4, 1.0, &mut stats))
+      .filter(|r| r.shape != RoomShape::Rect)
+      .collect();
+    assert!(shaped.iter().all(|r| r.doors.len() >= 1 && r.doors.len() <= 4));
+    assert!(shaped.iter().any(|r| r.doors.len() > 1));
+  }
 }
@@ -0,0 +1,100 @@
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Picks an index into `weights` with chances in proportion to them, or `None` if they're all 0
+pub fn weighted_pick<R: Rng>(rng: &mut R, weights: &[f32]) -> Option<usize> {
+  let total: f32 = weights.iter().sum();
+  if total <= 0.0 {
+    return None;
+  }
+  let mut left = rng.gen_range(0.0, total);
+  let ix = weights.iter().position(|&w| {
+    left -= w;
+    left < 0.0
+  });
+  // Rounding can leave a sliver past the last entry
+  ix.or_else(|| weights.iter().rposition(|&w| w > 0.0))
+}
+
+/// Reads named tables from sections like `[name min-max]`, each followed by entries like
+/// `name = rest`. `new_table` starts a table from the range in its header, and `add_entry` adds an
+/// entry's name and the rest of its line to it. Blank lines and lines starting with `#` are
+/// skipped, and errors say which line they're on.
+pub fn parse_sections<T>(
+  config: &str,
+  new_table: impl Fn((usize, usize)) -> T,
+  mut add_entry: impl FnMut(&mut T, &str, &str) -> Result<(), String>,
+) -> Result<BTreeMap<String, T>, String> {
+  let mut tables = BTreeMap::new();
+  let mut current: Option<(String, T)> = None;
+  for (ix, line) in config.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let err = |e: String| format!("line {}: {}", ix + 1, e);
+    if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+      tables.extend(current.take());
+      let (name, range) = match header.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        [name, range] => (name.to_string(), parse_range(range)),
+        _ => (String::new(), None),
+      };
+      let range = range.ok_or_else(|| err("expected [name min-max]".to_string()))?;
+      current = Some((name, new_table(range)));
+      continue;
+    }
+    let table = match &mut current {
+      Some((_, table)) => table,
+      None => return Err(err("entry before any [table]".to_string())),
+    };
+    let mut halves = line.splitn(2, '=');
+    match (halves.next(), halves.next()) {
+      (Some(name), Some(rest)) => add_entry(table, name.trim(), rest).map_err(err)?,
+      _ => return Err(err("expected name = entry".to_string())),
+    }
+  }
+  tables.extend(current);
+  Ok(tables)
+}
+
+/// Reads a range like `1-3`
+pub fn parse_range<T: FromStr + PartialOrd>(range: &str) -> Option<(T, T)> {
+  let mut ends = range.splitn(2, '-');
+  match (ends.next()?.parse(), ends.next()?.parse()) {
+    (Ok(min), Ok(max)) if min <= max => Some((min, max)),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_sections() {
+    let config = "# Comment\n[a 1-2]\nx = 1\n\n[b 3-3]\ny = 2\nz = 3";
+    let tables = parse_sections(
+      config,
+      |range| (range, vec![]),
+      |table, name, rest| {
+        table.1.push((name.to_string(), rest.trim().to_string()));
+        Ok(())
+      },
+    )
+    .unwrap();
+    assert_eq!(tables["a"], ((1, 2), vec![("x".to_string(), "1".to_string())]));
+    assert_eq!(tables["b"].1.len(), 2);
+    let fail = |_: &mut (), _: &str, _: &str| Err("nope".to_string());
+    assert_eq!(parse_sections("[a 0-1]\n\nx = 1", |_| (), fail).unwrap_err(), "line 3: nope");
+    assert!(parse_sections("x = 1", |_| (), fail).is_err());
+    assert!(parse_sections("[a 1]", |_| (), fail).is_err());
+  }
+
+  #[test]
+  fn test_ranges() {
+    assert_eq!(parse_range::<u32>("1-3"), Some((1, 3)));
+    assert_eq!(parse_range::<u32>("3-1"), None);
+    assert_eq!(parse_range::<u32>("3"), None);
+  }
+}
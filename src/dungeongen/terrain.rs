@@ -0,0 +1,204 @@
+use crate::util::{Point, Vec2};
+use ggez::graphics::{Color, DrawParam, Drawable, FilterMode, Image};
+use ggez::{Context, GameResult};
+use rand::Rng;
+
+/// What the cave floor is made of at some spot
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Terrain {
+  Stone,
+  Moss,
+  Mud,
+  Water,
+  Lava,
+}
+
+/// How each kind of terrain gets laid down over the stone. Later layers are grown over the top of
+/// earlier ones. The fill is the chance a cell starts off live before smoothing, so lower values
+/// end up as fewer, smaller patches.
+static LAYERS: [(Terrain, f64); 4] =
+  [(Terrain::Moss, 0.47), (Terrain::Mud, 0.42), (Terrain::Water, 0.4), (Terrain::Lava, 0.34)];
+/// How many rounds of smoothing each layer gets, which clumps the random cells into patches
+static SMOOTHING_PASSES: usize = 5;
+
+impl Terrain {
+  pub fn color(self) -> Color {
+    match self {
+      Terrain::Stone => Color::new(0.5, 0.5, 0.5, 1.0),
+      Terrain::Moss => Color::new(0.3, 0.55, 0.25, 1.0),
+      Terrain::Mud => Color::new(0.45, 0.33, 0.2, 1.0),
+      Terrain::Water => Color::new(0.2, 0.35, 0.7, 1.0),
+      Terrain::Lava => Color::new(0.9, 0.3, 0.05, 1.0),
+    }
+  }
+
+  /// How much effort it takes to cross this terrain compared to bare stone
+  pub fn move_cost(self) -> f32 {
+    match self {
+      Terrain::Stone | Terrain::Moss => 1.0,
+      Terrain::Mud => 2.0,
+      Terrain::Water => 3.0,
+      Terrain::Lava => 1.5,
+    }
+  }
+
+  /// Damage per second done to anything standing on this terrain
+  pub fn hazard(self) -> f32 {
+    match self {
+      Terrain::Lava => 10.0,
+      _ => 0.0,
+    }
+  }
+}
+
+/// A terrain value for every cell of the cave sim's grid. Cells outside the cave have no terrain.
+#[derive(Clone)]
+pub struct TerrainMap {
+  cells: Vec<Vec<Option<Terrain>>>,
+  /// Size of one cell in level space
+  cell_dims: Vec2,
+}
+
+impl Default for TerrainMap {
+  fn default() -> Self {
+    TerrainMap { cells: vec![], cell_dims: Vec2::new(1.0, 1.0) }
+  }
+}
+
+impl TerrainMap {
+  /// Lays terrain over every live cell of `cave`, a grid that covers a level of size `level_dims`
+  pub fn generate<R: Rng>(rng: &mut R, cave: &[Vec<bool>], level_dims: Vec2) -> TerrainMap {
+    let (width, height) = (cave.len(), cave.first().map_or(0, |c| c.len()));
+    let mut cells: Vec<Vec<Option<Terrain>>> = cave
+      .iter()
+      .map(|col| col.iter().map(|&live| if live { Some(Terrain::Stone) } else { None }).collect())
+      .collect();
+    for &(terrain, fill) in LAYERS.iter() {
+      let patches = grow_patches(rng, width, height, fill);
+      for (col, patch_col) in cells.iter_mut().zip(patches) {
+        for (cell, in_patch) in col.iter_mut().zip(patch_col) {
+          if in_patch && cell.is_some() {
+            *cell = Some(terrain);
+          }
+        }
+      }
+    }
+    let cell_dims = Vec2::new(level_dims.x / width as f32, level_dims.y / height as f32);
+    TerrainMap { cells, cell_dims }
+  }
+
+  /// A map that's `width` by `height` cells of nothing but `terrain`
+  #[cfg(test)]
+  pub fn filled(terrain: Terrain, width: usize, height: usize, cell_dims: Vec2) -> TerrainMap {
+    TerrainMap { cells: vec![vec![Some(terrain); height]; width], cell_dims }
+  }
+
+  /// The terrain at a level space point, if it's inside the cave
+  pub fn at(&self, p: Point) -> Option<Terrain> {
+    // Cells are centered on their coordinates, same as the cave outlines
+    let x = (p.x / self.cell_dims.x).round();
+    let y = (p.y / self.cell_dims.y).round();
+    if x < 0.0 || y < 0.0 {
+      return None;
+    }
+    self.cells.get(x as usize).and_then(|col| col.get(y as usize)).and_then(|t| *t)
+  }
+
+  /// Draws the terrain in level space
+  pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+    let (width, height) = (self.cells.len(), self.cells.first().map_or(0, |c| c.len()));
+    if width == 0 || height == 0 {
+      return Ok(());
+    }
+    let mut rgba = vec![0u8; width * height * 4];
+    for (x, col) in self.cells.iter().enumerate() {
+      for (y, cell) in col.iter().enumerate() {
+        if let Some(terrain) = cell {
+          let i = (width * y + x) * 4;
+          let (r, g, b, a) = terrain.color().to_rgba();
+          rgba[i..i + 4].copy_from_slice(&[r, g, b, a]);
+        }
+      }
+    }
+    let mut img = Image::from_rgba8(ctx, width as u16, height as u16, &rgba)?;
+    img.set_filter(FilterMode::Nearest);
+    let param = DrawParam {
+      dest: Point::from(-self.cell_dims / 2.0).into(),
+      scale: self.cell_dims.into(),
+      ..DrawParam::default()
+    };
+    img.draw(ctx, param)
+  }
+}
+
+/// Scatters live cells randomly and then smooths them out into blobby patches. A cell ends up
+/// live when most of its neighbours are.
+fn grow_patches<R: Rng>(rng: &mut R, width: usize, height: usize, fill: f64) -> Vec<Vec<bool>> {
+  let mut grid: Vec<Vec<bool>> =
+    (0..width).map(|_| (0..height).map(|_| rng.gen_bool(fill)).collect()).collect();
+  for _ in 0..SMOOTHING_PASSES {
+    let mut next = grid.clone();
+    for x in 0..width {
+      for y in 0..height {
+        let mut live_neighbors = 0;
+        for nx in x.saturating_sub(1)..(x + 2).min(width) {
+          for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            if (nx, ny) != (x, y) && grid[nx][ny] {
+              live_neighbors += 1;
+            }
+          }
+        }
+        next[x][y] = live_neighbors >= 5 || (grid[x][y] && live_neighbors == 4);
+      }
+    }
+    grid = next;
+  }
+  grid
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::level_rng::level_rng;
+
+  #[test]
+  fn test_terrain_only_inside_cave() {
+    let mut cave = vec![vec![false; 20]; 20];
+    for col in cave.iter_mut().take(15).skip(5) {
+      for cell in col.iter_mut().take(15).skip(5) {
+        *cell = true;
+      }
+    }
+    let map = TerrainMap::generate(&mut level_rng(1), &cave, Vec2::new(10.0, 10.0));
+    // Cells are half a meter across
+    assert!(map.at(Point::new(5.0, 5.0)).is_some());
+    assert!(map.at(Point::new(1.0, 1.0)).is_none());
+    assert!(map.at(Point::new(-1.0, 5.0)).is_none());
+    assert!(map.at(Point::new(50.0, 5.0)).is_none());
+  }
+
+  #[test]
+  fn test_big_caves_get_varied_terrain() {
+    let map =
+      TerrainMap::generate(&mut level_rng(1), &vec![vec![true; 100]; 100], Vec2::new(50.0, 50.0));
+    let mut seen = vec![];
+    for x in 0..100 {
+      for y in 0..100 {
+        let t = map.at(Point::new(x as f32 / 2.0, y as f32 / 2.0)).unwrap();
+        if !seen.contains(&t) {
+          seen.push(t);
+        }
+      }
+    }
+    assert!(seen.len() >= 3, "{:?}", seen);
+  }
+
+  #[test]
+  fn test_only_lava_hurts() {
+    for t in &[Terrain::Stone, Terrain::Moss, Terrain::Mud, Terrain::Water] {
+      assert_eq!(t.hazard(), 0.0);
+      assert!(t.move_cost() >= 1.0);
+    }
+    assert!(Terrain::Lava.hazard() > 0.0);
+  }
+}
@@ -0,0 +1,98 @@
+use super::Item;
+use crate::collision::{CollGroups, Collidable, CollidableType, Shape2D};
+use crate::dungeongen::level::WALL_THICKNESS;
+use crate::util::geom::CenterOriginRect;
+use crate::util::{Meters, Point, Vec2};
+use ggez::graphics::{draw, DrawMode, DrawParam, Mesh};
+use ggez::{Context, GameResult};
+use na::Isometry2;
+use nc::shape::{Ball, ShapeHandle};
+use nc::world::CollisionGroups;
+use rand::Rng;
+
+pub static ITEM_RADIUS: Meters = 0.25;
+
+/// An item lying on the floor, waiting to be picked up. Walkers step right over these.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloorItem {
+  pub item: Item,
+  pub pos: Point,
+}
+
+impl FloorItem {
+  pub fn new(item: Item, pos: Point) -> FloorItem {
+    FloorItem { item, pos }
+  }
+
+  /// Randomly scatters `items` inside `room`. Like furniture layouts, they still need to be checked
+  /// against everything else in the room.
+  pub fn rand_scatter<R: Rng>(
+    rng: &mut R,
+    items: Vec<Item>,
+    room: &CenterOriginRect,
+  ) -> Vec<FloorItem> {
+    let x_room = room.width() / 2.0 - ITEM_RADIUS - WALL_THICKNESS;
+    let y_room = room.height() / 2.0 - ITEM_RADIUS - WALL_THICKNESS;
+    if x_room <= 0.0 || y_room <= 0.0 {
+      return vec![];
+    }
+    items
+      .into_iter()
+      .map(|item| {
+        let pos =
+          room.center() + Vec2::new(rng.gen_range(-x_room, x_room), rng.gen_range(-y_room, y_room));
+        FloorItem::new(item, pos)
+      })
+      .collect()
+  }
+
+  /// Corners of the square the item sits in
+  pub fn corners(&self) -> [Point; 4] {
+    let (x, y, r) = (self.pos.x, self.pos.y, ITEM_RADIUS);
+    [
+      Point::new(x - r, y - r),
+      Point::new(x + r, y - r),
+      Point::new(x + r, y + r),
+      Point::new(x - r, y + r),
+    ]
+  }
+
+  pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+    let dot =
+      Mesh::new_circle(ctx, DrawMode::fill(), self.pos, ITEM_RADIUS, 0.01, self.item.color())?;
+    draw(ctx, &dot, DrawParam::new())
+  }
+}
+
+impl Collidable for FloorItem {
+  fn location(&self) -> Isometry2<Meters> {
+    Isometry2::new(self.pos.coords, na::zero())
+  }
+  fn shape(&self) -> Shape2D {
+    ShapeHandle::new(Ball::new(ITEM_RADIUS))
+  }
+  fn collision_group(&self) -> CollisionGroups {
+    CollGroups::item_cg()
+  }
+  fn coltype(&self) -> CollidableType {
+    CollidableType::Item
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::level_rng::level_rng;
+  use crate::util::geom::CenteredRect;
+
+  #[test]
+  fn test_scatter_stays_inside_room() {
+    let room = CenteredRect::new(Point::new(-4.0, 1.0), 3.0, 2.0);
+    let items = vec![Item::named("bread").unwrap(); 20];
+    let scattered = FloorItem::rand_scatter(&mut level_rng(1), items, &room);
+    assert_eq!(scattered.len(), 20);
+    for item in scattered {
+      assert!(item.corners().iter().all(|&c| (&room as &CenterOriginRect).contains(c)));
+    }
+  }
+}
@@ -0,0 +1,164 @@
+use super::{EquipSlot, Item, Kg};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InventoryError {
+  /// Every slot is taken
+  Full,
+  /// Carrying it would go over the weight limit
+  TooHeavy,
+  NoSuchItem,
+  /// It can't be equipped, eaten or drunk
+  Unusable,
+}
+
+impl fmt::Display for InventoryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let msg = match self {
+      InventoryError::Full => "No room left to carry that",
+      InventoryError::TooHeavy => "That's too heavy to carry as well",
+      InventoryError::NoSuchItem => "Nothing is in that slot",
+      InventoryError::Unusable => "That can't be used",
+    };
+    write!(f, "{}", msg)
+  }
+}
+
+/// Everything an agent carries, limited by how many items and how much weight they can manage.
+/// Equipped items are still carried, they're just marked as being worn or held.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inventory {
+  /// Each item along with whether it's equipped
+  items: Vec<(Item, bool)>,
+  max_items: usize,
+  max_weight: Kg,
+}
+
+impl Inventory {
+  pub fn new(max_items: usize, max_weight: Kg) -> Inventory {
+    Inventory { items: Vec::new(), max_items, max_weight }
+  }
+
+  pub fn add(&mut self, item: Item) -> Result<(), InventoryError> {
+    if self.items.len() >= self.max_items {
+      return Err(InventoryError::Full);
+    }
+    if self.weight() + item.weight > self.max_weight {
+      return Err(InventoryError::TooHeavy);
+    }
+    self.items.push((item, false));
+    Ok(())
+  }
+
+  /// Takes out the item at `ix`, unequipping it if need be. Later items move up a slot.
+  pub fn remove(&mut self, ix: usize) -> Option<Item> {
+    if ix < self.items.len() {
+      Some(self.items.remove(ix).0)
+    } else {
+      None
+    }
+  }
+
+  pub fn get(&self, ix: usize) -> Option<&Item> {
+    self.items.get(ix).map(|(item, _)| item)
+  }
+
+  /// Every item carried and whether it's equipped, in the order they were picked up
+  pub fn items(&self) -> impl Iterator<Item = (&Item, bool)> {
+    self.items.iter().map(|(item, equipped)| (item, *equipped))
+  }
+
+  pub fn weight(&self) -> Kg {
+    self.items.iter().map(|(item, _)| item.weight).sum()
+  }
+
+  pub fn max_weight(&self) -> Kg {
+    self.max_weight
+  }
+
+  /// Equips the item at `ix`, taking off whatever was in its slot before
+  pub fn equip(&mut self, ix: usize) -> Result<(), InventoryError> {
+    let slot = self.get(ix).ok_or(InventoryError::NoSuchItem)?.slot();
+    let slot = slot.ok_or(InventoryError::Unusable)?;
+    for (item, equipped) in &mut self.items {
+      if item.slot() == Some(slot) {
+        *equipped = false;
+      }
+    }
+    self.items[ix].1 = true;
+    Ok(())
+  }
+
+  pub fn unequip(&mut self, ix: usize) -> Result<(), InventoryError> {
+    let (_, equipped) = self.items.get_mut(ix).ok_or(InventoryError::NoSuchItem)?;
+    *equipped = false;
+    Ok(())
+  }
+
+  pub fn is_equipped(&self, ix: usize) -> bool {
+    self.items.get(ix).is_some_and(|(_, equipped)| *equipped)
+  }
+
+  /// What's equipped in `slot`, if anything
+  pub fn equipped(&self, slot: EquipSlot) -> Option<&Item> {
+    self.equipped_items().find(|item| item.slot() == Some(slot))
+  }
+
+  /// Attack added by everything equipped
+  pub fn attack_bonus(&self) -> u32 {
+    self.equipped_items().map(|item| item.attack_bonus()).sum()
+  }
+
+  /// Defense added by everything equipped
+  pub fn defense_bonus(&self) -> u32 {
+    self.equipped_items().map(|item| item.defense_bonus()).sum()
+  }
+
+  fn equipped_items(&self) -> impl Iterator<Item = &Item> {
+    self.items.iter().filter(|(_, equipped)| *equipped).map(|(item, _)| item)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn item(name: &str) -> Item {
+    Item::named(name).unwrap()
+  }
+
+  #[test]
+  fn test_limits() {
+    let mut inv = Inventory::new(2, 10.0);
+    assert_eq!(inv.add(item("chainmail")), Err(InventoryError::TooHeavy));
+    inv.add(item("sword")).unwrap();
+    inv.add(item("bread")).unwrap();
+    assert_eq!(inv.add(item("iron key")), Err(InventoryError::Full));
+    assert_eq!(inv.remove(0), Some(item("sword")));
+    assert_eq!(inv.get(0), Some(&item("bread")));
+    assert!((inv.weight() - 0.3).abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_one_item_per_slot() {
+    let mut inv = Inventory::new(10, 100.0);
+    for name in &["dagger", "axe", "helmet", "bread"] {
+      inv.add(item(name)).unwrap();
+    }
+    inv.equip(0).unwrap();
+    inv.equip(2).unwrap();
+    assert_eq!(inv.attack_bonus(), 2);
+    assert_eq!(inv.defense_bonus(), 1);
+    // Swapping weapons takes the dagger off
+    inv.equip(1).unwrap();
+    assert!(!inv.is_equipped(0));
+    assert_eq!(inv.equipped(EquipSlot::Weapon), Some(&item("axe")));
+    assert_eq!(inv.attack_bonus(), 6);
+    assert_eq!(inv.equip(3), Err(InventoryError::Unusable));
+    assert_eq!(inv.equip(9), Err(InventoryError::NoSuchItem));
+    // Dropping an equipped item takes it off too
+    inv.remove(1);
+    assert_eq!(inv.attack_bonus(), 0);
+    assert_eq!(inv.equipped(EquipSlot::Head), Some(&item("helmet")));
+  }
+}
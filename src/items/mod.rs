@@ -0,0 +1,112 @@
+use ggez::graphics::Color;
+use std::fmt;
+
+pub mod floor_item;
+pub mod inventory;
+
+/// Kilograms
+pub type Kg = f32;
+
+/// Where an equipped item is worn or held. Only one item can be in each slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EquipSlot {
+  Weapon,
+  Head,
+  Body,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ItemKind {
+  Weapon {
+    attack: u32,
+  },
+  Armour {
+    slot: EquipSlot,
+    defense: u32,
+  },
+  /// Used up to get back some health
+  Consumable {
+    heal: u32,
+  },
+  Key,
+}
+
+/// Something that can be picked up and carried around
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Item {
+  pub name: &'static str,
+  pub kind: ItemKind,
+  pub weight: Kg,
+}
+
+/// Every item in the game
+static CATALOGUE: &[Item] = &[
+  Item { name: "dagger", kind: ItemKind::Weapon { attack: 2 }, weight: 1.0 },
+  Item { name: "sword", kind: ItemKind::Weapon { attack: 4 }, weight: 3.0 },
+  Item { name: "axe", kind: ItemKind::Weapon { attack: 6 }, weight: 6.0 },
+  Item {
+    name: "leather armour",
+    kind: ItemKind::Armour { slot: EquipSlot::Body, defense: 2 },
+    weight: 5.0,
+  },
+  Item {
+    name: "chainmail",
+    kind: ItemKind::Armour { slot: EquipSlot::Body, defense: 4 },
+    weight: 12.0,
+  },
+  Item {
+    name: "helmet",
+    kind: ItemKind::Armour { slot: EquipSlot::Head, defense: 1 },
+    weight: 2.0,
+  },
+  Item { name: "health potion", kind: ItemKind::Consumable { heal: 8 }, weight: 0.5 },
+  Item { name: "bread", kind: ItemKind::Consumable { heal: 3 }, weight: 0.3 },
+  Item { name: "iron key", kind: ItemKind::Key, weight: 0.1 },
+];
+
+impl Item {
+  /// Looks an item up in the catalogue
+  pub fn named(name: &str) -> Option<Item> {
+    CATALOGUE.iter().find(|i| i.name == name).cloned()
+  }
+
+  /// The slot this item goes in when equipped, if it can be equipped at all
+  pub fn slot(&self) -> Option<EquipSlot> {
+    match self.kind {
+      ItemKind::Weapon { .. } => Some(EquipSlot::Weapon),
+      ItemKind::Armour { slot, .. } => Some(slot),
+      _ => None,
+    }
+  }
+
+  /// Attack added while this is equipped
+  pub fn attack_bonus(&self) -> u32 {
+    match self.kind {
+      ItemKind::Weapon { attack } => attack,
+      _ => 0,
+    }
+  }
+
+  /// Defense added while this is equipped
+  pub fn defense_bonus(&self) -> u32 {
+    match self.kind {
+      ItemKind::Armour { defense, .. } => defense,
+      _ => 0,
+    }
+  }
+
+  pub fn color(&self) -> Color {
+    match self.kind {
+      ItemKind::Weapon { .. } => Color::new(0.75, 0.75, 0.8, 1.0),
+      ItemKind::Armour { .. } => Color::new(0.35, 0.5, 0.75, 1.0),
+      ItemKind::Consumable { .. } => Color::new(0.85, 0.2, 0.5, 1.0),
+      ItemKind::Key => Color::new(0.95, 0.8, 0.2, 1.0),
+    }
+  }
+}
+
+impl fmt::Display for Item {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} ({}kg)", self.name, self.weight)
+  }
+}
@@ -15,7 +15,9 @@ extern crate core;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate gif;
 
+use crate::dungeongen::level::Level;
 use crate::world::render::WorldRender;
 use crate::world::World;
 use env_logger::{Builder, Env};
@@ -24,6 +26,7 @@ use ggez::{conf, event, ContextBuilder};
 mod agents;
 mod collision;
 mod dungeongen;
+mod items;
 mod util;
 mod world;
 
@@ -31,6 +34,20 @@ fn main() {
   // Set default log level to warn for everything, and info for our code
   Builder::from_env(Env::default().default_filter_or("warn,rustlike=info")).init();
 
+  // `--gen-stats [count]` generates levels without opening a window and prints their stats
+  let args: Vec<String> = std::env::args().collect();
+  if let Some(ix) = args.iter().position(|a| a == "--gen-stats") {
+    let count = args.get(ix + 1).and_then(|c| c.parse().ok()).unwrap_or(1);
+    for _ in 0..count {
+      let mut level = Level::new();
+      while !level.level_gen_finished {
+        level.tick_level_gen();
+      }
+      println!("{}", level.stats());
+    }
+    return;
+  }
+
   let cb = ContextBuilder::new("rougelike", "ggez")
     .window_setup(
       // TODO: Enable this and implement a fixed-ratio black bars solution or something
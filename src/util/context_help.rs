@@ -14,11 +14,10 @@ pub trait ContextHelp {
   fn screen_x(&self) -> f32;
   fn screen_y(&self) -> f32;
   fn screen_middle(&self) -> Point;
-  fn sscale(&self) -> DrawParam;
-  fn uspace_to_sspace(&self, p: Point) -> Point;
-  fn sspace_to_uspace(&self, p: Point) -> Point;
+  fn screen_dims(&self) -> Vec2;
   fn center_rect(&mut self, center: Point, w: Meters, h: Meters, color: Color) -> GameResult<()>;
   fn draw_bb(&mut self, bb: &AABB<Meters>) -> GameResult<()>;
+  fn draw_polyline(&mut self, points: &[Point]) -> GameResult<()>;
 }
 
 impl ContextHelp for Context {
@@ -34,22 +33,8 @@ impl ContextHelp for Context {
     Point::new(self.screen_x() / 2.0, self.screen_y() / 2.0)
   }
 
-  fn sscale(&self) -> DrawParam {
-    DrawParam {
-      scale: Vec2::new(self.screen_x(), self.screen_y()).into(),
-      dest: self.screen_middle().into(),
-      ..Default::default()
-    }
-  }
-
-  fn uspace_to_sspace(&self, p: Point) -> Point {
-    let sx = self.screen_x();
-    let sy = self.screen_y();
-    Point::new(p.x * sx, p.y * sy)
-  }
-
-  fn sspace_to_uspace(&self, p: Point) -> Point {
-    Point::new(p.x / self.screen_x(), p.y / self.screen_y())
+  fn screen_dims(&self) -> Vec2 {
+    Vec2::new(self.screen_x(), self.screen_y())
   }
 
   fn center_rect(&mut self, c: Point, w: Meters, h: Meters, color: Color) -> GameResult<()> {
@@ -63,4 +48,12 @@ impl ContextHelp for Context {
     let bw = bb.maxs().x - bb.mins().x;
     self.center_rect(bb.center(), bw, bh, Color::new(0.0, 1.0, 0.0, 0.5))
   }
+
+  fn draw_polyline(&mut self, points: &[Point]) -> GameResult<()> {
+    if points.len() < 2 {
+      return Ok(());
+    }
+    let line = Mesh::new_line(self, points, 0.1, Color::new(0.0, 1.0, 0.0, 0.5))?;
+    draw(self, &line, DrawParam::new())
+  }
 }
@@ -6,7 +6,7 @@ use crate::na;
 use crate::na::{Isometry2, Vector2};
 use crate::nc::shape::ShapeHandle;
 use crate::nc::world::CollisionGroups;
-use crate::util::{Meters, Point};
+use crate::util::{Meters, Point, Vec2};
 use ggez::graphics::Rect;
 
 pub trait CenterOriginRect {
@@ -28,6 +28,40 @@ pub trait CenterOriginRect {
 }
 
 impl<'a> CenterOriginRect + 'a {
+  /// True if `p` is inside the rect, or on its edge
+  pub fn contains(&self, p: Point) -> bool {
+    p.x >= self.left_edge()
+      && p.x <= self.right_edge()
+      && p.y >= self.top_edge()
+      && p.y <= self.bottom_edge()
+  }
+
+  /// True if the segment from `a` to `b` passes through the rect, or starts or ends inside it
+  pub fn crossed_by(&self, a: Point, b: Point) -> bool {
+    let corners = self.corners();
+    self.contains(a)
+      || self.contains(b)
+      || (0..4).any(|i| segment_intersection(a, b, corners[i], corners[(i + 1) % 4]).is_some())
+  }
+
+  /// True if the two rects share any area. Rects that only touch along an edge don't overlap.
+  pub fn overlaps(&self, other: &CenterOriginRect) -> bool {
+    self.left_edge() < other.right_edge()
+      && other.left_edge() < self.right_edge()
+      && self.top_edge() < other.bottom_edge()
+      && other.top_edge() < self.bottom_edge()
+  }
+
+  /// Corners of the rect, clockwise from the top left
+  pub fn corners(&self) -> [Point; 4] {
+    [
+      Point::new(self.left_edge(), self.top_edge()),
+      Point::new(self.right_edge(), self.top_edge()),
+      Point::new(self.right_edge(), self.bottom_edge()),
+      Point::new(self.left_edge(), self.bottom_edge()),
+    ]
+  }
+
   /// Generates walls for the rect. Walls are `WALL_THICKNESS` thick
   pub fn gen_walls(&self) -> Vec<(Wall, Direction)> {
     let mut retme = vec![];
@@ -114,6 +148,268 @@ impl Into<Vector2<Meters>> for PolarVec {
   }
 }
 
+/// A polygon made of an outer ring, with some number of inner rings cut out of it. Rings may or
+/// may not repeat their first point at the end.
+#[derive(new, Debug, PartialEq, Clone, Default)]
+pub struct PolygonWithHoles {
+  pub outer: Vec<Point>,
+  pub holes: Vec<Vec<Point>>,
+}
+
+impl PolygonWithHoles {
+  /// True if the point is inside the outer ring but not inside any of the holes
+  pub fn contains(&self, p: Point) -> bool {
+    ring_contains(&self.outer, p) && !self.holes.iter().any(|h| ring_contains(h, p))
+  }
+
+  pub fn map_points(&self, f: impl Fn(Point) -> Point) -> PolygonWithHoles {
+    PolygonWithHoles {
+      outer: self.outer.iter().map(|&p| f(p)).collect(),
+      holes: self.holes.iter().map(|h| h.iter().map(|&p| f(p)).collect()).collect(),
+    }
+  }
+
+  /// Area inside the outer ring that isn't in any of the holes
+  pub fn area(&self) -> f32 {
+    ring_area(&self.outer).abs() - self.holes.iter().map(|h| ring_area(h).abs()).sum::<f32>()
+  }
+}
+
+/// Even-odd point in polygon test
+pub fn ring_contains(ring: &[Point], p: Point) -> bool {
+  if ring.len() < 3 {
+    return false;
+  }
+  let mut inside = false;
+  let mut j = ring.len() - 1;
+  for i in 0..ring.len() {
+    let (a, b) = (ring[i], ring[j]);
+    if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+      inside = !inside;
+    }
+    j = i;
+  }
+  inside
+}
+
+// Ring simplification below here ================================================================
+// Rings here are closed implicitly, their last point connects back to their first.
+
+/// Simplifies a ring with Ramer-Douglas-Peucker. Any point closer than `tolerance` to the
+/// simplified outline is dropped.
+pub fn simplify_ring(ring: &[Point], tolerance: f32) -> Vec<Point> {
+  if ring.len() < 4 {
+    return ring.to_vec();
+  }
+  // RDP works on open lines, so split the ring into two at the point furthest from the first one
+  let (far_ix, _) = ring
+    .iter()
+    .enumerate()
+    .map(|(i, p)| (i, (p - ring[0]).norm_squared()))
+    .max_by(|(_, d1), (_, d2)| d1.partial_cmp(d2).unwrap())
+    .unwrap();
+  let mut closed = ring.to_vec();
+  closed.push(ring[0]);
+  let mut out = vec![];
+  rdp(&closed[..=far_ix], tolerance, &mut out);
+  rdp(&closed[far_ix..], tolerance, &mut out);
+  out
+}
+
+/// Pushes the simplified version of `line` onto `out`, except for its last point
+fn rdp(line: &[Point], tolerance: f32, out: &mut Vec<Point>) {
+  let (first, last) = (line[0], line[line.len() - 1]);
+  let furthest = line
+    .iter()
+    .enumerate()
+    .skip(1)
+    .take(line.len().saturating_sub(2))
+    .map(|(i, &p)| (i, dist_to_segment(p, first, last)))
+    .max_by(|(_, d1), (_, d2)| d1.partial_cmp(d2).unwrap());
+  match furthest {
+    Some((i, dist)) if dist > tolerance => {
+      rdp(&line[..=i], tolerance, out);
+      rdp(&line[i..], tolerance, out);
+    }
+    _ => out.push(first),
+  }
+}
+
+pub fn dist_to_segment(p: Point, a: Point, b: Point) -> f32 {
+  let ab = b - a;
+  let len_sq = ab.norm_squared();
+  if len_sq == 0.0 {
+    return (p - a).norm();
+  }
+  let t = ((p - a).dot(&ab) / len_sq).max(0.0).min(1.0);
+  (p - (a + ab * t)).norm()
+}
+
+/// Rounds off a ring's corners with `passes` iterations of Chaikin's corner cutting
+pub fn chaikin_ring(ring: &[Point], passes: u32) -> Vec<Point> {
+  let mut ring = ring.to_vec();
+  for _ in 0..passes {
+    if ring.len() < 3 {
+      break;
+    }
+    ring = (0..ring.len())
+      .flat_map(|i| {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        vec![a + (b - a) * 0.25, a + (b - a) * 0.75]
+      })
+      .collect();
+  }
+  ring
+}
+
+/// Simplifies and then rounds off a ring, with the guarantee that the result is a simple polygon
+/// (assuming there are at least three distinct points to work with). If simplifying at the
+/// given tolerance would make the ring cross itself, progressively smaller tolerances are tried.
+pub fn smooth_ring(ring: &[Point], tolerance: f32, chaikin_passes: u32) -> Vec<Point> {
+  let ring = untangle_ring(ring.to_vec());
+  let mut tolerance = tolerance;
+  while tolerance > 0.01 {
+    let smoothed = chaikin_ring(&simplify_ring(&ring, tolerance), chaikin_passes);
+    if is_simple_ring(&smoothed) {
+      return smoothed;
+    }
+    tolerance /= 2.0;
+  }
+  ring
+}
+
+/// Signed area of a ring. Positive if the points wind clockwise on screen (with +y down).
+pub fn ring_area(ring: &[Point]) -> f32 {
+  let n = ring.len();
+  (0..n).map(|i| ring[i].x * ring[(i + 1) % n].y - ring[(i + 1) % n].x * ring[i].y).sum::<f32>()
+    / 2.0
+}
+
+/// Cuts a simple ring into triangles by clipping ears off it. A ring that crosses itself may not
+/// get all of its area covered.
+pub fn triangulate_ring(ring: &[Point]) -> Vec<[Point; 3]> {
+  // Corners that turn the same way the whole ring winds are convex
+  let winding = ring_area(ring).signum();
+  let turn = |a: Point, b: Point, c: Point| {
+    let (u, v) = (b - a, c - a);
+    (u.x * v.y - u.y * v.x) * winding
+  };
+  let mut left: Vec<usize> = (0..ring.len()).collect();
+  let mut tris = vec![];
+  while left.len() > 3 {
+    let n = left.len();
+    let corner = |i: usize| (ring[left[(i + n - 1) % n]], ring[left[i]], ring[left[(i + 1) % n]]);
+    // An ear is a convex corner with no other points of the ring inside it. Points on its edge
+    // count too, unless that rules out every corner, which can happen with collinear points.
+    let is_ear = |i: usize, edges_count: bool| {
+      let (a, b, c) = corner(i);
+      let inside = |p: Point| {
+        let turns = [turn(a, b, p), turn(b, c, p), turn(c, a, p)];
+        if edges_count {
+          turns.iter().all(|&t| t >= 0.0)
+        } else {
+          turns.iter().all(|&t| t > 0.0)
+        }
+      };
+      turn(a, b, c) > 0.0
+        && !left.iter().map(|&j| ring[j]).any(|p| p != a && p != b && p != c && inside(p))
+    };
+    let ear = (0..n).find(|&i| is_ear(i, true)).or_else(|| (0..n).find(|&i| is_ear(i, false)));
+    match ear {
+      Some(i) => {
+        let (a, b, c) = corner(i);
+        tris.push([a, b, c]);
+        left.remove(i);
+      }
+      None => return tris,
+    }
+  }
+  if left.len() == 3 {
+    tris.push([ring[left[0]], ring[left[1]], ring[left[2]]]);
+  }
+  tris
+}
+
+/// True if the ring has at least three points, no repeated consecutive points, and no edges that
+/// cross or touch other than neighbors sharing an endpoint
+pub fn is_simple_ring(ring: &[Point]) -> bool {
+  let n = ring.len();
+  if n < 3 || (0..n).any(|i| ring[i] == ring[(i + 1) % n]) {
+    return false;
+  }
+  first_crossing(ring).is_none()
+}
+
+/// Cuts loops out of a ring until it no longer crosses itself. At each crossing the ring is split
+/// in two and the piece with the bigger area is kept.
+pub fn untangle_ring(mut ring: Vec<Point>) -> Vec<Point> {
+  ring.dedup();
+  while ring.len() > 1 && ring[0] == ring[ring.len() - 1] {
+    ring.pop();
+  }
+  while ring.len() > 3 {
+    let (i, j, crossing) = match first_crossing(&ring) {
+      Some(c) => c,
+      None => break,
+    };
+    // Edge i runs from ring[i] to ring[i + 1], edge j from ring[j] to ring[j + 1]
+    let mut inner: Vec<Point> = ring[(i + 1)..=j].to_vec();
+    inner.push(crossing);
+    let mut outer: Vec<Point> = ring[(j + 1)..].iter().chain(&ring[..=i]).cloned().collect();
+    outer.push(crossing);
+    ring = if ring_area(&inner).abs() > ring_area(&outer).abs() { inner } else { outer };
+    ring.dedup();
+    while ring.len() > 1 && ring[0] == ring[ring.len() - 1] {
+      ring.pop();
+    }
+  }
+  ring
+}
+
+/// Finds the first pair of non-neighboring edges in the ring that intersect, returned as the
+/// indices of their first points and where they intersect.
+fn first_crossing(ring: &[Point]) -> Option<(usize, usize, Point)> {
+  let n = ring.len();
+  for i in 0..n {
+    for j in (i + 2)..n {
+      // The first and last edges are neighbors too
+      if i == 0 && j == n - 1 {
+        continue;
+      }
+      let hit = segment_intersection(ring[i], ring[(i + 1) % n], ring[j], ring[(j + 1) % n]);
+      if let Some(p) = hit {
+        return Some((i, j, p));
+      }
+    }
+  }
+  None
+}
+
+/// Where segments a1-a2 and b1-b2 meet, if they do. Touching counts.
+pub fn segment_intersection(a1: Point, a2: Point, b1: Point, b2: Point) -> Option<Point> {
+  let cross = |u: Vec2, v: Vec2| u.x * v.y - u.y * v.x;
+  let (r, s) = (a2 - a1, b2 - b1);
+  let denom = cross(r, s);
+  let eps = 1e-6;
+  if denom.abs() < eps {
+    // Parallel. Only matters if they're collinear and overlapping.
+    if cross(b1 - a1, r).abs() > eps {
+      return None;
+    }
+    return [b1, b2, a1, a2]
+      .iter()
+      .cloned()
+      .find(|&p| dist_to_segment(p, a1, a2) < eps && dist_to_segment(p, b1, b2) < eps);
+  }
+  let t = cross(b1 - a1, s) / denom;
+  let u = cross(b1 - a1, r) / denom;
+  if t >= -eps && t <= 1.0 + eps && u >= -eps && u <= 1.0 + eps {
+    Some(a1 + r * t)
+  } else {
+    None
+  }
+}
+
 // Gridded geometry below here ====================================================================
 pub type IntPoint = na::Point2<i32>;
 
@@ -170,3 +466,108 @@ pub fn walk_grid(p1: IntPoint, p2: IntPoint) -> Vec<IntPoint> {
   }
   points
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn square(x: f32, y: f32, size: f32) -> Vec<Point> {
+    vec![
+      Point::new(x, y),
+      Point::new(x + size, y),
+      Point::new(x + size, y + size),
+      Point::new(x, y + size),
+    ]
+  }
+
+  /// A square with lots of points along each side, and some little bumps
+  fn noisy_square() -> Vec<Point> {
+    let mut ring = vec![];
+    for i in 0..10 {
+      ring.push(Point::new(i as f32, if i % 3 == 0 { 0.1 } else { 0.0 }));
+    }
+    for i in 0..10 {
+      ring.push(Point::new(10.0, i as f32));
+    }
+    for i in 0..10 {
+      ring.push(Point::new(10.0 - i as f32, 10.0));
+    }
+    for i in 0..10 {
+      ring.push(Point::new(if i % 4 == 0 { -0.1 } else { 0.0 }, 10.0 - i as f32));
+    }
+    ring
+  }
+
+  #[test]
+  fn test_simplify_ring_drops_points_within_tolerance() {
+    let simplified = simplify_ring(&noisy_square(), 0.5);
+    assert_eq!(simplified.len(), 4);
+    for corner in &square(0.0, 0.0, 10.0) {
+      assert!(simplified.iter().any(|p| (p - corner).norm() < 0.2), "{} missing", corner);
+    }
+    // Tight enough tolerance keeps the bumps
+    assert!(simplify_ring(&noisy_square(), 0.05).len() > 4);
+  }
+
+  #[test]
+  fn test_chaikin_cuts_corners() {
+    let rounded = chaikin_ring(&square(0.0, 0.0, 4.0), 2);
+    assert_eq!(rounded.len(), 16);
+    assert!(is_simple_ring(&rounded));
+    assert!(!rounded.contains(&Point::new(0.0, 0.0)));
+    assert!(ring_area(&rounded).abs() < 16.0);
+  }
+
+  #[test]
+  fn test_untangle_bowtie() {
+    let bowtie =
+      vec![Point::new(0.0, 0.0), Point::new(4.0, 4.0), Point::new(4.0, 0.0), Point::new(0.0, 4.0)];
+    assert!(!is_simple_ring(&bowtie));
+    let untangled = untangle_ring(bowtie);
+    assert!(is_simple_ring(&untangled));
+    assert_eq!(untangled.len(), 3);
+  }
+
+  #[test]
+  fn test_smooth_ring_stays_simple() {
+    // A staircase diagonal, like the cave tracer produces, pinched almost shut in the middle
+    let mut ring = vec![];
+    for i in 0..20 {
+      ring.push(Point::new(i as f32, i as f32));
+      ring.push(Point::new(i as f32 + 1.0, i as f32));
+    }
+    ring.push(Point::new(20.0, 20.0));
+    ring.push(Point::new(10.2, 10.0));
+    ring.push(Point::new(0.0, 1.0));
+    for &(tol, passes) in &[(0.1, 0), (0.5, 1), (2.0, 2), (5.0, 3)] {
+      let smoothed = smooth_ring(&ring, tol, passes);
+      assert!(is_simple_ring(&smoothed), "Not simple with tolerance {}", tol);
+    }
+  }
+
+  #[test]
+  fn test_triangulate_concave_ring() {
+    // An L shape, which has one corner no triangle can be cut from
+    let ring = [(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (2.0, 2.0), (2.0, 4.0), (0.0, 4.0)];
+    let ring: Vec<Point> = ring.iter().map(|&(x, y)| Point::new(x, y)).collect();
+    let tris = triangulate_ring(&ring);
+    assert_eq!(tris.len(), ring.len() - 2);
+    let area: f32 = tris.iter().map(|t| ring_area(t).abs()).sum();
+    assert!((area - ring_area(&ring).abs()).abs() < 1e-4, "{}", area);
+    let reversed: Vec<Point> = ring.iter().rev().cloned().collect();
+    assert_eq!(triangulate_ring(&reversed).len(), ring.len() - 2);
+    // Nothing gets covered outside the notch
+    assert!(tris.iter().all(|t| !ring_contains(t, Point::new(3.0, 3.0))));
+  }
+
+  #[test]
+  fn test_polygon_with_holes_contains() {
+    let poly = PolygonWithHoles::new(square(0.0, 0.0, 10.0), vec![square(4.0, 4.0, 2.0)]);
+    assert!(poly.contains(Point::new(1.0, 1.0)));
+    assert!(poly.contains(Point::new(7.0, 5.0)));
+    assert!(!poly.contains(Point::new(5.0, 5.0)));
+    assert!(!poly.contains(Point::new(11.0, 5.0)));
+    assert!(!poly.contains(Point::new(-1.0, -1.0)));
+    assert!((poly.area() - 96.0).abs() < 0.0001);
+  }
+}
@@ -4,7 +4,7 @@ extern crate nalgebra as na;
 use super::agents::Agent;
 use ggez::graphics::Color;
 use ggez::graphics::TextFragment;
-use ggez::graphics::{Font, Text};
+use ggez::graphics::{Font, Scale, Text};
 use ggez::Context;
 use std::collections::HashMap;
 
@@ -16,38 +16,35 @@ pub type Point = na::Point2<f32>;
 pub type Vec2 = na::Vector2<f32>;
 
 pub struct Assets {
-  /// This map maps world sizes in meters -> font where the size as rendered
-  /// without scaling is equal to that world size.
-  font_map: HashMap<u32, Font>,
-  // This maps strings to their text objects so we don't need to
-  // build text objects over and over
+  font: Font,
+  /// Agent symbols, rendered big enough to still look sharp when scaled up to large creatures.
+  /// This maps strings to their text objects so we don't need to build them over and over.
   text_map: HashMap<&'static str, Text>,
 }
 
+/// Pixel size agent symbols are rendered at before being scaled to the agent's size
+static GLYPH_PX: f32 = 64.0;
+
 impl Assets {
   pub fn new(ctx: &mut Context) -> Assets {
-    let mut m = HashMap::new();
-    m.insert(1, Font::new(ctx, "/Hack-Bold.ttf").unwrap());
-    Assets { font_map: m, text_map: HashMap::new() }
+    let font = Font::new(ctx, "/Hack-Bold.ttf").unwrap();
+    Assets { font, text_map: HashMap::new() }
   }
 
   pub fn agent_txt<T: Agent>(&mut self, agent: &T) -> &Text {
-    // TODO: Could be crashy
-    let font = self.font_map[&agent.width()];
     let text_frag = TextFragment {
       color: Some(Color::new(1.0, 1.0, 1.0, 1.0)),
-      font: Some(font),
-      scale: None,
+      font: Some(self.font),
+      scale: Some(Scale::uniform(GLYPH_PX)),
       text: agent.symbol().to_string(),
     };
     self.text_map.entry(agent.symbol()).or_insert_with(|| Text::new(text_frag))
   }
 
   pub fn txt(&mut self, content: &str) -> Text {
-    let font = self.font_map[&1];
     let text_frag = TextFragment {
       color: Some(Color::new(1.0, 1.0, 1.0, 1.0)),
-      font: Some(font),
+      font: Some(self.font),
       scale: None,
       text: content.to_string(),
     };
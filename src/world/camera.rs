@@ -0,0 +1,196 @@
+use crate::util::context_help::ContextHelp;
+use crate::util::{Meters, Point, Vec2};
+use ggez::graphics::DrawParam;
+use ggez::Context;
+
+pub static MIN_ZOOM: f32 = 1.0;
+pub static MAX_ZOOM: f32 = 8.0;
+
+/// Maps level space onto the screen. At a zoom of 1.0 the whole level fills the window. Zooming in
+/// shows a smaller window of the level centered on `center`, which is kept inside the level bounds.
+pub struct Camera {
+  /// The level-space point shown in the middle of the screen
+  center: Point,
+  zoom: f32,
+  level_dims: Vec2,
+  /// When true, the camera re-centers on whatever it's told to follow every update
+  following: bool,
+  /// Level-space point that was under the cursor when a drag-pan began
+  drag_anchor: Option<Point>,
+}
+
+impl Camera {
+  pub fn new(level_dims: Vec2) -> Camera {
+    Camera {
+      center: Point::new(level_dims.x / 2.0, level_dims.y / 2.0),
+      zoom: MIN_ZOOM,
+      level_dims,
+      following: true,
+      drag_anchor: None,
+    }
+  }
+
+  pub fn center(&self) -> Point {
+    self.center
+  }
+
+  pub fn zoom(&self) -> f32 {
+    self.zoom
+  }
+
+  /// Re-centers on `target` if the camera is in follow mode
+  pub fn follow(&mut self, target: Point) {
+    if self.following && self.drag_anchor.is_none() {
+      self.center = target;
+      self.clamp();
+    }
+  }
+
+  pub fn resume_following(&mut self) {
+    self.following = true;
+  }
+
+  /// Multiplies the zoom by `factor`, keeping the level point under `s_point` fixed on screen
+  pub fn zoom_at(&mut self, ctx: &Context, s_point: Point, factor: f32) {
+    self.zoom_at_inner(ctx.screen_dims(), s_point, factor)
+  }
+
+  /// Starts a drag-pan from the given screen point. Panning stops following the player.
+  pub fn start_drag(&mut self, ctx: &Context, s_point: Point) {
+    self.drag_anchor = Some(self.sspace_to_lspace(ctx, s_point));
+    self.following = false;
+  }
+
+  /// Moves the camera so the point grabbed by `start_drag` stays under the cursor
+  pub fn drag_to(&mut self, ctx: &Context, s_point: Point) {
+    self.drag_to_inner(ctx.screen_dims(), s_point)
+  }
+
+  pub fn end_drag(&mut self) {
+    self.drag_anchor = None;
+  }
+
+  pub fn lspace_to_sspace(&self, ctx: &Context, p: Point) -> Point {
+    self.l_to_s(ctx.screen_dims(), p)
+  }
+
+  pub fn sspace_to_lspace(&self, ctx: &Context, p: Point) -> Point {
+    self.s_to_l(ctx.screen_dims(), p)
+  }
+
+  /// How many pixels tall `m` meters currently are on screen
+  pub fn meters_to_pixels(&self, ctx: &Context, m: Meters) -> f32 {
+    self.pixels_per_meter(ctx.screen_dims()).y * m
+  }
+
+  /// Transform that takes level space to screen space
+  pub fn draw_param(&self, ctx: &Context) -> DrawParam {
+    self.draw_param_inner(ctx.screen_dims())
+  }
+
+  fn pixels_per_meter(&self, screen: Vec2) -> Vec2 {
+    screen.component_div(&self.level_dims) * self.zoom
+  }
+
+  fn l_to_s(&self, screen: Vec2, p: Point) -> Point {
+    let ppm = self.pixels_per_meter(screen);
+    Point::from((p - self.center).component_mul(&ppm) + screen / 2.0)
+  }
+
+  fn s_to_l(&self, screen: Vec2, p: Point) -> Point {
+    let ppm = self.pixels_per_meter(screen);
+    self.center + (p.coords - screen / 2.0).component_div(&ppm)
+  }
+
+  fn draw_param_inner(&self, screen: Vec2) -> DrawParam {
+    let ppm = self.pixels_per_meter(screen);
+    let dest = Point::from(screen / 2.0 - self.center.coords.component_mul(&ppm));
+    DrawParam { scale: ppm.into(), dest: dest.into(), ..Default::default() }
+  }
+
+  fn zoom_at_inner(&mut self, screen: Vec2, s_point: Point, factor: f32) {
+    let anchored = self.s_to_l(screen, s_point);
+    self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+    // Shift so the anchored point ends up back under the cursor
+    let drifted = self.s_to_l(screen, s_point);
+    self.center += anchored - drifted;
+    self.clamp();
+  }
+
+  fn drag_to_inner(&mut self, screen: Vec2, s_point: Point) {
+    if let Some(anchor) = self.drag_anchor {
+      let under_cursor = self.s_to_l(screen, s_point);
+      self.center += anchor - under_cursor;
+      self.clamp();
+    }
+  }
+
+  /// Keeps the visible window inside the level
+  fn clamp(&mut self) {
+    let half_view = self.level_dims / (2.0 * self.zoom);
+    self.center.x = self.center.x.max(half_view.x).min(self.level_dims.x - half_view.x);
+    self.center.y = self.center.y.max(half_view.y).min(self.level_dims.y - half_view.y);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn screen() -> Vec2 {
+    Vec2::new(1000.0, 1000.0)
+  }
+
+  fn assert_close(p1: Point, p2: Point) {
+    assert!((p1 - p2).norm() < 0.001, "{} != {}", p1, p2);
+  }
+
+  #[test]
+  fn test_unzoomed_fills_screen() {
+    let cam = Camera::new(Vec2::new(50.0, 50.0));
+    assert_close(cam.l_to_s(screen(), Point::new(0.0, 0.0)), Point::new(0.0, 0.0));
+    assert_close(cam.l_to_s(screen(), Point::new(50.0, 50.0)), Point::new(1000.0, 1000.0));
+    assert_close(cam.s_to_l(screen(), Point::new(500.0, 250.0)), Point::new(25.0, 12.5));
+  }
+
+  #[test]
+  fn test_conversions_round_trip_when_zoomed() {
+    let mut cam = Camera::new(Vec2::new(50.0, 50.0));
+    cam.zoom_at_inner(screen(), Point::new(100.0, 700.0), 3.0);
+    let p = Point::new(12.3, 31.4);
+    assert_close(cam.s_to_l(screen(), cam.l_to_s(screen(), p)), p);
+  }
+
+  #[test]
+  fn test_zoom_keeps_cursor_point_fixed() {
+    let mut cam = Camera::new(Vec2::new(50.0, 50.0));
+    let cursor = Point::new(400.0, 600.0);
+    let before = cam.s_to_l(screen(), cursor);
+    cam.zoom_at_inner(screen(), cursor, 2.0);
+    assert_close(cam.s_to_l(screen(), cursor), before);
+  }
+
+  #[test]
+  fn test_clamps_to_level_bounds() {
+    let mut cam = Camera::new(Vec2::new(50.0, 50.0));
+    cam.zoom_at_inner(screen(), Point::new(500.0, 500.0), 2.0);
+    cam.follow(Point::new(0.0, 50.0));
+    // Half of the view is 12.5m at 2x zoom, so the camera can't go further than that to an edge
+    assert_close(cam.center(), Point::new(12.5, 37.5));
+    assert_close(cam.l_to_s(screen(), Point::new(0.0, 50.0)), Point::new(0.0, 1000.0));
+  }
+
+  #[test]
+  fn test_drag_pans_and_stops_following() {
+    let mut cam = Camera::new(Vec2::new(50.0, 50.0));
+    cam.zoom_at_inner(screen(), Point::new(500.0, 500.0), 4.0);
+    cam.drag_anchor = Some(cam.s_to_l(screen(), Point::new(500.0, 500.0)));
+    cam.following = false;
+    cam.drag_to_inner(screen(), Point::new(400.0, 500.0));
+    // At 4x zoom one meter is 80 pixels
+    assert_close(cam.center(), Point::new(26.25, 25.0));
+    cam.end_drag();
+    cam.follow(Point::new(10.0, 10.0));
+    assert_close(cam.center(), Point::new(26.25, 25.0));
+  }
+}
@@ -0,0 +1,367 @@
+use crate::agents::monster::MonsterKind;
+use crate::util::context_help::ContextHelp;
+use crate::util::{Assets, Meters, Point};
+use crate::world::noise::NoiseKind;
+use ggez::graphics;
+use ggez::graphics::{Color, DrawMode, DrawParam, Mesh, Rect};
+use ggez::{Context, GameResult};
+
+/// Every command, along with the words that can come after it, for tab completion
+static COMMANDS: &[(&str, &[&str])] = &[
+  ("help", &[]),
+  ("regen", &[]),
+  ("teleport", &[]),
+  ("spawn", &["monster"]),
+  ("set", &["fastmode"]),
+  ("toggle", &["debug", "collision_bb", "rejections"]),
+  ("stats", &[]),
+  ("inventory", &[]),
+  ("use", &[]),
+  ("drop", &[]),
+  ("noise", &["footstep", "combat", "door"]),
+];
+/// What `spawn monster` puts down when it isn't told a kind
+static DEFAULT_SPAWN: MonsterKind = MonsterKind::Goblin;
+/// How many lines of output the console keeps around and shows
+static CONSOLE_LINES: usize = 12;
+/// Screen pixels between lines of console text
+static LINE_HEIGHT: f32 = 20.0;
+
+/// Something typed into the console
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+  Help,
+  /// Throws the level away and generates a new one, from the given seed if there is one, that
+  /// many levels down
+  Regen {
+    seed: Option<u64>,
+    depth: u32,
+  },
+  /// Moves the player to a point in level space
+  Teleport(Point),
+  /// Puts a monster of the given kind next to the player, of the given radius or the usual size
+  /// for its kind
+  SpawnMonster {
+    kind: MonsterKind,
+    radius: Option<Meters>,
+  },
+  /// How many generation ticks run per frame. `None` runs generation flat out.
+  SetFastmode(Option<usize>),
+  Toggle(DebugFlag),
+  Stats,
+  /// Lists what the player is carrying
+  Inventory,
+  /// Uses the item in an inventory slot, which equips or unequips anything that isn't eaten or
+  /// drunk
+  Use(usize),
+  /// Drops the item in an inventory slot
+  Drop(usize),
+  /// Makes a noise where the player is standing, to see which monsters come to look
+  Noise(NoiseKind),
+}
+
+/// Parts of the debug view that can be switched on and off
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugFlag {
+  /// The whole debug view
+  Debug,
+  /// Bounding volumes of everything in the collision world
+  CollisionBB,
+  /// Placements level generation threw away
+  Rejections,
+}
+
+impl Command {
+  pub fn parse(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+      ["help"] => Ok(Command::Help),
+      ["regen"] => Ok(Command::Regen { seed: None, depth: 1 }),
+      ["regen", seed] => seed
+        .parse()
+        .map(|s| Command::Regen { seed: Some(s), depth: 1 })
+        .map_err(|_| format!("bad seed {}", seed)),
+      ["regen", seed, depth] => match (seed.parse(), depth.parse()) {
+        (Ok(s), Ok(d)) if d > 0 => Ok(Command::Regen { seed: Some(s), depth: d }),
+        _ => Err(format!("bad seed {} or depth {}, depths start at 1", seed, depth)),
+      },
+      ["regen", ..] => Err("usage: regen [seed] [depth]".to_string()),
+      ["teleport", x, y] => match (x.parse(), y.parse()) {
+        (Ok(x), Ok(y)) => Ok(Command::Teleport(Point::new(x, y))),
+        _ => Err(format!("bad position {} {}", x, y)),
+      },
+      ["teleport", ..] => Err("usage: teleport <x> <y>".to_string()),
+      ["spawn", "monster"] => Ok(Command::SpawnMonster { kind: DEFAULT_SPAWN, radius: None }),
+      ["spawn", "monster", word] => match MonsterKind::from_name(word) {
+        Some(kind) => Ok(Command::SpawnMonster { kind, radius: None }),
+        None => {
+          parse_radius(word).map(|r| Command::SpawnMonster { kind: DEFAULT_SPAWN, radius: r })
+        }
+      },
+      ["spawn", "monster", kind, radius] => {
+        let kind =
+          MonsterKind::from_name(kind).ok_or_else(|| format!("unknown monster {}", kind))?;
+        parse_radius(radius).map(|radius| Command::SpawnMonster { kind, radius })
+      }
+      ["spawn", ..] => Err("usage: spawn monster [kind] [radius]".to_string()),
+      ["set", "fastmode", "on"] => Ok(Command::SetFastmode(None)),
+      ["set", "fastmode", "off"] => Ok(Command::SetFastmode(Some(1))),
+      ["set", "fastmode", ticks] => match ticks.parse() {
+        Ok(ticks) if ticks > 0 => Ok(Command::SetFastmode(Some(ticks))),
+        _ => Err(format!("bad tick count {}, should be on, off or at least 1", ticks)),
+      },
+      ["set", ..] => Err("usage: set fastmode <on|off|ticks per frame>".to_string()),
+      ["toggle", "debug"] => Ok(Command::Toggle(DebugFlag::Debug)),
+      ["toggle", "collision_bb"] => Ok(Command::Toggle(DebugFlag::CollisionBB)),
+      ["toggle", "rejections"] => Ok(Command::Toggle(DebugFlag::Rejections)),
+      ["toggle", ..] => Err("usage: toggle <debug|collision_bb|rejections>".to_string()),
+      ["stats"] => Ok(Command::Stats),
+      ["inventory"] => Ok(Command::Inventory),
+      ["use", slot] => slot.parse().map(Command::Use).map_err(|_| format!("bad slot {}", slot)),
+      ["use", ..] => Err("usage: use <slot>".to_string()),
+      ["drop", slot] => slot.parse().map(Command::Drop).map_err(|_| format!("bad slot {}", slot)),
+      ["drop", ..] => Err("usage: drop <slot>".to_string()),
+      ["noise", "footstep"] => Ok(Command::Noise(NoiseKind::Footstep)),
+      ["noise", "combat"] => Ok(Command::Noise(NoiseKind::Combat)),
+      ["noise", "door"] => Ok(Command::Noise(NoiseKind::Door)),
+      ["noise", ..] => Err("usage: noise <footstep|combat|door>".to_string()),
+      [] => Err("type help to see the commands".to_string()),
+      [cmd, ..] => Err(format!("unknown command {}", cmd)),
+    }
+  }
+
+  /// One line describing how to use every command
+  pub fn help() -> &'static str {
+    "regen [seed] [depth] | teleport <x> <y> | spawn monster [kind] [radius] | \
+     set fastmode <on|off|ticks> | toggle <debug|collision_bb|rejections> | stats | inventory | \
+     use <slot> | drop <slot> | noise <footstep|combat|door>"
+  }
+}
+
+/// Reads a monster radius, which has to be more than nothing
+fn parse_radius(radius: &str) -> Result<Option<Meters>, String> {
+  match radius.parse() {
+    Ok(r) if r > 0.0 => Ok(Some(r)),
+    _ => Err(format!("bad radius {}", radius)),
+  }
+}
+
+/// A drop-down console for driving the game with typed commands
+#[derive(Debug, Default)]
+pub struct Console {
+  pub open: bool,
+  input: String,
+  /// Commands entered so far, oldest first
+  history: Vec<String>,
+  /// How far back into the history the input came from, if it came from there at all
+  history_pos: Option<usize>,
+  output: Vec<String>,
+}
+
+impl Console {
+  pub fn new() -> Console {
+    Console::default()
+  }
+
+  pub fn toggle(&mut self) {
+    self.open = !self.open;
+  }
+
+  /// Adds a typed character to the input. A leading slash is dropped, so `/stats` works as well.
+  pub fn type_char(&mut self, c: char) {
+    if c.is_control() || (c == '/' && self.input.is_empty()) {
+      return;
+    }
+    self.input.push(c);
+  }
+
+  pub fn backspace(&mut self) {
+    self.input.pop();
+  }
+
+  /// Takes the entered line, echoing it and remembering it in the history
+  pub fn submit(&mut self) -> String {
+    let line = std::mem::take(&mut self.input).trim().to_string();
+    self.history_pos = None;
+    self.print(&format!("> {}", line));
+    if !line.is_empty() && self.history.last() != Some(&line) {
+      self.history.push(line.clone());
+    }
+    line
+  }
+
+  /// Adds some lines to the output, dropping the oldest ones once there are too many
+  pub fn print(&mut self, text: &str) {
+    self.output.extend(text.lines().map(|l| l.to_string()));
+    let excess = self.output.len().saturating_sub(CONSOLE_LINES);
+    self.output.drain(..excess);
+  }
+
+  /// Replaces the input with the previous command in the history
+  pub fn history_back(&mut self) {
+    if self.history.is_empty() {
+      return;
+    }
+    let pos = self.history_pos.map_or(0, |p| (p + 1).min(self.history.len() - 1));
+    self.history_pos = Some(pos);
+    self.input = self.history[self.history.len() - 1 - pos].clone();
+  }
+
+  /// Replaces the input with the next command in the history, or clears it after the newest one
+  pub fn history_forward(&mut self) {
+    match self.history_pos {
+      Some(0) | None => {
+        self.history_pos = None;
+        self.input.clear();
+      }
+      Some(p) => {
+        self.history_pos = Some(p - 1);
+        self.input = self.history[self.history.len() - p].clone();
+      }
+    }
+  }
+
+  /// Completes the word being typed. If more than one word fits, it's completed as far as they
+  /// agree and the options are printed.
+  pub fn complete(&mut self) {
+    let words: Vec<&str> = self.input.split(' ').collect();
+    let (typed, partial) = words.split_at(words.len() - 1);
+    let candidates: Vec<&str> = match typed {
+      [] => COMMANDS.iter().map(|c| c.0).collect(),
+      [cmd] => COMMANDS.iter().find(|c| c.0 == *cmd).map_or(vec![], |c| c.1.to_vec()),
+      ["set", "fastmode"] => vec!["on", "off"],
+      ["spawn", "monster"] => MonsterKind::all().iter().map(|k| k.name()).collect(),
+      _ => vec![],
+    };
+    let matches: Vec<&str> = candidates.into_iter().filter(|c| c.starts_with(partial[0])).collect();
+    let completed = match matches.as_slice() {
+      [] => return,
+      [only] => format!("{} ", only),
+      [first, rest @ ..] => {
+        let common = rest.iter().fold(first.len(), |len, m| {
+          first.chars().zip(m.chars()).take(len).take_while(|(a, b)| a == b).count()
+        });
+        first[..common].to_string()
+      }
+    };
+    let options = if matches.len() > 1 { Some(matches.join(" ")) } else { None };
+    let prefix_len = self.input.len() - partial[0].len();
+    self.input.truncate(prefix_len);
+    self.input.push_str(&completed);
+    if let Some(options) = options {
+      self.print(&options);
+    }
+  }
+
+  /// Draws the console across the top of the screen, if it's open
+  pub fn draw(&self, ctx: &mut Context, assets: &mut Assets) -> GameResult<()> {
+    if !self.open {
+      return Ok(());
+    }
+    let height = (CONSOLE_LINES + 1) as f32 * LINE_HEIGHT;
+    let bg = Rect::new(0.0, 0.0, ctx.screen_x(), height);
+    let bg = Mesh::new_rectangle(ctx, DrawMode::fill(), bg, Color::new(0.0, 0.0, 0.0, 0.8))?;
+    graphics::draw(ctx, &bg, DrawParam::default())?;
+    let first_line = CONSOLE_LINES - self.output.len();
+    for (ix, line) in self.output.iter().enumerate() {
+      let dest = Point::new(4.0, (first_line + ix) as f32 * LINE_HEIGHT);
+      graphics::draw(ctx, &assets.txt(line), DrawParam::default().dest(dest))?;
+    }
+    let prompt = assets.txt(&format!("> {}_", self.input));
+    graphics::draw(ctx, &prompt, DrawParam::default().dest(Point::new(4.0, height - LINE_HEIGHT)))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn typed(text: &str) -> Console {
+    let mut console = Console::new();
+    text.chars().for_each(|c| console.type_char(c));
+    console
+  }
+
+  #[test]
+  fn test_parses_commands() {
+    assert_eq!(Command::parse("regen 42"), Ok(Command::Regen { seed: Some(42), depth: 1 }));
+    assert_eq!(Command::parse("  regen "), Ok(Command::Regen { seed: None, depth: 1 }));
+    assert_eq!(Command::parse("regen 42 3"), Ok(Command::Regen { seed: Some(42), depth: 3 }));
+    assert!(Command::parse("regen 42 0").is_err());
+    assert_eq!(Command::parse("teleport 3 -4.5"), Ok(Command::Teleport(Point::new(3.0, -4.5))));
+    let spawn = |kind, radius| Ok(Command::SpawnMonster { kind, radius });
+    assert_eq!(Command::parse("spawn monster"), spawn(MonsterKind::Goblin, None));
+    assert_eq!(Command::parse("spawn monster 1.5"), spawn(MonsterKind::Goblin, Some(1.5)));
+    assert_eq!(Command::parse("spawn monster ogre"), spawn(MonsterKind::Ogre, None));
+    assert_eq!(Command::parse("spawn monster rat 0.5"), spawn(MonsterKind::Rat, Some(0.5)));
+    assert!(Command::parse("spawn monster -1").is_err());
+    assert!(Command::parse("spawn monster dragon 1").is_err());
+    assert_eq!(Command::parse("set fastmode 10"), Ok(Command::SetFastmode(Some(10))));
+    assert_eq!(Command::parse("set fastmode on"), Ok(Command::SetFastmode(None)));
+    assert_eq!(Command::parse("toggle collision_bb"), Ok(Command::Toggle(DebugFlag::CollisionBB)));
+    assert!(Command::parse("set fastmode 0").is_err());
+    assert!(Command::parse("teleport 3").is_err());
+    assert!(Command::parse("regen abc").is_err());
+    assert_eq!(Command::parse("drop 2"), Ok(Command::Drop(2)));
+    assert!(Command::parse("use").is_err());
+    assert_eq!(Command::parse("noise combat"), Ok(Command::Noise(NoiseKind::Combat)));
+    assert!(Command::parse("noise").is_err());
+    assert!(Command::parse("dance").is_err());
+  }
+
+  #[test]
+  fn test_history() {
+    let mut console = typed("/stats");
+    assert_eq!(console.submit(), "stats");
+    "regen 1".chars().for_each(|c| console.type_char(c));
+    console.submit();
+    // Repeats aren't remembered twice
+    "regen 1".chars().for_each(|c| console.type_char(c));
+    console.submit();
+    console.history_back();
+    assert_eq!(console.input, "regen 1");
+    console.history_back();
+    assert_eq!(console.input, "stats");
+    console.history_back();
+    assert_eq!(console.input, "stats");
+    console.history_forward();
+    assert_eq!(console.input, "regen 1");
+    console.history_forward();
+    assert_eq!(console.input, "");
+  }
+
+  #[test]
+  fn test_tab_completion() {
+    let mut console = typed("tog");
+    console.complete();
+    assert_eq!(console.input, "toggle ");
+    "c".chars().for_each(|c| console.type_char(c));
+    console.complete();
+    assert_eq!(console.input, "toggle collision_bb ");
+
+    // Ambiguous words complete as far as the options agree, and the options get listed
+    let mut console = typed("s");
+    console.complete();
+    assert_eq!(console.input, "s");
+    assert_eq!(console.output.last().unwrap(), "spawn set stats");
+    let mut console = typed("set fastmode o");
+    console.complete();
+    assert_eq!(console.input, "set fastmode o");
+    let mut console = typed("st");
+    console.complete();
+    assert_eq!(console.input, "stats ");
+    let mut console = typed("spawn monster sk");
+    console.complete();
+    assert_eq!(console.input, "spawn monster skeleton ");
+  }
+
+  #[test]
+  fn test_output_is_bounded() {
+    let mut console = Console::new();
+    for i in 0..CONSOLE_LINES * 2 {
+      console.print(&i.to_string());
+    }
+    assert_eq!(console.output.len(), CONSOLE_LINES);
+    assert_eq!(console.output.last().unwrap(), &(CONSOLE_LINES * 2 - 1).to_string());
+  }
+}
@@ -0,0 +1,410 @@
+use crate::util::Vec2;
+use ggez::event::{Axis, Button, KeyMods};
+use ggez::input::keyboard::KeyCode;
+use std::fs;
+
+/// The bindings used when there's no `keymap.cfg` in the directory the game is run from
+static DEFAULT_KEYMAP: &str = include_str!("keymap.cfg");
+static KEYMAP_PATH: &str = "keymap.cfg";
+/// Stick positions closer to the middle than this are ignored, since sticks rarely rest at zero
+static STICK_DEADZONE: f32 = 0.2;
+
+/// Something the player can ask the game to do, whatever input it came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+  MoveUp,
+  MoveDown,
+  MoveLeft,
+  MoveRight,
+  PickUp,
+  ToggleDoor,
+  FinishGeneration,
+  ToggleFastmode,
+  Regenerate,
+  ToggleDebug,
+  OpenConsole,
+  Quit,
+  ReplayBack,
+  ReplayForward,
+  ReplayPrevRejection,
+  ReplayNextRejection,
+  ReplayPlay,
+  ReplayExit,
+  ExportGif,
+}
+
+/// What each action is called in the keymap config
+static ACTION_NAMES: &[(&str, Action)] = &[
+  ("move_up", Action::MoveUp),
+  ("move_down", Action::MoveDown),
+  ("move_left", Action::MoveLeft),
+  ("move_right", Action::MoveRight),
+  ("pick_up", Action::PickUp),
+  ("toggle_door", Action::ToggleDoor),
+  ("finish_generation", Action::FinishGeneration),
+  ("toggle_fastmode", Action::ToggleFastmode),
+  ("regenerate", Action::Regenerate),
+  ("toggle_debug", Action::ToggleDebug),
+  ("open_console", Action::OpenConsole),
+  ("quit", Action::Quit),
+  ("replay_back", Action::ReplayBack),
+  ("replay_forward", Action::ReplayForward),
+  ("replay_prev_rejection", Action::ReplayPrevRejection),
+  ("replay_next_rejection", Action::ReplayNextRejection),
+  ("replay_play", Action::ReplayPlay),
+  ("replay_exit", Action::ReplayExit),
+  ("export_gif", Action::ExportGif),
+];
+
+/// Keys that can be bound, which are written in the config the same way they're named here
+static NAMED_KEYS: &[KeyCode] = &[
+  KeyCode::A,
+  KeyCode::B,
+  KeyCode::C,
+  KeyCode::D,
+  KeyCode::E,
+  KeyCode::F,
+  KeyCode::G,
+  KeyCode::H,
+  KeyCode::I,
+  KeyCode::J,
+  KeyCode::K,
+  KeyCode::L,
+  KeyCode::M,
+  KeyCode::N,
+  KeyCode::O,
+  KeyCode::P,
+  KeyCode::Q,
+  KeyCode::R,
+  KeyCode::S,
+  KeyCode::T,
+  KeyCode::U,
+  KeyCode::V,
+  KeyCode::W,
+  KeyCode::X,
+  KeyCode::Y,
+  KeyCode::Z,
+  KeyCode::Key0,
+  KeyCode::Key1,
+  KeyCode::Key2,
+  KeyCode::Key3,
+  KeyCode::Key4,
+  KeyCode::Key5,
+  KeyCode::Key6,
+  KeyCode::Key7,
+  KeyCode::Key8,
+  KeyCode::Key9,
+  KeyCode::F1,
+  KeyCode::F2,
+  KeyCode::F3,
+  KeyCode::F4,
+  KeyCode::F5,
+  KeyCode::F6,
+  KeyCode::F7,
+  KeyCode::F8,
+  KeyCode::F9,
+  KeyCode::F10,
+  KeyCode::F11,
+  KeyCode::F12,
+  KeyCode::Up,
+  KeyCode::Down,
+  KeyCode::Left,
+  KeyCode::Right,
+  KeyCode::Space,
+  KeyCode::Escape,
+  KeyCode::Return,
+  KeyCode::Tab,
+  KeyCode::Back,
+  KeyCode::Insert,
+  KeyCode::Delete,
+  KeyCode::Home,
+  KeyCode::End,
+  KeyCode::PageUp,
+  KeyCode::PageDown,
+  KeyCode::Grave,
+  KeyCode::Slash,
+  KeyCode::Backslash,
+  KeyCode::LBracket,
+  KeyCode::RBracket,
+  KeyCode::Minus,
+  KeyCode::Equals,
+  KeyCode::Comma,
+  KeyCode::Period,
+  KeyCode::Semicolon,
+  KeyCode::Apostrophe,
+  KeyCode::Add,
+  KeyCode::Subtract,
+  KeyCode::Multiply,
+  KeyCode::Divide,
+  KeyCode::Numpad0,
+  KeyCode::Numpad1,
+  KeyCode::Numpad2,
+  KeyCode::Numpad3,
+  KeyCode::Numpad4,
+  KeyCode::Numpad5,
+  KeyCode::Numpad6,
+  KeyCode::Numpad7,
+  KeyCode::Numpad8,
+  KeyCode::Numpad9,
+  KeyCode::NumpadEnter,
+];
+
+/// Gamepad buttons that can be bound, written in the config as `pad:` and then the name
+static NAMED_BUTTONS: &[Button] = &[
+  Button::South,
+  Button::East,
+  Button::North,
+  Button::West,
+  Button::LeftTrigger,
+  Button::LeftTrigger2,
+  Button::RightTrigger,
+  Button::RightTrigger2,
+  Button::Select,
+  Button::Start,
+  Button::Mode,
+  Button::LeftThumb,
+  Button::RightThumb,
+  Button::DPadUp,
+  Button::DPadDown,
+  Button::DPadLeft,
+  Button::DPadRight,
+];
+
+impl Action {
+  pub fn from_name(name: &str) -> Option<Action> {
+    ACTION_NAMES.iter().find(|(n, _)| *n == name).map(|(_, a)| *a)
+  }
+
+  /// Which way this action moves the player, if it's a movement
+  pub fn direction(self) -> Option<Vec2> {
+    match self {
+      Action::MoveUp => Some(Vec2::new(0.0, -1.0)),
+      Action::MoveDown => Some(Vec2::new(0.0, 1.0)),
+      Action::MoveLeft => Some(Vec2::new(-1.0, 0.0)),
+      Action::MoveRight => Some(Vec2::new(1.0, 0.0)),
+      _ => None,
+    }
+  }
+}
+
+/// A key with the modifiers that have to be held along with it, or a gamepad button
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Input {
+  Key(KeyCode, KeyMods),
+  Pad(Button),
+}
+
+impl Input {
+  /// Parses something like `Ctrl+Shift+R` or `pad:South`
+  pub fn parse(text: &str) -> Result<Input, String> {
+    if let Some(button) = text.strip_prefix("pad:") {
+      return NAMED_BUTTONS
+        .iter()
+        .find(|b| format!("{:?}", b) == button)
+        .map(|b| Input::Pad(*b))
+        .ok_or_else(|| format!("unknown gamepad button {}", button));
+    }
+    let mut parts: Vec<&str> = text.split('+').collect();
+    let key = parts.pop().unwrap_or_default();
+    let mut mods = KeyMods::empty();
+    for part in parts {
+      mods |= match part {
+        "Ctrl" => KeyMods::CTRL,
+        "Shift" => KeyMods::SHIFT,
+        "Alt" => KeyMods::ALT,
+        "Logo" => KeyMods::LOGO,
+        _ => return Err(format!("unknown modifier {}", part)),
+      };
+    }
+    NAMED_KEYS
+      .iter()
+      .find(|k| format!("{:?}", k) == key)
+      .map(|k| Input::Key(*k, mods))
+      .ok_or_else(|| format!("unknown key {}", key))
+  }
+}
+
+/// Which inputs do which actions. An action can have any number of inputs bound to it.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+  bindings: Vec<(Input, Action)>,
+}
+
+impl Keymap {
+  /// Reads bindings from lines like `regenerate = Ctrl+R, pad:Select`. Blank lines and lines
+  /// starting with `#` are skipped.
+  pub fn parse(config: &str) -> Result<Keymap, String> {
+    let mut bindings = vec![];
+    for (ix, line) in config.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let err = |e: String| format!("line {}: {}", ix + 1, e);
+      let mut halves = line.splitn(2, '=');
+      let (name, inputs) = match (halves.next(), halves.next()) {
+        (Some(name), Some(inputs)) => (name.trim(), inputs),
+        _ => return Err(err("expected action = inputs".to_string())),
+      };
+      let action =
+        Action::from_name(name).ok_or_else(|| err(format!("unknown action {}", name)))?;
+      for input in inputs.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        bindings.push((Input::parse(input).map_err(err)?, action));
+      }
+    }
+    Ok(Keymap { bindings })
+  }
+
+  /// Loads `keymap.cfg` from the working directory if there is one, or the default bindings if
+  /// not. A broken config is reported and ignored.
+  pub fn load() -> Keymap {
+    let default = || Keymap::parse(DEFAULT_KEYMAP).expect("The default keymap is broken");
+    match fs::read_to_string(KEYMAP_PATH) {
+      Ok(config) => Keymap::parse(&config).unwrap_or_else(|e| {
+        warn!("Ignoring {}, {}", KEYMAP_PATH, e);
+        default()
+      }),
+      Err(_) => default(),
+    }
+  }
+
+  /// The action for a key pressed with the given modifiers. Bindings that need exactly those
+  /// modifiers win, but otherwise unmodified bindings still work with modifiers held.
+  pub fn key_action(&self, key: KeyCode, mods: KeyMods) -> Option<Action> {
+    let bound = |want: KeyMods| {
+      self.bindings.iter().find_map(|(input, action)| match input {
+        Input::Key(k, m) if *k == key && *m == want => Some(*action),
+        _ => None,
+      })
+    };
+    bound(mods).or_else(|| bound(KeyMods::empty()))
+  }
+
+  /// Every action bound to a key, whatever its modifiers. When a key is let go the modifiers may
+  /// already have been released, so this is used to work out what stopped.
+  pub fn key_actions(&self, key: KeyCode) -> Vec<Action> {
+    self
+      .bindings
+      .iter()
+      .filter_map(|(input, action)| match input {
+        Input::Key(k, _) if *k == key => Some(*action),
+        _ => None,
+      })
+      .collect()
+  }
+
+  pub fn button_action(&self, button: Button) -> Option<Action> {
+    self.bindings.iter().find(|(input, _)| *input == Input::Pad(button)).map(|(_, a)| *a)
+  }
+}
+
+/// Which way the player is being told to move, from held keys and buttons and the left stick
+#[derive(Debug, Clone)]
+pub struct MoveInput {
+  held: Vec<Action>,
+  stick: Vec2,
+}
+
+impl MoveInput {
+  pub fn new() -> MoveInput {
+    MoveInput { held: vec![], stick: Vec2::zeros() }
+  }
+
+  /// Starts moving in the action's direction. Does nothing for actions that aren't movement.
+  pub fn press(&mut self, action: Action) {
+    if action.direction().is_some() && !self.held.contains(&action) {
+      self.held.push(action);
+    }
+  }
+
+  pub fn release(&mut self, action: Action) {
+    self.held.retain(|a| *a != action);
+  }
+
+  /// Follows the left stick. Stick Y points up, unlike the level's.
+  pub fn set_axis(&mut self, axis: Axis, value: f32) {
+    match axis {
+      Axis::LeftStickX => self.stick.x = value,
+      Axis::LeftStickY => self.stick.y = -value,
+      _ => (),
+    }
+  }
+
+  /// Stops all movement, like when input goes somewhere else
+  pub fn clear(&mut self) {
+    self.held.clear();
+    self.stick = Vec2::zeros();
+  }
+
+  /// The direction to move in, no longer than 1. Shorter when the stick is only pushed part way.
+  pub fn direction(&self) -> Vec2 {
+    let keys = self.held.iter().filter_map(|a| a.direction()).fold(Vec2::zeros(), |d, a| d + a);
+    let stick = if self.stick.norm() < STICK_DEADZONE { Vec2::zeros() } else { self.stick };
+    let dir = keys + stick;
+    if dir.norm() > 1.0 {
+      dir.normalize()
+    } else {
+      dir
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_default_keymap_parses() {
+    let keymap = Keymap::parse(DEFAULT_KEYMAP).unwrap();
+    assert_eq!(keymap.key_action(KeyCode::W, KeyMods::empty()), Some(Action::MoveUp));
+    assert_eq!(keymap.key_action(KeyCode::Up, KeyMods::empty()), Some(Action::MoveUp));
+    assert_eq!(keymap.button_action(Button::DPadUp), Some(Action::MoveUp));
+  }
+
+  #[test]
+  fn test_modifiers() {
+    let keymap =
+      Keymap::parse("regenerate = Ctrl+R\nreplay_back = P\nreplay_play = Shift+P").unwrap();
+    assert_eq!(keymap.key_action(KeyCode::R, KeyMods::CTRL), Some(Action::Regenerate));
+    assert_eq!(keymap.key_action(KeyCode::R, KeyMods::empty()), None);
+    assert_eq!(keymap.key_action(KeyCode::P, KeyMods::SHIFT), Some(Action::ReplayPlay));
+    // Unmodified bindings still work with other modifiers held
+    assert_eq!(keymap.key_action(KeyCode::P, KeyMods::CTRL), Some(Action::ReplayBack));
+    assert_eq!(keymap.key_actions(KeyCode::P), vec![Action::ReplayBack, Action::ReplayPlay]);
+  }
+
+  #[test]
+  fn test_bad_config() {
+    assert!(Keymap::parse("jump = Space").unwrap_err().contains("line 1"));
+    assert!(Keymap::parse("# fine\nquit = Hyper+Q").unwrap_err().contains("line 2"));
+    assert!(Keymap::parse("quit = pad:Banana").is_err());
+    assert!(Keymap::parse("quit Q").is_err());
+  }
+
+  #[test]
+  fn test_held_movement() {
+    let mut moving = MoveInput::new();
+    moving.press(Action::MoveUp);
+    moving.press(Action::MoveUp);
+    moving.press(Action::Quit);
+    assert_eq!(moving.direction(), Vec2::new(0.0, -1.0));
+    // Diagonals aren't any faster
+    moving.press(Action::MoveRight);
+    assert!((moving.direction().norm() - 1.0).abs() < 0.0001);
+    moving.release(Action::MoveUp);
+    assert_eq!(moving.direction(), Vec2::new(1.0, 0.0));
+    moving.release(Action::MoveRight);
+    assert_eq!(moving.direction(), Vec2::zeros());
+  }
+
+  #[test]
+  fn test_stick_deadzone() {
+    let mut moving = MoveInput::new();
+    moving.set_axis(Axis::LeftStickX, 0.1);
+    assert_eq!(moving.direction(), Vec2::zeros());
+    moving.set_axis(Axis::LeftStickY, 0.5);
+    assert_eq!(moving.direction(), Vec2::new(0.1, -0.5));
+    moving.clear();
+    assert_eq!(moving.direction(), Vec2::zeros());
+  }
+}
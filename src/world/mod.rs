@@ -1,28 +1,82 @@
 extern crate ncollide2d as nc;
 
+use crate::agents::monster::{Monster, MonsterKind};
 use crate::agents::player::Player;
-use crate::collision::{new_collw, CollW, CollidableDat, GameObjRegistrar};
+use crate::agents::Agent;
+use crate::collision::{
+  new_collw, CollGroups, CollW, Collidable, CollidableDat, GameObjRegistrar, SLOW_GROUP,
+};
 use crate::dungeongen::level::Level;
-use crate::util::Point;
+use crate::dungeongen::rooms::Door;
+use crate::items::floor_item::FloorItem;
+use crate::items::Item;
+use crate::util::geom::CenterOriginRect;
+use crate::util::{Meters, Point, Vec2};
+use crate::world::noise::{Noise, NoiseKind};
+use na::Isometry2;
+use nc::bounding_volume::{BoundingVolume, AABB};
+use nc::query;
+use nc::world::CollisionObjectHandle;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod camera;
+mod console;
+mod input;
+pub mod noise;
 pub mod render;
 
+/// Walkers in slowing terrain only cover this fraction of their normal step
+static SLOWED_STEP: f32 = 0.5;
+/// Items this close to the player can be picked up
+static PICKUP_REACH: Meters = 1.0;
+/// Doors this close to the player can be opened and closed
+static DOOR_REACH: Meters = 1.5;
+/// How far the player walks between footsteps
+static FOOTSTEP_STRIDE: Meters = 1.5;
+
 /// The entire world. Contains all world objects, and handles interaction
 /// between subsystems.
 pub struct World {
   level: Level,
   player: Player,
+  monsters: Vec<Monster>,
+  /// Items lying around, by entity ID
+  floor_items: BTreeMap<usize, (FloorItem, CollisionObjectHandle)>,
+  /// How far the player has walked since their last footstep
+  stride: Meters,
   collision: CollW,
   // TODO: Move to Specs and use that for entity IDs?
   next_eid: AtomicUsize, // Could be atomic
 }
 
 impl World {
+  /// A world with an empty level, which gets a real one once it's been generated
   pub fn new() -> World {
-    let level = Level::new();
+    let level = Level::empty();
     let player = Player::new(level.middle());
-    World { level, player, collision: new_collw(), next_eid: AtomicUsize::new(0) }
+    World {
+      level,
+      player,
+      monsters: Vec::new(),
+      floor_items: BTreeMap::new(),
+      stride: 0.0,
+      collision: new_collw(),
+      next_eid: AtomicUsize::new(0),
+    }
+  }
+
+  /// Swaps in a newly generated level and puts the player back in the middle of it
+  pub fn set_level(&mut self, level: Level) {
+    self.player = Player::new(level.middle());
+    self.floor_items.clear();
+    self.level = level;
+    self.collision = new_collw();
+    self.add_level_contents_to_collision();
+    for item in self.level.floor_items.clone() {
+      self.place_item(item);
+    }
+    self.monsters = self.level.spawns.iter().map(|s| Monster::new(s.kind, s.pos)).collect();
   }
 
   fn add_level_contents_to_collision(&mut self) {
@@ -34,6 +88,191 @@ impl World {
     self.collision.update();
   }
 
+  /// Moves the player by `by`, or less if they're wading through something or the terrain is hard
+  /// going. Returns how far they actually moved, which is nothing if the way is blocked.
+  pub fn move_player(&mut self, by: Vec2) -> Vec2 {
+    let step = self.allowed_step(&self.player, by);
+    self.player.trans(step);
+    step
+  }
+
+  /// How far `agent` trying to move by `by` actually gets, which is less in slowing terrain and
+  /// nothing if anything would get in the way of its whole body
+  fn allowed_step<A: Agent>(&self, agent: &A, by: Vec2) -> Vec2 {
+    let from = agent.pos();
+    let walker = CollGroups::walker_cg();
+    let in_slow_terrain = |p: Point| {
+      self
+        .collision
+        .interferences_with_point(&p, &walker)
+        .any(|o| o.collision_groups().is_member_of(SLOW_GROUP))
+    };
+    let step =
+      if in_slow_terrain(from) || in_slow_terrain(from + by) { by * SLOWED_STEP } else { by };
+    let step = step / self.level.terrain_at(from).map_or(1.0, |t| t.move_cost());
+    // Sweep the agent's shape along the step, checking everything in the area it passes over
+    let body = agent.shape();
+    let reach = Vec2::new(agent.radius(), agent.radius());
+    let swept = AABB::new(from - reach, from + reach)
+      .merged(&AABB::new(from + step - reach, from + step + reach));
+    let (start, still) = (Isometry2::new(from.coords, 0.0), Vec2::zeros());
+    let blocked = self
+      .collision
+      .interferences_with_aabb(&swept, &walker)
+      .filter(|o| !o.collision_groups().is_member_of(SLOW_GROUP))
+      .filter_map(|o| {
+        query::time_of_impact(
+          &start,
+          &step,
+          body.as_ref(),
+          o.position(),
+          &still,
+          o.shape().as_ref(),
+        )
+      })
+      .any(|toi| toi <= 1.0);
+    if blocked {
+      Vec2::zeros()
+    } else {
+      step
+    }
+  }
+
+  /// Damage per second done by the terrain at `p`
+  fn hazard_at(&self, p: Point) -> f32 {
+    self.level.terrain_at(p).map_or(0.0, |t| t.hazard())
+  }
+
+  /// Runs the player's movement for `dt` seconds while they're pushed towards `dir`. Running into
+  /// something stops them dead, and every few steps they make a noise. Whatever they're standing
+  /// on hurts them first if it's hazardous. Returns how far they moved.
+  pub fn step_player(&mut self, dir: Vec2, dt: f32) -> Vec2 {
+    let hazard = self.hazard_at(self.player.pos());
+    self.player.stats_mut().take_hazard(hazard * dt);
+    let by = self.player.walk(dir, dt);
+    if by == Vec2::zeros() {
+      return by;
+    }
+    let moved = self.move_player(by);
+    if moved == Vec2::zeros() {
+      self.player.kinematics.stop();
+    }
+    self.stride += moved.norm();
+    if self.stride >= FOOTSTEP_STRIDE {
+      self.stride = 0.0;
+      self.make_noise(Noise::new(NoiseKind::Footstep, self.player.pos()));
+    }
+    moved
+  }
+
+  /// Lets every monster know about a noise, returning how many of them heard it
+  pub fn make_noise(&mut self, noise: Noise) -> usize {
+    let rooms = &self.level.rooms;
+    let heard =
+      self.monsters.iter_mut().map(|m| m.hear(noise.pos, noise.loudness_at(m.pos(), rooms)));
+    heard.filter(|&h| h).count()
+  }
+
+  /// Walks monsters towards whatever they heard for `dt` seconds. Monsters that run into
+  /// something on the way give up. Hazardous terrain hurts them same as the player.
+  pub fn update_monsters(&mut self, dt: f32) {
+    for ix in 0..self.monsters.len() {
+      let hazard = self.hazard_at(self.monsters[ix].pos());
+      self.monsters[ix].stats_mut().take_hazard(hazard * dt);
+      let want = self.monsters[ix].investigate_step(dt);
+      if want == Vec2::zeros() {
+        continue;
+      }
+      let step = self.allowed_step(&self.monsters[ix], want);
+      if step == Vec2::zeros() {
+        self.monsters[ix].give_up();
+      } else {
+        self.monsters[ix].trans(step);
+      }
+    }
+  }
+
+  /// Opens or closes the closest door in reach, which everything nearby hears. Returns whether
+  /// the door is now open, or `None` if there's no door in reach.
+  pub fn toggle_door(&mut self) -> Option<bool> {
+    let pos = self.player.pos();
+    let dist = |d: &Door| na::distance(&d.center(), &pos);
+    let door = self
+      .level
+      .rooms
+      .iter_mut()
+      .flat_map(|r| r.doors_mut().iter_mut())
+      .filter(|d| dist(d) <= DOOR_REACH)
+      .min_by(|a, b| dist(a).partial_cmp(&dist(b)).unwrap())?;
+    let open = !door.is_open();
+    door.set_open(open);
+    let at = door.center();
+    self.make_noise(Noise::new(NoiseKind::Door, at));
+    Some(open)
+  }
+
+  /// Moves the player straight to `to`, whatever is in the way
+  pub fn teleport_player(&mut self, to: Point) {
+    let by = to - self.player.pos();
+    self.player.trans(by);
+  }
+
+  /// Puts a monster at `at`, of the usual size for its kind unless `radius` says otherwise
+  pub fn spawn_monster(&mut self, kind: MonsterKind, at: Point, radius: Option<Meters>) {
+    self.monsters.push(Monster::with_radius(kind, at, radius.unwrap_or_else(|| kind.radius())));
+  }
+
+  pub fn floor_items(&self) -> impl Iterator<Item = &FloorItem> {
+    self.floor_items.values().map(|(item, _)| item)
+  }
+
+  /// Leaves an item lying on the floor
+  pub fn place_item(&mut self, item: FloorItem) {
+    let eid = self.next_eid.fetch_add(1, Ordering::Relaxed);
+    let handle = self.collision.register(&item, CollidableDat::new(item.coltype(), eid));
+    self.collision.update();
+    self.floor_items.insert(eid, (item, handle));
+    self.sync_floor_items();
+  }
+
+  /// Copies what's lying on the floor back into the level, so its stats and snapshots keep up
+  /// with pickups and drops
+  fn sync_floor_items(&mut self) {
+    self.level.floor_items = self.floor_items().cloned().collect();
+  }
+
+  /// Entity ID of the closest item within the player's reach, if there is one
+  fn item_in_reach(&self) -> Option<usize> {
+    let pos = self.player.pos();
+    let reach = Vec2::new(PICKUP_REACH, PICKUP_REACH);
+    let aabb = AABB::new(pos - reach, pos + reach);
+    let dist = |eid: &usize| na::distance(&self.floor_items[eid].0.pos, &pos);
+    self
+      .collision
+      .interferences_with_aabb(&aabb, &CollGroups::reach_cg())
+      .map(|o| o.data().id)
+      .filter(|eid| self.floor_items.contains_key(eid) && dist(eid) <= PICKUP_REACH)
+      .min_by(|a, b| dist(a).partial_cmp(&dist(b)).unwrap())
+  }
+
+  /// Picks up the closest item in reach, returning what it was
+  pub fn pick_up(&mut self) -> Result<Item, String> {
+    let eid = self.item_in_reach().ok_or_else(|| "Nothing in reach to pick up".to_string())?;
+    let item = self.floor_items[&eid].0.item;
+    self.player.pick_up(item).map_err(|e| e.to_string())?;
+    let (_, handle) = self.floor_items.remove(&eid).unwrap();
+    self.collision.remove(&[handle]);
+    self.sync_floor_items();
+    Ok(item)
+  }
+
+  /// Drops the item in the player's inventory slot `ix` at their feet
+  pub fn drop_item(&mut self, ix: usize) -> Option<Item> {
+    let item = self.player.drop_item(ix)?;
+    self.place_item(FloorItem::new(item, self.player.pos()));
+    Some(item)
+  }
+
   fn collision_test(&self, p: Point) {
     let mut cgs = nc::world::CollisionGroups::new();
     cgs.set_membership(&[2]);
@@ -45,3 +284,139 @@ impl World {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::terrain::{Terrain, TerrainMap};
+
+  fn world_with(terrain: nc::world::CollisionGroups) -> World {
+    let mut world = World {
+      level: Level::new(),
+      player: Player::new(Point::new(0.0, 0.0)),
+      monsters: Vec::new(),
+      floor_items: BTreeMap::new(),
+      stride: 0.0,
+      collision: new_collw(),
+      // The blob below takes the first ID
+      next_eid: AtomicUsize::new(1),
+    };
+    let blob = AABB::new(Point::new(1.5, -1.0), Point::new(3.5, 1.0));
+    world.collision.register_with_group(&blob, terrain, CollidableDat::new(blob.coltype(), 0));
+    world.collision.update();
+    world
+  }
+
+  #[test]
+  fn test_open_ground_is_walkable() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    assert_eq!(world.move_player(Vec2::new(0.0, 1.0)), Vec2::new(0.0, 1.0));
+    assert_eq!(world.player.pos(), Point::new(0.0, 1.0));
+  }
+
+  #[test]
+  fn test_chasms_block_walking() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    assert_eq!(world.move_player(Vec2::new(1.0, 0.0)), Vec2::new(1.0, 0.0));
+    assert_eq!(world.move_player(Vec2::new(1.0, 0.0)), Vec2::zeros());
+    assert_eq!(world.player.pos(), Point::new(1.0, 0.0));
+  }
+
+  #[test]
+  fn test_walking_into_a_wall_stops() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    // Builds up speed towards the chasm until it's right up against it
+    let mut moved = Vec2::new(1.0, 0.0);
+    for _ in 0..120 {
+      moved = world.step_player(Vec2::new(1.0, 0.0), 1.0 / 60.0);
+    }
+    assert_eq!(moved, Vec2::zeros());
+    assert_eq!(world.player.kinematics.vel, Vec2::zeros());
+    assert!(world.player.pos().x < 1.5);
+  }
+
+  #[test]
+  fn test_pick_up_and_drop() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    let sword = Item::named("sword").unwrap();
+    world.place_item(FloorItem::new(sword, Point::new(0.0, 3.0)));
+    world.place_item(FloorItem::new(Item::named("bread").unwrap(), Point::new(0.0, 1.8)));
+    world.move_player(Vec2::new(0.0, 2.5));
+    // Both are in reach, but the sword is closer
+    assert_eq!(world.pick_up(), Ok(sword));
+    assert_eq!(world.player.inventory().get(0), Some(&sword));
+    assert_eq!(world.floor_items().count(), 1);
+    assert_eq!(world.level.stats().loot, 1);
+    world.move_player(Vec2::new(0.0, -0.5));
+    assert_eq!(world.drop_item(0), Some(sword));
+    assert_eq!(world.level.snapshot().floor_items.len(), 2);
+    assert!(world.pick_up().is_ok());
+    assert!(world.pick_up().is_ok());
+    assert!(world.pick_up().is_err());
+  }
+
+  #[test]
+  fn test_monsters_investigate_noises() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    world.spawn_monster(MonsterKind::Rat, Point::new(0.0, 5.0), None);
+    world.spawn_monster(MonsterKind::Skeleton, Point::new(0.0, 40.0), None);
+    // Right the other side of the chasm
+    world.spawn_monster(MonsterKind::Rat, Point::new(5.0, 0.0), None);
+    assert_eq!(world.make_noise(Noise::new(NoiseKind::Footstep, Point::new(0.0, 0.0))), 2);
+    for _ in 0..120 {
+      world.update_monsters(1.0 / 60.0);
+    }
+    let [near, far, blocked] = [&world.monsters[0], &world.monsters[1], &world.monsters[2]];
+    assert!(na::distance(&near.pos(), &Point::new(0.0, 0.0)) <= near.radius());
+    assert_eq!(far.pos(), Point::new(0.0, 40.0));
+    assert_eq!(blocked.investigating(), None);
+    assert!(blocked.pos().x > 3.5);
+  }
+
+  #[test]
+  fn test_big_agents_stop_short() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    let radius = 1.0;
+    world.spawn_monster(MonsterKind::Ogre, Point::new(-3.0, 0.0), Some(radius));
+    world.monsters[0].hear(Point::new(2.5, 0.0), 100.0);
+    for _ in 0..120 {
+      world.update_monsters(1.0 / 60.0);
+    }
+    // Its edge gets right up to the chasm without its middle going anywhere near it
+    let ogre = &world.monsters[0];
+    assert_eq!(ogre.investigating(), None);
+    assert!(ogre.pos().x + radius <= 1.5, "{}", ogre.pos());
+    assert!(ogre.pos().x + radius > 1.45, "{}", ogre.pos());
+  }
+
+  #[test]
+  fn test_water_slows_walking() {
+    let mut world = world_with(CollGroups::slow_cg());
+    world.move_player(Vec2::new(1.0, 0.0));
+    assert_eq!(world.move_player(Vec2::new(1.0, 0.0)), Vec2::new(0.5, 0.0));
+    assert_eq!(world.move_player(Vec2::new(1.0, 0.0)), Vec2::new(0.5, 0.0));
+  }
+
+  #[test]
+  fn test_lava_burns_agents() {
+    let mut world = world_with(CollGroups::walk_block_cg());
+    world.spawn_monster(MonsterKind::Rat, Point::new(0.0, 5.0), None);
+    for _ in 0..60 {
+      world.step_player(Vec2::zeros(), 1.0 / 60.0);
+      world.update_monsters(1.0 / 60.0);
+    }
+    // Nothing underfoot yet
+    assert_eq!(world.player.stats().health, world.player.stats().max_health);
+    assert!(world.monsters[0].stats().is_alive());
+
+    world.level.terrain = TerrainMap::filled(Terrain::Lava, 20, 20, Vec2::new(1.0, 1.0));
+    for _ in 0..60 {
+      world.step_player(Vec2::zeros(), 1.0 / 60.0);
+      world.update_monsters(1.0 / 60.0);
+    }
+    // A second on lava, give or take rounding
+    let lost = world.player.stats().max_health - world.player.stats().health;
+    assert!(lost >= 9 && lost <= 10, "{}", lost);
+    assert!(!world.monsters[0].stats().is_alive());
+  }
+}
@@ -0,0 +1,89 @@
+use crate::dungeongen::rooms::Room;
+use crate::util::geom::{segment_intersection, CenterOriginRect};
+use crate::util::Point;
+
+/// How much quieter a noise gets for every room wall it goes through, in decibels
+static WALL_DAMPING: f32 = 20.0;
+/// How much quieter a noise gets going through a closed door. Open doors let it straight through.
+static DOOR_DAMPING: f32 = 10.0;
+
+/// The things that make noise
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseKind {
+  Footstep,
+  Combat,
+  Door,
+}
+
+impl NoiseKind {
+  /// How loud the noise is a meter away, in decibels
+  pub fn loudness(self) -> f32 {
+    match self {
+      NoiseKind::Footstep => 30.0,
+      NoiseKind::Combat => 50.0,
+      NoiseKind::Door => 40.0,
+    }
+  }
+}
+
+/// A noise made somewhere in the level
+#[derive(new, Debug, Copy, Clone, PartialEq)]
+pub struct Noise {
+  pub kind: NoiseKind,
+  pub pos: Point,
+}
+
+impl Noise {
+  /// How loud the noise is by the time it reaches `at`, in decibels. It falls off with the square
+  /// of the distance, and gets muffled by every wall and closed door in a straight line between.
+  pub fn loudness_at(&self, at: Point, rooms: &[Room]) -> f32 {
+    let dist = na::distance(&self.pos, &at).max(1.0);
+    let crossed = |r: &CenterOriginRect| r.crossed_by(self.pos, at);
+    let walls = rooms
+      .iter()
+      .map(|room| {
+        let rect_walls = room.walls.iter().filter(|(w, _)| crossed(w)).count();
+        let segments = room
+          .segments
+          .iter()
+          .filter(|s| segment_intersection(self.pos, at, s.a, s.b).is_some())
+          .count();
+        rect_walls + segments
+      })
+      .sum::<usize>();
+    let closed_doors =
+      rooms.iter().flat_map(|room| room.doors()).filter(|d| !d.is_open() && crossed(*d)).count();
+    self.kind.loudness()
+      - 20.0 * dist.log10()
+      - walls as f32 * WALL_DAMPING
+      - closed_doors as f32 * DOOR_DAMPING
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::dungeongen::direction::Direction;
+
+  #[test]
+  fn test_walls_and_doors_muffle_noise() {
+    // The door is in the middle of the north wall, which is at y = 5
+    let mut rooms =
+      vec![
+        Room::new_with_centered_door(Point::new(10.0, 10.0), 10.0, 10.0, Direction::North).unwrap()
+      ];
+    let inside = Point::new(10.0, 9.0);
+    let noise = Noise::new(NoiseKind::Door, inside);
+    // Ten meters away is 20dB quieter than one meter away
+    let open_air = noise.loudness_at(Point::new(20.0, 9.0), &[]);
+    assert!((open_air - 20.0).abs() < 1e-4, "{}", open_air);
+
+    let through_wall = noise.loudness_at(Point::new(20.0, 9.0), &rooms);
+    assert!((through_wall - (open_air - WALL_DAMPING)).abs() < 1e-4, "{}", through_wall);
+    let through_door = noise.loudness_at(Point::new(10.0, -1.0), &rooms);
+    assert!((through_door - open_air).abs() < 1e-4, "{}", through_door);
+    rooms[0].doors_mut()[0].set_open(false);
+    let through_closed = noise.loudness_at(Point::new(10.0, -1.0), &rooms);
+    assert!((through_closed - (open_air - DOOR_DAMPING)).abs() < 1e-4, "{}", through_closed);
+  }
+}
@@ -1,47 +1,300 @@
 use crate::agents::mouse_mover::MouseTarget;
 use crate::agents::Agent;
-use crate::collision::Compound2D;
+use crate::collision::{Compound2D, Polyline2D};
+use crate::dungeongen::generator::{GenMsg, LevelGenerator};
+use crate::dungeongen::level::{Level, LevelSnapshot};
+use crate::dungeongen::level_rng::rand_seed;
 use crate::util::context_help::ContextHelp;
-use crate::util::Assets;
+use crate::util::{Assets, Point, Vec2};
+use crate::world::camera::Camera;
+use crate::world::console::{Command, Console, DebugFlag};
+use crate::world::input::{Action, Keymap, MoveInput};
+use crate::world::noise::Noise;
 use crate::world::World;
 use ggez::event;
-use ggez::event::KeyMods;
+use ggez::event::{Axis, Button, KeyMods, MouseButton};
 use ggez::graphics;
 use ggez::graphics::{Color, DrawParam, Drawable};
+use ggez::input::gamepad::GamepadId;
 use ggez::input::keyboard::KeyCode;
 use ggez::input::mouse;
 use ggez::timer;
 use ggez::{Context, GameResult};
-use nalgebra::Vector2;
 use std;
 use std::time::Duration;
 
+/// Where `spawn` puts things, relative to the player
+static SPAWN_OFFSET: (f32, f32) = (2.0, 0.0);
+
 pub struct WorldRender {
   world: World,
-  fastmode: bool,
+  /// Level generation ticks to run each frame, or `None` to generate as fast as possible
+  gen_speed: Option<usize>,
   assets: Assets,
   debug: bool,
-  // TODO: Move
-  level_finished: bool,
+  /// Whether the debug view shows collision bounding volumes
+  show_collision_bb: bool,
+  /// Whether the debug view shows placements that level generation threw away
+  show_rejections: bool,
+  console: Console,
+  keymap: Keymap,
+  /// Movement inputs currently held down
+  move_input: MoveInput,
+  /// Builds the next level in the background. Gone once the level is finished.
+  generator: Option<LevelGenerator>,
+  /// What the level looked like last time we heard from the generator
+  preview: Option<LevelSnapshot>,
+  /// Frame of the level's generation being replayed in the debug view, if any
+  replay_frame: Option<usize>,
+  /// Whether the replay advances by itself
+  replay_playing: bool,
   mouse_target: MouseTarget,
+  camera: Camera,
 }
 
 impl WorldRender {
   pub fn new(world: World, ctx: &mut Context) -> GameResult<WorldRender> {
     let assets = Assets::new(ctx);
     let mouse_target = MouseTarget::new(ctx)?;
+    let camera = Camera::new(world.level.dims());
+    let gen_speed = None;
     Ok(WorldRender {
       world,
-      fastmode: true,
+      gen_speed,
       assets,
       debug: false,
-      level_finished: false,
+      show_collision_bb: true,
+      show_rejections: true,
+      console: Console::new(),
+      keymap: Keymap::load(),
+      move_input: MoveInput::new(),
+      generator: Some(LevelGenerator::spawn(Level::new(), gen_speed)),
+      preview: None,
+      replay_frame: None,
+      replay_playing: false,
       mouse_target,
+      camera,
     })
   }
 
   fn stop_render(&mut self) {
-    self.world.level.level_gen_finished = true
+    if let Some(generator) = &self.generator {
+      generator.finish_now();
+    }
+  }
+
+  /// Throws away the current level, including one that's still being generated, and starts
+  /// generating a new one `depth` levels down. The same seed and depth always generate the same
+  /// level.
+  fn regenerate(&mut self, seed: Option<u64>, depth: u32) -> u64 {
+    // Dropping the old generator cancels it
+    self.generator = None;
+    self.preview = None;
+    self.stop_replay();
+    self.world = World::new();
+    self.camera = Camera::new(self.world.level.dims());
+    let level = Level::with_seed_at_depth(seed.unwrap_or_else(rand_seed), depth);
+    let seed = level.seed();
+    info!("Generating level from seed {} at depth {}", seed, depth);
+    self.generator = Some(LevelGenerator::spawn(level, self.gen_speed));
+    seed
+  }
+
+  fn set_gen_speed(&mut self, ticks_per_frame: Option<usize>) {
+    self.gen_speed = ticks_per_frame;
+    if let Some(generator) = &self.generator {
+      generator.set_speed(ticks_per_frame);
+    }
+  }
+
+  fn toggle_debug(&mut self) {
+    self.debug = !self.debug;
+    info!("Debug mode now {}", self.debug);
+    if self.debug && self.generator.is_none() {
+      info!("{}", self.world.level.stats());
+    }
+  }
+
+  /// Carries out a console command, returning what to print back
+  fn run_command(&mut self, cmd: Command) -> String {
+    match cmd {
+      Command::Help => Command::help().to_string(),
+      Command::Regen { seed, depth } => {
+        format!("Generating level from seed {} at depth {}", self.regenerate(seed, depth), depth)
+      }
+      Command::Teleport(to) => {
+        self.world.teleport_player(to);
+        self.camera.resume_following();
+        format!("Teleported to {}", to)
+      }
+      Command::SpawnMonster { kind, radius } => {
+        let at = self.world.player.pos() + Vec2::new(SPAWN_OFFSET.0, SPAWN_OFFSET.1);
+        self.world.spawn_monster(kind, at, radius);
+        format!("Spawned a {} at {}", kind.name(), at)
+      }
+      Command::SetFastmode(ticks_per_frame) => {
+        self.set_gen_speed(ticks_per_frame);
+        match ticks_per_frame {
+          Some(ticks) => format!("Generating {} ticks per frame", ticks),
+          None => "Generating as fast as possible".to_string(),
+        }
+      }
+      Command::Toggle(flag) => {
+        let on = match flag {
+          DebugFlag::Debug => {
+            self.toggle_debug();
+            self.debug
+          }
+          DebugFlag::CollisionBB => {
+            self.show_collision_bb = !self.show_collision_bb;
+            self.show_collision_bb
+          }
+          DebugFlag::Rejections => {
+            self.show_rejections = !self.show_rejections;
+            self.show_rejections
+          }
+        };
+        format!("{:?} is now {}", flag, if on { "on" } else { "off" })
+      }
+      Command::Stats if self.generator.is_some() => "The level is still generating".to_string(),
+      Command::Stats => self.world.level.stats().to_string(),
+      Command::Inventory => {
+        let inv = self.world.player.inventory();
+        let mut lines = vec![format!("Carrying {}/{}kg", inv.weight(), inv.max_weight())];
+        for (ix, (item, equipped)) in inv.items().enumerate() {
+          lines.push(format!("{}: {}{}", ix, item, if equipped { " (equipped)" } else { "" }));
+        }
+        lines.join("\n")
+      }
+      Command::Use(ix) => self.world.player.use_item(ix).unwrap_or_else(|e| e.to_string()),
+      Command::Drop(ix) => match self.world.drop_item(ix) {
+        Some(item) => format!("Dropped the {}", item.name),
+        None => "Nothing is in that slot".to_string(),
+      },
+      Command::Noise(kind) => {
+        let heard = self.world.make_noise(Noise::new(kind, self.world.player.pos()));
+        format!("{} monsters heard the {:?} noise", heard, kind)
+      }
+    }
+  }
+
+  /// Handles keys while the console is open. Typed characters come in through
+  /// `text_input_event` instead.
+  fn console_key(&mut self, keycode: KeyCode) {
+    match keycode {
+      KeyCode::Escape => self.console.open = false,
+      KeyCode::Back => self.console.backspace(),
+      KeyCode::Tab => self.console.complete(),
+      KeyCode::Up => self.console.history_back(),
+      KeyCode::Down => self.console.history_forward(),
+      KeyCode::Return | KeyCode::NumpadEnter => {
+        let line = self.console.submit();
+        if line.is_empty() {
+          return;
+        }
+        let reply = match Command::parse(&line) {
+          Ok(cmd) => self.run_command(cmd),
+          Err(e) => e,
+        };
+        self.console.print(&reply);
+      }
+      _ => (),
+    }
+  }
+
+  /// Shows frame `ix` of the finished level's generation. Replays only work once generation is
+  /// done, since the worker thread owns the level until then.
+  fn show_replay_frame(&mut self, ix: usize) {
+    if self.generator.is_some() {
+      return;
+    }
+    let last = self.world.level.recording().frames.len().saturating_sub(1);
+    let ix = ix.min(last);
+    self.replay_frame = Some(ix);
+    self.preview = Some(self.world.level.replay(ix));
+  }
+
+  /// Goes back to showing the level as it finished
+  fn stop_replay(&mut self) {
+    self.replay_frame = None;
+    self.replay_playing = false;
+    if self.generator.is_none() {
+      self.preview = Some(self.world.level.snapshot());
+    }
+  }
+
+  /// Does whatever an input asked for. Replay actions only work in debug mode.
+  fn perform(&mut self, action: Action) {
+    if action.direction().is_some() {
+      self.move_input.press(action);
+      return;
+    }
+    let last = self.world.level.recording().frames.len().saturating_sub(1);
+    let cur = self.replay_frame.unwrap_or(last);
+    match action {
+      Action::PickUp => {
+        let msg = match self.world.pick_up() {
+          Ok(item) => format!("Picked up the {}", item.name),
+          Err(e) => e,
+        };
+        info!("{}", msg);
+        self.console.print(&msg);
+      }
+      Action::ToggleDoor => {
+        let msg = match self.world.toggle_door() {
+          Some(true) => "Opened the door",
+          Some(false) => "Closed the door",
+          None => "No door in reach",
+        };
+        info!("{}", msg);
+        self.console.print(msg);
+        // The level on screen is a snapshot, so it needs retaking to show the door
+        if self.generator.is_none() && self.replay_frame.is_none() {
+          self.preview = Some(self.world.level.snapshot());
+        }
+      }
+      Action::FinishGeneration => self.stop_render(),
+      Action::ToggleFastmode => {
+        let speed = if self.gen_speed.is_some() { None } else { Some(1) };
+        self.set_gen_speed(speed);
+      }
+      Action::Regenerate => {
+        self.regenerate(None, 1);
+      }
+      Action::ToggleDebug => self.toggle_debug(),
+      Action::OpenConsole => {
+        self.console.open = true;
+        // Whatever was held down won't see its release while the console has the keyboard
+        self.move_input.clear();
+      }
+      Action::Quit => std::process::exit(0),
+      _ if !self.debug => (),
+      Action::ReplayBack => self.show_replay_frame(cur.saturating_sub(1)),
+      Action::ReplayForward => self.show_replay_frame(cur + 1),
+      Action::ReplayPrevRejection => {
+        let prev = self.world.level.recording().next_rejection(cur, false);
+        self.show_replay_frame(prev.unwrap_or(0));
+      }
+      Action::ReplayNextRejection => {
+        let next = self.world.level.recording().next_rejection(cur, true);
+        self.show_replay_frame(next.unwrap_or(last));
+      }
+      Action::ReplayPlay => {
+        if self.replay_frame.is_none() || cur == last {
+          self.show_replay_frame(0);
+        }
+        self.replay_playing = !self.replay_playing;
+      }
+      Action::ReplayExit => self.stop_replay(),
+      Action::ExportGif => {
+        let path = std::path::Path::new("generation.gif");
+        match self.world.level.export_gif(path) {
+          Ok(()) => info!("Saved level generation to {}", path.display()),
+          Err(e) => warn!("Couldn't save level generation GIF: {}", e),
+        }
+      }
+      Action::MoveUp | Action::MoveDown | Action::MoveLeft | Action::MoveRight => (),
+    }
   }
 }
 
@@ -52,17 +305,34 @@ impl event::EventHandler for WorldRender {
       return Ok(());
     }
 
-    // Tick the simulation TODO: Move elsewhere.
-    if !self.world.level.level_gen_finished {
-      // TODO: Configurable fastmode speed
-      let i = if self.fastmode { 40 } else { 2 };
-      for _ in 1..i {
-        self.world.level.tick_level_gen();
+    // Pick up whatever the generator has made since last frame
+    match self.generator.as_ref().and_then(|g| g.poll()) {
+      Some(GenMsg::Progress(snapshot)) => self.preview = Some(snapshot),
+      Some(GenMsg::Finished(level)) => {
+        self.preview = Some(level.snapshot());
+        self.world.set_level(*level);
+        self.generator = None;
+        if self.debug {
+          info!("{}", self.world.level.stats());
+        }
+      }
+      None => (),
+    }
+    if self.replay_playing {
+      match self.replay_frame {
+        Some(ix) if ix + 1 < self.world.level.recording().frames.len() => {
+          self.show_replay_frame(ix + 1)
+        }
+        _ => self.replay_playing = false,
       }
-    } else if !self.level_finished {
-      self.world.add_level_contents_to_collision();
-      self.level_finished = true
     }
+    let dt = 1.0 / DESIRED_FPS as f32;
+    let moved = self.world.step_player(self.move_input.direction(), dt);
+    self.world.update_monsters(dt);
+    if moved != Vec2::zeros() {
+      self.camera.resume_following();
+    }
+    self.camera.follow(self.world.player.pos());
     Ok(())
   }
 
@@ -70,16 +340,31 @@ impl event::EventHandler for WorldRender {
     graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 1.0));
 
     let mouse_p = mouse::position(ctx);
-    let w_mouse_p = self.world.level.sspace_to_lspace(ctx, mouse_p.into());
+    let w_mouse_p = self.camera.sspace_to_lspace(ctx, mouse_p.into());
 
     // First thing that is drawn is the level itself
-    self.world.level.draw(ctx)?;
+    if let Some(preview) = &self.preview {
+      preview.draw(ctx, self.camera.draw_param(ctx))?;
+    }
+    // Finished levels hand their items over to the world, which draws them at the level's scale
+    for item in self.world.floor_items() {
+      item.draw(ctx)?;
+    }
     // Render debug info that needs to be drawn at level scale
-    if self.debug {
-      // Render all collision bounding volumes
+    if self.debug && self.show_rejections {
+      if let Some(preview) = &self.preview {
+        preview.draw_debug(ctx, self.camera.draw_param(ctx))?;
+      }
+    }
+    if self.debug && self.show_collision_bb {
+      // Render all collision bounding volumes. Polylines get drawn as-is, their bounding box
+      // would cover the whole cave.
       for c in self.world.collision.collision_objects() {
         let shape_h = c.shape();
-        if shape_h.is_shape::<Compound2D>() {
+        if let Some(pline) = shape_h.as_shape::<Polyline2D>() {
+          let points: Vec<Point> = pline.points().iter().map(|p| c.position() * p).collect();
+          ctx.draw_polyline(&points)?;
+        } else if shape_h.is_shape::<Compound2D>() {
           let o_comp = shape_h.as_shape::<Compound2D>();
           if let Some(comp) = o_comp {
             for s in comp.shapes() {
@@ -97,63 +382,104 @@ impl event::EventHandler for WorldRender {
     // Reset scaling
     graphics::set_transform(ctx, DrawParam::default().to_matrix());
     graphics::apply_transformations(ctx)?;
-    // Draw the player
-    let scaler = self.world.level.lscale(ctx);
-    self.world.player.draw(ctx, &mut self.assets, scaler.scale.into())?;
+    // Draw the player and everything else that moves around
+    self.world.player.draw(ctx, &mut self.assets, &self.camera)?;
+    for monster in &self.world.monsters {
+      monster.draw(ctx, &mut self.assets, &self.camera)?;
+    }
 
     // Textual debug info
     if self.debug {
-      let mouse_p = mouse::position(ctx);
-      let w_mouse_p = self.world.level.sspace_to_lspace(ctx, mouse_p.into());
       let dbg_txt = self.assets.txt(&format!("Mouse pos scrn: {:?} world: {}", mouse_p, w_mouse_p));
       dbg_txt.draw(ctx, DrawParam::default())?;
+      if let (Some(ix), Some(preview)) = (self.replay_frame, &self.preview) {
+        let frames = self.world.level.recording().frames.len();
+        let replay_txt =
+          self.assets.txt(&format!("Replay frame {}/{} ({:?})", ix + 1, frames, preview.stage));
+        replay_txt.draw(ctx, DrawParam::default().dest(Point::new(0.0, 20.0)))?;
+      }
       self.world.collision_test(w_mouse_p);
     }
+    self.console.draw(ctx, &mut self.assets)?;
 
     graphics::present(ctx)?;
     timer::sleep(Duration::from_secs(0));
     Ok(())
   }
 
-  // Handle key events. These just map keyboard events and alter our input
-  // state appropriately.
+  // Handle key events. These just map keyboard events to actions through the keymap, apart from
+  // when the console is open and gets the keyboard to itself.
   fn key_down_event(
     &mut self,
     _ctx: &mut Context,
     keycode: KeyCode,
     keymod: KeyMods,
-    _repeat: bool,
+    repeat: bool,
   ) {
-    match keycode {
-      KeyCode::Space => {
-        self.stop_render();
-      }
-      KeyCode::Add => {
-        self.fastmode = !self.fastmode;
-      }
-      KeyCode::Up => {
-        self.world.player.trans(Vector2::new(0.0, -1.0));
-      }
-      KeyCode::Down => {
-        self.world.player.trans(Vector2::new(0.0, 1.0));
-      }
-      KeyCode::Left => {
-        self.world.player.trans(Vector2::new(-1.0, 0.0));
-      }
-      KeyCode::Right => {
-        self.world.player.trans(Vector2::new(1.0, 0.0));
-      }
-      KeyCode::Grave => {
-        self.debug = !self.debug;
-        info!("Debug mode now {}", self.debug);
-      }
-      KeyCode::R if keymod.contains(KeyMods::CTRL) => {
-        self.world = World::new();
-      }
-      KeyCode::Q if keymod.contains(KeyMods::CTRL) => {
-        std::process::exit(0);
+    if self.console.open {
+      self.console_key(keycode);
+      return;
+    }
+    if let Some(action) = self.keymap.key_action(keycode, keymod) {
+      // Holding a key down only keeps stepping through replays. Movement is continuous anyway.
+      let repeats = action == Action::ReplayBack || action == Action::ReplayForward;
+      if !repeat || repeats {
+        self.perform(action);
       }
-      _ => (), // Do nothing
     }
   }
+
+  fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+    for action in self.keymap.key_actions(keycode) {
+      self.move_input.release(action);
+    }
+  }
+
+  fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+    // Like the keyboard, the pad doesn't drive the game while the console is open
+    if self.console.open {
+      return;
+    }
+    if let Some(action) = self.keymap.button_action(btn) {
+      self.perform(action);
+    }
+  }
+
+  fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+    if let Some(action) = self.keymap.button_action(btn) {
+      self.move_input.release(action);
+    }
+  }
+
+  fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+    self.move_input.set_axis(axis, value);
+  }
+
+  fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+    if self.console.open {
+      self.console.type_char(character);
+    }
+  }
+
+  fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+    if button == MouseButton::Middle {
+      self.camera.start_drag(ctx, Point::new(x, y));
+    }
+  }
+
+  fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
+    if button == MouseButton::Middle {
+      self.camera.end_drag();
+    }
+  }
+
+  fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+    self.camera.drag_to(ctx, Point::new(x, y));
+  }
+
+  fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) {
+    // TODO: Configurable zoom speed
+    let factor = 1.1f32.powf(y);
+    self.camera.zoom_at(ctx, mouse::position(ctx).into(), factor);
+  }
 }